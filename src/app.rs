@@ -1,10 +1,15 @@
+#[allow(clippy::module_inception)]
 mod app;
+mod bulk;
 mod button;
 mod categoryfield;
 mod chart;
 mod color;
 mod footer;
+mod imports;
 mod popup;
+mod preview;
+mod rules;
 mod stringfield;
 mod table;
 
@@ -1,5 +1,3 @@
-use std::fmt::format;
-
 use color_eyre::Result;
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::buffer::Buffer;
@@ -11,10 +9,12 @@ use strum::{Display, FromRepr, IntoEnumIterator};
 use strum_macros::EnumIter;
 
 use crate::app::chart::ChartComponent;
-use crate::db;
+use crate::app::imports::{ImportsAction, ImportsComponent};
+use crate::app::rules::{RulesAction, RulesComponent};
 use crate::event::{AppEvent, EventHandler};
+use crate::exchange::ExchangeRates;
 use crate::labeling;
-use crate::models::{Category, NewTransaction, Transaction};
+use crate::models::{Currency, Transaction};
 use crate::{app::table::TableComponent, db::Database};
 
 #[derive(Default, Clone, Copy, Display, FromRepr, EnumIter)]
@@ -24,6 +24,8 @@ pub enum CurrentTab {
     Table,
     #[strum(to_string = "Chart")]
     Chart,
+    #[strum(to_string = "Rules")]
+    Rules,
 }
 
 impl CurrentTab {
@@ -55,46 +57,44 @@ pub struct App {
     current_tab: CurrentTab,
     pub table: TableComponent,
     pub chart: ChartComponent,
+    pub rules: RulesComponent,
+    imports: Option<ImportsComponent>,
 }
 
 impl App {
-    fn new(transactions: Vec<NewTransaction>) -> Self {
-        let database = db::Database::new().expect("Could not acess Database");
-        match database.insert_transactions(transactions) {
-            Ok(v) => v,
-            Err(_) => println!("SQL Insert Error"),
-        }
+    fn new(database: Database, currency: Currency) -> Self {
         let mut transactions = database.get_transactions().expect("Could not acess DB");
-        let category_map = labeling::FieldMap::<Category>::new();
-        for transaction in transactions.iter_mut() {
-            transaction.group = category_map.get(&transaction.title);
-        }
+        let rules = database.get_rules().expect("Could not acess DB");
+        labeling::label_transactions(&mut transactions, &labeling::RuleSet::new(rules.clone()));
 
-        let category_map = database.get_categories().expect("Could not acess DB");
-        let title_map = database.get_titlemaps().expect("Could not acess DB");
+        let exchange_rates =
+            ExchangeRates::new(&database.get_exchange_rates().expect("Could not acess DB"));
+        let category_list = database.get_category_list().expect("Could not acess DB");
 
         let mut app = Self {
             database,
             running: true,
             has_changed: true,
             events: EventHandler::new(),
-            table: TableComponent::new(&transactions.to_vec()),
+            table: TableComponent::new(&transactions),
             current_tab: CurrentTab::Table,
             items: transactions.to_vec(),
-            chart: ChartComponent::new(&transactions.to_vec()),
+            chart: ChartComponent::new(
+                &transactions.to_vec(),
+                exchange_rates,
+                currency,
+                category_list.clone(),
+            ),
+            rules: RulesComponent::new(rules.clone(), &transactions),
+            imports: None,
         };
 
-        app.table.set_titlemap(title_map);
-        app.table.set_categories(category_map);
+        app.table.set_rules(rules);
+        app.table.set_category_list(category_list);
         app.table.update_transactions();
         app
     }
 
-    pub fn save_to_db(&self) {
-        let _ = self.database.insert_categories(self.table.get_categories());
-        let _ = self.database.insert_titlemaps(self.table.get_titlemap());
-    }
-
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> Result<Vec<Transaction>> {
         while self.running {
             match self.events.next().await? {
@@ -105,35 +105,112 @@ impl App {
                         self.chart.update_chart(&self.table.items);
                     }
                 }
-                AppEvent::Crossterm(event) => match event {
-                    crossterm::event::Event::Key(key) => {
+                AppEvent::Crossterm(event) => {
+                    if let crossterm::event::Event::Key(key) = event {
                         self.handle_key_events(key)?;
                         self.has_changed = true;
                     }
-                    _ => (),
-                },
+                }
                 AppEvent::Quit => self.quit(),
             }
         }
 
-        self.save_to_db();
-
         Ok(self.table.items)
     }
 
+    fn reload_transactions(&mut self) -> Result<()> {
+        self.items = self.database.get_transactions()?;
+        self.table.set_items(self.items.clone());
+        self.table.update_transactions();
+        // Match counts and chart totals change with the transactions too.
+        self.rules
+            .set_items(self.database.get_rules()?, &self.table.items);
+        self.chart.update_chart(&self.table.items);
+        Ok(())
+    }
+
+    fn reload_rules(&mut self) -> Result<()> {
+        let rules = self.database.get_rules()?;
+        self.table.set_rules(rules.clone());
+        self.table.update_transactions();
+        self.rules.set_items(rules, &self.table.items);
+        Ok(())
+    }
+
+    fn handle_rules_key_events(&mut self, key_event: KeyEvent) -> Result<()> {
+        match self.rules.handle_key_events(key_event) {
+            Some(RulesAction::Save(rules)) => self.database.save_rules(&rules)?,
+            Some(RulesAction::Delete(rule_id)) => {
+                self.database.delete_rule(rule_id)?;
+            }
+            None => return Ok(()),
+        }
+        self.reload_rules()
+    }
+
+    fn open_imports(&mut self) -> Result<()> {
+        self.imports = Some(ImportsComponent::new(self.database.get_imports()?));
+        Ok(())
+    }
+
+    fn handle_imports_key_events(&mut self, key_event: KeyEvent) -> Result<()> {
+        let Some(imports) = self.imports.as_mut() else {
+            return Ok(());
+        };
+
+        match imports.handle_key_events(key_event) {
+            Some(ImportsAction::Close) => self.imports = None,
+            Some(ImportsAction::Undo(import_id)) => {
+                self.database.undo_import(import_id)?;
+                self.reload_transactions()?;
+                self.open_imports()?;
+            }
+            None => (),
+        }
+        Ok(())
+    }
+
     fn handle_key_events(&mut self, key_event: KeyEvent) -> color_eyre::Result<()> {
+        if self.imports.is_some() {
+            return self.handle_imports_key_events(key_event);
+        }
+
         if self.table.is_blocking() {
             self.table.handle_key_events(key_event);
+            if let Some((transaction_id, splits)) = self.table.take_split_edit() {
+                self.database.save_splits(transaction_id, &splits)?;
+            }
+            if let Some((transaction_id, tags)) = self.table.take_tag_edit() {
+                self.database.save_tags(transaction_id, &tags)?;
+            }
+            if let Some(transaction) = self.table.take_override_edit() {
+                self.database.save_overrides(&transaction)?;
+            }
+            let bulk_edit = self.table.take_bulk_edit();
+            if !bulk_edit.is_empty() {
+                self.database.save_edits(&bulk_edit)?;
+            }
+            let rule_edits = self.table.take_rule_edits();
+            if !rule_edits.is_empty() {
+                self.database.save_rules(&rule_edits)?;
+                self.reload_rules()?;
+            }
             return Ok(());
         }
 
+        if self.rules.is_blocking() {
+            return self.handle_rules_key_events(key_event);
+        }
+
         match key_event.code {
             KeyCode::Char('q') => self.events.send(AppEvent::Quit),
             KeyCode::Char(';') | KeyCode::Right => self.next_tab(),
             KeyCode::Char('j') | KeyCode::Left => self.previous_tab(),
+            KeyCode::Char('i') => self.open_imports()?,
             _ => match self.current_tab {
                 CurrentTab::Chart => self.chart.handle_key_events(key_event),
                 CurrentTab::Table => self.table.handle_key_events(key_event),
+                CurrentTab::Rules => self.handle_rules_key_events(key_event)?,
             },
         };
         Ok(())
@@ -169,14 +246,18 @@ impl App {
         match self.current_tab {
             CurrentTab::Table => self.table.render(frame, inner_area),
             CurrentTab::Chart => self.chart.render(frame, inner_area),
+            CurrentTab::Rules => self.rules.render(frame, inner_area),
+        }
+
+        if let Some(imports) = self.imports.as_mut() {
+            imports.render(frame);
         }
     }
 }
 
-pub async fn init_app(transactions: Vec<NewTransaction>) -> Result<()> {
-    color_eyre::install()?;
+pub async fn init_app(database: Database, currency: Currency) -> Result<()> {
     let terminal = ratatui::init();
-    let app = App::new(transactions);
+    let app = App::new(database, currency);
     let _transactions = app.run(terminal).await;
     ratatui::restore();
     Ok(())
@@ -0,0 +1,276 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Stylize};
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph};
+
+use crate::app::categoryfield::CategoryField;
+use crate::app::color::{PALETTES, TableColors};
+use crate::app::popup::{ItemStyle, PopupFocus, centered_area};
+use crate::app::stringfield::StringField;
+use crate::models::{Category, CategoryList, Transaction};
+use std::collections::HashSet;
+
+const FIELD_LEN: usize = 48;
+
+const ACTIONS: [&str; 5] = ["Set category", "Rename title", "Add tag", "Hide", "Show"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BulkAction {
+    SetCategory(Category),
+    Rename(String),
+    AddTag(String),
+    Hide(bool),
+}
+
+impl BulkAction {
+    // Bulk edits are kept on each marked transaction, like edits made to one of them in the form.
+    // Returns the transactions changed, to be saved.
+    pub fn apply(
+        &self,
+        transactions: &mut [Transaction],
+        marked: &HashSet<u32>,
+    ) -> Vec<Transaction> {
+        let mut edited = Vec::new();
+        for item in transactions
+            .iter_mut()
+            .filter(|item| marked.contains(&item.id))
+        {
+            match self {
+                BulkAction::SetCategory(category) => {
+                    item.category_override = Some(category.clone())
+                }
+                BulkAction::Rename(title) => item.title_override = Some(title.clone()),
+                BulkAction::AddTag(tag) => {
+                    if !item.manual_tags.contains(tag) {
+                        item.manual_tags.push(tag.clone());
+                    }
+                }
+                BulkAction::Hide(hidden) => item.hidden = *hidden,
+            }
+            edited.push(item.clone());
+        }
+        edited
+    }
+}
+
+pub struct BulkForm {
+    marked: usize,
+    action: usize,
+    // The action is chosen on the first row and its value on the second.
+    on_value: bool,
+    category: CategoryField,
+    title: StringField,
+    tag: StringField,
+    item_style: ItemStyle,
+}
+
+impl BulkForm {
+    pub fn new(marked: usize, categories: &CategoryList) -> Self {
+        let item_style = ItemStyle::new(&TableColors::new(&PALETTES[0]));
+        BulkForm {
+            marked,
+            action: 0,
+            on_value: false,
+            category: CategoryField::new(
+                "Category",
+                categories
+                    .default_category()
+                    .unwrap_or_else(|| Category::from("Other")),
+                categories,
+                PopupFocus::Category,
+                item_style.clone(),
+            ),
+            title: StringField::new("Title : ", "", FIELD_LEN, item_style.clone()),
+            tag: StringField::new("Tag : ", "", FIELD_LEN, item_style.clone()),
+            item_style,
+        }
+    }
+
+    fn has_value(&self) -> bool {
+        self.action < 3
+    }
+
+    fn get_action(&self) -> Result<BulkAction, &'static str> {
+        match self.action {
+            0 => Ok(BulkAction::SetCategory(self.category.value())),
+            1 => match self.title.get_value().trim() {
+                title if title.chars().count() < 3 => Err("Use 3 or more chars"),
+                title => Ok(BulkAction::Rename(title.to_string())),
+            },
+            2 => match self.tag.get_value().trim() {
+                "" => Err("Type the tag to add"),
+                tag if tag.contains(',') => Err("Add one tag at a time"),
+                tag => Ok(BulkAction::AddTag(tag.to_string())),
+            },
+            3 => Ok(BulkAction::Hide(true)),
+            _ => Ok(BulkAction::Hide(false)),
+        }
+    }
+
+    // Returns the action once it is confirmed, and None while it is still being filled in.
+    pub fn handle_key_event(&mut self, key_event: KeyEvent) -> Option<BulkAction> {
+        match key_event.code {
+            KeyCode::Enter => return self.get_action().ok(),
+            KeyCode::Tab | KeyCode::BackTab => self.on_value = !self.on_value && self.has_value(),
+            _ if self.on_value => match self.action {
+                0 => self.category.handle_key_event(key_event),
+                1 => self.title.handle_key_event(key_event),
+                _ => self.tag.handle_key_event(key_event),
+            },
+            KeyCode::Right | KeyCode::Char(';') => self.action = (self.action + 1) % ACTIONS.len(),
+            KeyCode::Left | KeyCode::Char('j') => {
+                self.action = (self.action + ACTIONS.len() - 1) % ACTIONS.len()
+            }
+            _ => (),
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let area = centered_area(76, 6, frame.area());
+        frame.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title(format!("Edit {} marked transactions", self.marked))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        let inner_area = block.inner(area);
+        frame.render_widget(block, area);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+            ])
+            .split(inner_area);
+
+        let action = format!("< {} >", ACTIONS[self.action]);
+        let action_style = match self.on_value {
+            true => self.item_style.non_selected,
+            false => self.item_style.selected,
+        };
+        let action_areas = Layout::horizontal([
+            Constraint::Length(10),
+            Constraint::Length(action.len() as u16),
+        ])
+        .split(layout[0]);
+        frame.render_widget(Paragraph::new("Action: ").bold(), action_areas[0]);
+        frame.render_widget(Paragraph::new(action).style(action_style), action_areas[1]);
+
+        // The category field shows itself as focused when its focus matches.
+        let mut focus = match self.on_value {
+            true => PopupFocus::Category,
+            false => PopupFocus::default(),
+        };
+        let field = match self.action {
+            0 => {
+                frame.render_stateful_widget(&self.category, layout[1], &mut focus);
+                None
+            }
+            1 => Some(&self.title),
+            2 => Some(&self.tag),
+            _ => None,
+        };
+        if let Some(field) = field {
+            frame.render_stateful_widget(field, layout[1], &mut focus);
+            if self.on_value {
+                frame.set_cursor_position(layout[1].offset(field.cursor_offset()));
+            }
+        }
+
+        let message = match self.get_action() {
+            Ok(_) => Paragraph::new("Enter: apply   Tab: switch field   Esc: cancel"),
+            Err(error) => Paragraph::new(error).fg(Color::Red),
+        };
+        frame.render_widget(message.centered(), layout[3]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Kind;
+
+    fn transactions() -> Vec<Transaction> {
+        ["PADARIA", "MERCADO", "FARMACIA"]
+            .into_iter()
+            .enumerate()
+            .map(|(index, title)| {
+                let mut row = Transaction::sample(
+                    index as u32 + 1,
+                    "2024-01-10",
+                    title,
+                    1000,
+                    Kind::CreditPurchase,
+                );
+                row.manual_tags = vec!["home".to_string()];
+                row
+            })
+            .collect()
+    }
+
+    fn ids(transactions: &[Transaction]) -> Vec<u32> {
+        transactions.iter().map(|row| row.id).collect()
+    }
+
+    #[test]
+    fn only_marked_rows_change() {
+        let marked = HashSet::from([1, 3]);
+        let actions = [
+            BulkAction::SetCategory(Category::from("Food")),
+            BulkAction::Rename("Mercadinho".to_string()),
+            BulkAction::AddTag("trip".to_string()),
+            BulkAction::Hide(true),
+        ];
+        for action in actions {
+            let mut rows = transactions();
+            let edited = action.apply(&mut rows, &marked);
+            assert_eq!(ids(&edited), vec![1, 3], "{action:?}");
+
+            let unmarked = &rows[1];
+            assert_eq!(unmarked.category_override, None);
+            assert_eq!(unmarked.title_override, None);
+            assert_eq!(unmarked.manual_tags, vec!["home".to_string()]);
+            assert!(!unmarked.hidden);
+            for row in [&rows[0], &rows[2]] {
+                match &action {
+                    BulkAction::SetCategory(category) => {
+                        assert_eq!(row.category_override.as_ref(), Some(category))
+                    }
+                    BulkAction::Rename(title) => {
+                        assert_eq!(row.title_override.as_ref(), Some(title))
+                    }
+                    BulkAction::AddTag(_) => {
+                        assert_eq!(
+                            row.manual_tags,
+                            vec!["home".to_string(), "trip".to_string()]
+                        )
+                    }
+                    BulkAction::Hide(hidden) => assert_eq!(row.hidden, *hidden),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tag_already_there_is_not_added_again() {
+        let mut rows = transactions();
+        let edited = BulkAction::AddTag("home".to_string()).apply(&mut rows, &HashSet::from([2]));
+        assert_eq!(edited[0].manual_tags, vec!["home".to_string()]);
+    }
+
+    #[test]
+    fn nothing_marked_changes_nothing() {
+        let mut rows = transactions();
+        assert!(
+            BulkAction::Hide(true)
+                .apply(&mut rows, &HashSet::new())
+                .is_empty()
+        );
+        assert!(rows.iter().all(|row| !row.hidden));
+    }
+}
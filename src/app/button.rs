@@ -9,19 +9,21 @@ use crate::app::popup::{ItemStyle, PopupFocus};
 
 pub struct Button {
     label: &'static str,
+    focus: PopupFocus,
     style: ItemStyle,
 }
 
 impl Button {
-    pub fn new(label: &'static str, style: ItemStyle) -> Self {
-        Self { label, style }
+    pub fn new(label: &'static str, focus: PopupFocus, style: ItemStyle) -> Self {
+        Self {
+            label,
+            focus,
+            style,
+        }
     }
 
     pub fn handle_key_event(&mut self, key_event: KeyEvent) -> bool {
-        if key_event.code == KeyCode::Enter {
-            return true;
-        };
-        return false;
+        key_event.code == KeyCode::Enter
     }
 }
 
@@ -30,9 +32,9 @@ impl StatefulWidget for &Button {
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut PopupFocus) {
         let label = Line::from(self.label).bold();
-        let style = match state {
-            PopupFocus::Ok => self.style.selected,
-            _ => self.style.non_selected,
+        let style = match *state == self.focus {
+            true => self.style.selected,
+            false => self.style.non_selected,
         };
         let button = Paragraph::new(label)
             .alignment(ratatui::layout::Alignment::Center)
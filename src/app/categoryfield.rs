@@ -6,19 +6,39 @@ use ratatui::text::Line;
 use ratatui::widgets::{Paragraph, StatefulWidget, Widget};
 
 use crate::app::popup::{ItemStyle, PopupFocus};
-use crate::models::Category;
+use crate::models::{Category, CategoryList};
 
 pub struct CategoryField {
     label: &'static str,
     selected: Category,
+    // Whether the category shown was picked or accepted, rather than only offered.
+    chosen: bool,
+    options: Vec<(Category, String)>,
+    focus: PopupFocus,
     style: ItemStyle,
 }
 
 impl CategoryField {
-    pub fn new(label: &'static str, value: Category, style: ItemStyle) -> Self {
+    pub fn new(
+        label: &'static str,
+        value: Category,
+        categories: &CategoryList,
+        focus: PopupFocus,
+        style: ItemStyle,
+    ) -> Self {
         Self {
             label,
             selected: value,
+            chosen: false,
+            options: categories
+                .active()
+                .into_iter()
+                .map(|category| {
+                    let label = categories.label(&category);
+                    (category, label)
+                })
+                .collect(),
+            focus,
             style,
         }
     }
@@ -27,21 +47,54 @@ impl CategoryField {
         match key_event.code {
             KeyCode::Right | KeyCode::Char(';') => self.next(),
             KeyCode::Left | KeyCode::Char('j') => self.previous(),
-            _ => (),
+            KeyCode::Enter => (),
+            _ => return,
         }
+        self.chosen = true;
     }
 
+    pub fn is_chosen(&self) -> bool {
+        self.chosen
+    }
+
+    fn position(&self) -> Option<usize> {
+        self.options
+            .iter()
+            .position(|(category, _)| *category == self.selected)
+    }
+
+    // A category that is no longer offered, like an archived one, moves to either end of the list.
     fn next(&mut self) {
-        self.selected = self.selected.next();
+        let next = match self.position() {
+            Some(index) => self.options.get(index + 1).or(self.options.first()),
+            None => self.options.first(),
+        };
+        if let Some((category, _)) = next {
+            self.selected = category.clone();
+        }
     }
 
     fn previous(&mut self) {
-        self.selected = self.selected.previous();
+        let previous = match self.position() {
+            Some(index) if index > 0 => self.options.get(index - 1),
+            _ => self.options.last(),
+        };
+        if let Some((category, _)) = previous {
+            self.selected = category.clone();
+        }
     }
 
     pub fn value(&self) -> Category {
         self.selected.clone()
     }
+
+    // Subcategories are shown with their parents, as in Food > Restaurants.
+    fn value_label(&self) -> String {
+        match self.position() {
+            Some(index) => self.options[index].1.clone(),
+            None => self.selected.to_string(),
+        }
+    }
 }
 
 impl StatefulWidget for &CategoryField {
@@ -49,17 +102,17 @@ impl StatefulWidget for &CategoryField {
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut PopupFocus) {
         let layout = Layout::horizontal([
             Constraint::Length(self.label.len() as u16 + 2),
-            Constraint::Max(self.value().to_string().len() as u16 + 4),
+            Constraint::Max(self.value_label().chars().count() as u16 + 4),
         ])
         .split(area);
 
         let label = Line::from_iter([self.label, ": "]).bold();
         label.render(layout[0], buf);
-        let value = format!("< {} >", self.value());
+        let value = format!("< {} >", self.value_label());
 
-        let style = match state {
-            PopupFocus::Category => self.style.selected,
-            _ => self.style.non_selected,
+        let style = match *state == self.focus {
+            true => self.style.selected,
+            false => self.style.non_selected,
         };
         Paragraph::new(value)
             .clone()
@@ -1,54 +1,51 @@
-use chrono::Datelike;
+use chrono::Local;
 use crossterm::event::{KeyCode, KeyEvent};
 use itertools::Itertools;
 use ratatui::{
     Frame,
-    buffer::Buffer,
-    layout::{Constraint, Direction, Flex, Layout, Margin, Rect},
-    style::{Style, Styled, Stylize},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
+    style::{Color, Style, Stylize},
     text::Line,
     widgets::{
-        Axis, Bar, BarChart, BarGroup, Block, BorderType, Borders, Cell, Chart, Dataset, List,
-        ListItem, ListState, Row, StatefulWidget, Table, TableState, Widget,
+        Axis, Bar, BarChart, BarGroup, Block, BorderType, Borders, Cell, Chart, Dataset, Paragraph,
+        Row, StatefulWidget, Table, TableState, Widget,
     },
 };
-use strum::{Display, IntoEnumIterator};
-
-use crate::models::{self, Category, Kind, Transaction};
-use std::{
-    borrow::Cow,
-    cmp::Ordering,
-    collections::{HashMap, HashSet},
-    iter::zip,
+use std::str::FromStr;
+
+use crate::exchange::ExchangeRates;
+use crate::models::{self, Category, CategoryList, Currency, Money};
+use crate::summary::{
+    InstallmentProjection, MonthSummary, collapse_to, get_installment_projection,
+    get_transactions_by_month,
 };
+use std::{borrow::Cow, collections::HashMap, iter::zip};
 
-const NULL_KEY: &str = "[N/A]";
-const NEEDS: [Category; 5] = [
-    Category::Food,
-    Category::Housing,
-    Category::Transportation,
-    Category::Supermarket,
-    Category::Health,
-];
-const SAVINGS: [Category; 1] = [Category::Savings];
-const WANTS: [Category; 3] = [Category::Trips, Category::Personal, Category::Other];
-
-fn vertical_barchart(
-    category_totals: &HashMap<String, f64>,
+fn vertical_barchart<'a>(
+    category_totals: &'a HashMap<String, f64>,
+    colors: &HashMap<String, Color>,
+    order: &[String],
     bar_width: u16,
     bar_gap: u16,
     max_size: u64,
     percentage: bool,
-) -> BarChart {
+) -> BarChart<'a> {
     let bars: Vec<Bar> = category_totals
         .iter()
-        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .sorted_by_key(|(category, _)| {
+            let position = order.iter().position(|name| name == *category);
+            (position.unwrap_or(order.len()), category.as_str())
+        })
         .map(|(category, value)| {
             let text = match percentage {
                 true => format!("{:.2}%", value),
                 false => format!("{:.2}", value),
             };
-            vertical_bar(category.clone(), value, text)
+            let bar = vertical_bar(category.clone(), value, text);
+            match colors.get(category) {
+                Some(color) => bar.style(Style::default().fg(*color)),
+                None => bar,
+            }
         })
         .collect();
 
@@ -59,93 +56,17 @@ fn vertical_barchart(
         .max(max_size)
 }
 
-fn vertical_bar(category: String, value: &f64, text_value: String) -> Bar {
+fn vertical_bar(category: String, value: &f64, text_value: String) -> Bar<'_> {
     Bar::default()
         .value(*value as u64)
         .label(Line::from(category))
         .text_value(text_value)
 }
 
-fn get_transactions_by_category(transactions: &Vec<models::Transaction>) -> HashMap<String, f64> {
-    let mut category_totals: HashMap<String, f64> = Category::iter()
-        .map(|category| (category.to_string(), 0.0))
-        .collect();
-
-    category_totals.insert(NULL_KEY.to_string(), 0.0);
-
-    for transaction in transactions {
-        let key = match &transaction.group {
-            Some(category) => category.to_string(),
-            None => NULL_KEY.to_string(),
-        };
-        *category_totals.entry(key).or_default() += &transaction.amount;
-    }
-    category_totals
-}
-
-fn get_transactions_by_month(transactions: &Vec<models::Transaction>) -> Vec<MonthSummary> {
-    let month_set: HashSet<(i32, u32)> = HashSet::from(
-        transactions
-            .iter()
-            .map(|row| (row.date.year(), row.date.month()))
-            .collect::<HashSet<(i32, u32)>>(),
-    );
-
-    let month_expenses = month_set
-        .iter()
-        .map(|(year, month)| MonthSummary::new(transactions, *year, *month))
-        .sorted_by(|a, b| (a.month as i32 + a.year).cmp(&(b.month as i32 + b.year)))
-        .rev()
-        .collect();
-
-    month_expenses
-}
-
-#[derive(PartialEq, Debug)]
-struct FiftyThirtyTwenty {
-    needs: f64,
-    wants: f64,
-    savings: f64,
-}
-
-impl FiftyThirtyTwenty {
-    fn reduce_by(transactions: &Vec<Transaction>, list: &[Category]) -> f64 {
-        transactions
-            .iter()
-            .filter(|row| match &row.group {
-                Some(category) => list.contains(&category),
-                None => false,
-            })
-            .map(|row| row.amount)
-            .sum()
-    }
-
-    fn new(transactions: &Vec<Transaction>) -> Self {
-        let needs = FiftyThirtyTwenty::reduce_by(transactions, &NEEDS);
-        let wants = FiftyThirtyTwenty::reduce_by(transactions, &WANTS);
-        let savings = FiftyThirtyTwenty::reduce_by(transactions, &SAVINGS);
-        Self {
-            needs,
-            wants,
-            savings,
-        }
-    }
-}
-
-#[derive(PartialEq, Debug)]
-struct MonthSummary {
-    year: i32,
-    month: u32,
-    total_income: f64,
-    total_expenses: f64,
-    fifty_thirty_twenty: FiftyThirtyTwenty,
-    categorized_expenses: HashMap<String, f64>,
-}
-
 impl MonthSummary {
-    fn ref_array(&self) -> [Cow<str>; 3] {
-        let expenses_string = format!("{:.2}", self.total_expenses);
-        let income_string = format!("{:.2}", self.total_income);
+    fn ref_array(&self) -> [Cow<'_, str>; 3] {
+        let expenses_string = self.total_expenses.to_string();
+        let income_string = self.total_income.to_string();
         let date = format!("{:2}/{}", self.month, self.year);
         [
             Cow::Owned(date),
@@ -155,68 +76,61 @@ impl MonthSummary {
     }
 }
 
-impl MonthSummary {
-    fn new(transactions: &Vec<Transaction>, year: i32, month: u32) -> Self {
-        let month_transactions: Vec<Transaction> = transactions
-            .clone()
-            .into_iter()
-            .filter(|row| row.date.month() == month && row.date.year() == year)
-            .collect();
-        let expenses: Vec<Transaction> = month_transactions
-            .clone()
-            .into_iter()
-            .filter(|row| (row.kind == Kind::DebitPurchase || row.kind == Kind::CreditPurchase))
-            .collect();
-        let total_income = month_transactions
-            .iter()
-            .filter(|row| (row.kind == Kind::Income))
-            .map(|row| row.amount)
-            .sum();
-
-        let total_expenses = expenses.iter().map(|row| row.amount).sum();
-        let fifty_thirty_twenty = FiftyThirtyTwenty::new(&expenses);
-        let categorized_expenses = get_transactions_by_category(&expenses);
-        Self {
-            year,
-            month,
-            total_income,
-            total_expenses,
-            fifty_thirty_twenty,
-            categorized_expenses,
-        }
+impl InstallmentProjection {
+    fn ref_array(&self) -> [Cow<'_, str>; 3] {
+        [
+            Cow::Owned(format!("{:2}/{}", self.month, self.year)),
+            Cow::Owned(self.total.to_string()),
+            Cow::Owned(self.purchases.to_string()),
+        ]
     }
 }
 
-fn center(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect {
-    let [area] = Layout::horizontal([horizontal])
-        .flex(Flex::Center)
-        .areas(area);
-    let [area] = Layout::vertical([vertical]).flex(Flex::Center).areas(area);
-    area
-}
-
 pub struct ChartComponent {
     items: Vec<MonthSummary>,
+    projection: Vec<InstallmentProjection>,
     state: TableState,
     max_height: f64,
+    exchange_rates: ExchangeRates,
+    currency: Currency,
+    missing_rates: usize,
+    left_out: usize,
+    categories: CategoryList,
+    category_depth: usize,
+    by_tag: bool,
 }
 
 impl ChartComponent {
-    pub fn new(transactions: &Vec<models::Transaction>) -> Self {
+    pub fn new(
+        transactions: &[models::Transaction],
+        exchange_rates: ExchangeRates,
+        currency: Currency,
+        categories: CategoryList,
+    ) -> Self {
         let mut state = TableState::default();
         state.select(Some(0));
 
         let mut chart_component = Self {
             items: Vec::new(),
+            projection: Vec::new(),
             state,
             max_height: 0.0,
+            exchange_rates,
+            currency,
+            missing_rates: 0,
+            left_out: 0,
+            categories,
+            category_depth: 1,
+            by_tag: false,
         };
         chart_component.update_chart(transactions);
-        chart_component.max_height = chart_component.get_max_bar_height().unwrap();
         chart_component
     }
 
     pub fn next_row(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -231,6 +145,9 @@ impl ChartComponent {
     }
 
     pub fn previous_row(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -252,37 +169,76 @@ impl ChartComponent {
         match key_event.code {
             KeyCode::Char('k') => self.next_row(),
             KeyCode::Char('l') => self.previous_row(),
+            KeyCode::Char('+') => {
+                self.category_depth = (self.category_depth + 1).min(self.categories.max_depth())
+            }
+            KeyCode::Char('-') => {
+                self.category_depth = self.category_depth.saturating_sub(1).max(1)
+            }
+            KeyCode::Char('t') => self.by_tag = !self.by_tag,
             _ => (),
         }
     }
 
-    pub fn update_chart(&mut self, transactions: &Vec<models::Transaction>) {
-        self.items = get_transactions_by_month(
-            &transactions
-                .clone()
-                .into_iter()
-                .collect::<Vec<models::Transaction>>(),
-        );
-        self.max_height = self.get_max_bar_height().unwrap();
+    pub fn update_chart(&mut self, transactions: &[models::Transaction]) {
+        let (transactions, missing_rates) = self
+            .exchange_rates
+            .convert_transactions(transactions, self.currency);
+        self.missing_rates = missing_rates;
+        self.items = get_transactions_by_month(&transactions, &self.categories);
+        let (projection, left_out) =
+            get_installment_projection(&transactions, Local::now().date_naive());
+        self.projection = projection;
+        self.left_out = left_out.len()
+            + self
+                .items
+                .iter()
+                .map(|row| row.left_out.len())
+                .sum::<usize>();
+        self.max_height = self.get_max_bar_height().unwrap_or(0.0);
+        if self.state.selected().is_some_and(|i| i >= self.items.len()) {
+            self.state.select(Some(0));
+        }
     }
 
     fn get_max_bar_height(&self) -> Option<f64> {
         self.items
             .iter()
-            .map(|row| row.total_expenses)
+            .map(|row| row.total_expenses.as_f64())
             .reduce(f64::max)
     }
 
-    fn get_terminal_width() -> termsize::Size {
-        termsize::get().unwrap()
+    fn list_title(&self) -> String {
+        let mut notes = vec![self.currency.to_string()];
+        if self.missing_rates > 0 {
+            notes.push(format!("{} no rate", self.missing_rates));
+        }
+        if self.left_out > 0 {
+            notes.push(format!("{} left out", self.left_out));
+        }
+        format!("Expenses list ({})", notes.join(", "))
     }
 
     pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        if self.items.is_empty() {
+            let block = Block::default()
+                .title(self.list_title())
+                .border_type(BorderType::Rounded)
+                .borders(Borders::ALL);
+            frame.render_widget(Paragraph::new("No transactions").block(block), area);
+            return;
+        }
+
         let layout = Layout::new(
             Direction::Horizontal,
             [Constraint::Min(32), Constraint::Fill(4)],
         )
         .split(area);
+        let left_layout = Layout::new(
+            Direction::Vertical,
+            [Constraint::Fill(2), Constraint::Fill(1)],
+        )
+        .split(layout[0]);
         let right_layout = Layout::new(
             Direction::Vertical,
             [Constraint::Fill(1), Constraint::Fill(1)],
@@ -298,28 +254,70 @@ impl ChartComponent {
         self.render_rule_chart(frame, sub_right_layout[1]);
         self.render_scatter(frame, sub_right_layout[0]);
         self.render_category_chart(frame, right_layout[0]);
-        self.render_list(frame, layout[0]);
+        self.render_list(frame, left_layout[0]);
+        self.render_projection(frame, left_layout[1]);
     }
 
     fn render_category_chart(&self, frame: &mut Frame, area: Rect) {
         let current_month = &self.items[self.get_current_item().unwrap()];
 
+        let title = match self.by_tag {
+            true => "Month by tag (t for categories)".to_string(),
+            false => format!(
+                "Month by category, level {} of {} (+/-, t for tags)",
+                self.category_depth,
+                self.categories.max_depth()
+            ),
+        };
         let block = Block::default()
-            .title("Month by category")
+            .title(title)
             .border_type(BorderType::Rounded)
             .borders(Borders::ALL);
 
         let inner_area = block.inner(area).inner(Margin::new(5, 0));
         frame.render_widget(block, area);
 
-        let number_bars = (Category::iter().len() + 1) as f64;
+        // Tags have no order or color of their own, so their bars are sorted by name.
+        let totals = match self.by_tag {
+            true => current_month.tagged_expenses.clone(),
+            false => collapse_to(
+                &current_month.categorized_expenses,
+                &self.categories,
+                self.category_depth,
+            ),
+        };
+        let category_totals: HashMap<String, f64> = totals
+            .into_iter()
+            .map(|(category, total)| (category, total.as_f64()))
+            .collect();
+        let order: Vec<String> = match self.by_tag {
+            true => Vec::new(),
+            false => self
+                .categories
+                .items
+                .iter()
+                .map(|info| info.category.to_string())
+                .collect(),
+        };
+        let colors: HashMap<String, Color> = category_totals
+            .keys()
+            .filter(|_| !self.by_tag)
+            .filter_map(|name| {
+                let color = self.categories.color(&Category::from(name.as_str()))?;
+                Some((name.clone(), Color::from_str(color).ok()?))
+            })
+            .collect();
+
+        let number_bars = category_totals.len() as f64;
         let bar_gap = 2.0;
         let calculated_width = (inner_area.width as f64 - (number_bars - 1.0) * bar_gap)
             / (number_bars).floor().max(1.0);
         let bar_width = calculated_width;
 
         let barchart = vertical_barchart(
-            &current_month.categorized_expenses,
+            &category_totals,
+            &colors,
+            &order,
             bar_width as u16,
             bar_gap as u16,
             self.max_height as u64,
@@ -342,14 +340,21 @@ impl ChartComponent {
                 "Needs".to_string(),
                 "Wants".to_string(),
                 "Savings".to_string(),
-            ]
-            .into_iter(),
+            ],
             [
-                current_month.fifty_thirty_twenty.needs / current_month.total_income * 100.0,
-                current_month.fifty_thirty_twenty.wants / current_month.total_income * 100.0,
-                current_month.fifty_thirty_twenty.savings / current_month.total_income * 100.0,
-            ]
-            .into_iter(),
+                percentage(
+                    current_month.fifty_thirty_twenty.needs,
+                    current_month.total_income,
+                ),
+                percentage(
+                    current_month.fifty_thirty_twenty.wants,
+                    current_month.total_income,
+                ),
+                percentage(
+                    current_month.fifty_thirty_twenty.savings,
+                    current_month.total_income,
+                ),
+            ],
         )
         .collect::<HashMap<String, f64>>();
 
@@ -362,15 +367,22 @@ impl ChartComponent {
             / (number_bars).floor().max(1.0);
         let bar_width = calculated_width;
 
-        let barchart =
-            vertical_barchart(&rule_expenses, bar_width as u16, bar_gap as u16, 100, true);
+        let barchart = vertical_barchart(
+            &rule_expenses,
+            &HashMap::new(),
+            &[],
+            bar_width as u16,
+            bar_gap as u16,
+            100,
+            true,
+        );
 
         frame.render_widget(barchart, inner_area);
     }
 
     fn render_list(&mut self, frame: &mut Frame, area: Rect) {
         let block = Block::default()
-            .title("Expenses list")
+            .title(self.list_title())
             .border_type(BorderType::Rounded)
             .borders(Borders::ALL);
 
@@ -398,6 +410,40 @@ impl ChartComponent {
         StatefulWidget::render(list, area, frame.buffer_mut(), &mut self.state);
     }
 
+    fn render_projection(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default()
+            .title("Upcoming installments")
+            .border_type(BorderType::Rounded)
+            .borders(Borders::ALL);
+
+        if self.projection.is_empty() {
+            frame.render_widget(Paragraph::new("No installments left").block(block), area);
+            return;
+        }
+
+        let rows = self.projection.iter().map(|item| {
+            item.ref_array()
+                .into_iter()
+                .map(Cell::from)
+                .collect::<Row>()
+        });
+
+        let header = ["Month", "Amount", "Purchases"]
+            .into_iter()
+            .map(Cell::from)
+            .collect::<Row>()
+            .bold();
+
+        let table = Table::new(
+            rows,
+            [Constraint::Fill(1), Constraint::Min(8), Constraint::Min(9)],
+        )
+        .block(block)
+        .header(header);
+
+        frame.render_widget(table, area);
+    }
+
     fn render_scatter(&mut self, frame: &mut Frame, area: Rect) {
         let expense_data = self
             .items
@@ -406,7 +452,7 @@ impl ChartComponent {
             .map(|item| {
                 (
                     convert_date_float(item.month, item.year),
-                    item.total_expenses as f64,
+                    item.total_expenses.as_f64(),
                 )
             })
             .collect::<Vec<(f64, f64)>>();
@@ -446,8 +492,15 @@ impl ChartComponent {
     }
 }
 
+fn percentage(part: Money, total: Money) -> f64 {
+    match total.is_zero() {
+        true => 0.0,
+        false => part.cents as f64 / total.cents as f64 * 100.0,
+    }
+}
+
 fn convert_date_float(month: u32, year: i32) -> f64 {
-    (year as f64 + (month as f64) / 12.0) as f64
+    year as f64 + (month as f64) / 12.0
 }
 
 fn convert_float_string(float: f64) -> String {
@@ -455,9 +508,3 @@ fn convert_float_string(float: f64) -> String {
     let year = float.trunc() as i32;
     format!("{}, {}", month, year)
 }
-
-fn convert_float_date(float: f64) -> (u32, i32) {
-    let month = (float.fract() * 12.0).round() as u32;
-    let year = float.trunc() as i32;
-    (month, year)
-}
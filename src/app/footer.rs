@@ -1,7 +1,7 @@
 use std::vec::Vec;
 
 use ratatui::layout::Constraint;
-use ratatui::widgets::{Borders, Row, StatefulWidget, Table, TableState};
+use ratatui::widgets::{Borders, Paragraph, Row, StatefulWidget, Table, TableState};
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
@@ -30,8 +30,7 @@ impl FooterInfo<'_> {
             .map(|data| Row::new([data.0, data.1]))
             .collect();
 
-        let table = Table::new(rows, [Constraint::Max(15), Constraint::Fill(1)]);
-        table
+        Table::new(rows, [Constraint::Max(15), Constraint::Fill(1)])
     }
 }
 
@@ -51,6 +50,9 @@ fn get_help() -> FooterInfo<'static> {
     let command_map = Vec::from([
         ("Enter", "Edit Transaction"),
         ("o", "Order By"),
+        ("a", "Filter Account"),
+        ("c", "Filter Category"),
+        ("i", "Import batches"),
         ("↑", "Move Up"),
         ("↓", "Move Down"),
         ("q", "Quit"),
@@ -70,7 +72,7 @@ impl Footer {
 
         Footer {
             colors: TableColors::new(&PALETTES[0]),
-            search: StringField::new("/", &"".to_string(), 40, item_style),
+            search: StringField::new("/", "", 40, item_style),
         }
     }
 
@@ -94,6 +96,11 @@ impl StatefulWidget for &Footer {
                 self.search.render(area, buf, &mut PopupFocus::Title);
                 None
             }
+            TableMode::Searched => {
+                let hint = Paragraph::new("Space: mark   *: mark all   b: edit marked   Esc: back");
+                ratatui::widgets::Widget::render(hint.style(style), area, buf);
+                None
+            }
             _ => None,
         };
 
@@ -108,13 +115,12 @@ impl StatefulWidget for &Footer {
             .border_type(BorderType::Rounded)
             .border_style(style);
 
-        match table {
-            Some(table) => table.get_table().style(style).block(block).render(
-                area,
-                buf,
-                &mut TableState::new(),
-            ),
-            None => (),
-        };
+        if let Some(table) = table {
+            table
+                .get_table()
+                .style(style)
+                .block(block)
+                .render(area, buf, &mut TableState::new());
+        }
     }
 }
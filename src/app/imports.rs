@@ -0,0 +1,143 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Cell, Clear, Paragraph, Row, Table, TableState,
+};
+use std::borrow::Cow;
+
+use crate::app::popup::centered_rect;
+use crate::models::ImportBatch;
+
+impl ImportBatch {
+    fn ref_array(&self) -> [Cow<'_, str>; 5] {
+        [
+            Cow::Owned(self.id.to_string()),
+            Cow::Owned(self.imported_at.format("%Y-%m-%d %H:%M").to_string()),
+            Cow::Borrowed(&self.importer),
+            Cow::Owned(format!("{}/{}", self.inserted_rows, self.total_rows)),
+            Cow::Owned(self.file_name()),
+        ]
+    }
+}
+
+pub enum ImportsAction {
+    Close,
+    Undo(u32),
+}
+
+pub struct ImportsComponent {
+    state: TableState,
+    items: Vec<ImportBatch>,
+    confirming: bool,
+}
+
+impl ImportsComponent {
+    pub fn new(items: Vec<ImportBatch>) -> Self {
+        Self {
+            state: TableState::default().with_selected(0),
+            items,
+            confirming: false,
+        }
+    }
+
+    pub fn next_row(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) if i + 1 < self.items.len() => i + 1,
+            _ => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    pub fn previous_row(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(0) | None => self.items.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.state.select(Some(i));
+    }
+
+    fn selected(&self) -> Option<&ImportBatch> {
+        self.items.get(self.state.selected()?)
+    }
+
+    pub fn handle_key_events(&mut self, key_event: KeyEvent) -> Option<ImportsAction> {
+        if self.confirming {
+            self.confirming = false;
+            return match key_event.code {
+                KeyCode::Char('y') => self.selected().map(|batch| ImportsAction::Undo(batch.id)),
+                _ => None,
+            };
+        }
+
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('i') => return Some(ImportsAction::Close),
+            KeyCode::Char('k') | KeyCode::Down => self.next_row(),
+            KeyCode::Char('l') | KeyCode::Up => self.previous_row(),
+            KeyCode::Char('u') => self.confirming = self.selected().is_some(),
+            _ => (),
+        }
+        None
+    }
+
+    pub fn render(&mut self, frame: &mut Frame) {
+        let area = centered_rect(80, 60, frame.area());
+        frame.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title("Import batches")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        let inner_area = block.inner(area);
+        frame.render_widget(block, area);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Fill(1), Constraint::Length(1)])
+            .split(inner_area);
+
+        let header = ["Batch", "Imported at", "Importer", "Rows", "File"]
+            .into_iter()
+            .map(Cell::from)
+            .collect::<Row>()
+            .bold();
+        let rows = self.items.iter().map(|batch| {
+            batch
+                .ref_array()
+                .into_iter()
+                .map(Cell::from)
+                .collect::<Row>()
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(6),
+                Constraint::Length(17),
+                Constraint::Length(16),
+                Constraint::Length(10),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, layout[0], &mut self.state);
+
+        let message = match (self.confirming, self.selected()) {
+            (true, Some(batch)) => Paragraph::new(format!(
+                "Undo batch {} and remove its {} transactions? (y/n)",
+                batch.id, batch.inserted_rows
+            ))
+            .fg(Color::Red),
+            _ if self.items.is_empty() => Paragraph::new("No imports yet"),
+            _ => Paragraph::new("u: undo batch   Esc: close"),
+        };
+        frame.render_widget(message.centered(), layout[1]);
+    }
+}
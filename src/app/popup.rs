@@ -1,6 +1,7 @@
 use crossterm::event::{KeyCode, KeyEvent};
+use itertools::Itertools;
 use ratatui::Frame;
-use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::layout::{Constraint, Direction, Flex, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style, Stylize};
 use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Wrap};
 
@@ -8,21 +9,40 @@ use crate::app::button::Button;
 use crate::app::categoryfield::CategoryField;
 use crate::app::color::{PALETTES, TableColors};
 use crate::app::stringfield::StringField;
-use crate::models::{Category, Transaction};
+use crate::models::{Category, CategoryList, Money, Split, Transaction};
+
+const SPLIT_AMOUNT_LEN: usize = 10;
+const SPLIT_NOTE_LEN: usize = 16;
 
 #[derive(Clone, Default, PartialEq, Eq)]
 pub enum PopupFocus {
     #[default]
     Title,
     Category,
+    Notes,
+    Tags,
+    Scope,
+    SplitAmount(usize),
+    SplitCategory(usize),
+    SplitNote(usize),
+    AddSplit,
     Ok,
 }
 
 impl PopupFocus {
-    const fn next(&self) -> Self {
-        match self {
+    const fn next(&self, splits: usize) -> Self {
+        match *self {
             Self::Title => Self::Category,
-            Self::Category => Self::Ok,
+            Self::Category => Self::Notes,
+            Self::Notes => Self::Tags,
+            Self::Tags => Self::Scope,
+            Self::Scope if splits > 0 => Self::SplitAmount(0),
+            Self::Scope => Self::AddSplit,
+            Self::SplitAmount(index) => Self::SplitCategory(index),
+            Self::SplitCategory(index) => Self::SplitNote(index),
+            Self::SplitNote(index) if index + 1 < splits => Self::SplitAmount(index + 1),
+            Self::SplitNote(_) => Self::AddSplit,
+            Self::AddSplit => Self::Ok,
             Self::Ok => Self::Title,
         }
     }
@@ -45,17 +65,57 @@ impl ItemStyle {
     }
 }
 
+struct SplitRow {
+    amount: StringField,
+    category: CategoryField,
+    note: StringField,
+}
+
+impl SplitRow {
+    fn new(index: usize, split: &Split, categories: &CategoryList, item_style: &ItemStyle) -> Self {
+        let amount = match split.amount.is_zero() {
+            true => String::new(),
+            false => split.amount.to_string(),
+        };
+        SplitRow {
+            amount: StringField::new("Split : ", &amount, SPLIT_AMOUNT_LEN, item_style.clone()),
+            category: CategoryField::new(
+                "Category",
+                split.category.clone(),
+                categories,
+                PopupFocus::SplitCategory(index),
+                item_style.clone(),
+            ),
+            note: StringField::new("Note : ", &split.note, SPLIT_NOTE_LEN, item_style.clone()),
+        }
+    }
+}
+
 pub struct PopupForm {
     focus: PopupFocus,
     title: StringField,
     category: CategoryField,
+    notes: StringField,
+    tags: StringField,
+    as_rule: bool,
+    splits: Vec<SplitRow>,
+    add_button: Button,
     button: Button,
     transaction: Transaction,
+    suggestion: Option<(Category, f64)>,
+    categories: CategoryList,
     colors: TableColors,
+    item_style: ItemStyle,
 }
 
 impl PopupForm {
-    pub fn new(transaction: Transaction) -> Self {
+    // A transaction without a category starts on the suggested one, if any, but keeps having no
+    // category until one is picked or the suggestion is accepted with Enter.
+    pub fn new(
+        transaction: Transaction,
+        categories: &CategoryList,
+        suggestion: Option<(Category, f64)>,
+    ) -> Self {
         let title_label = "Title : ";
         let max_len = 60 - 2 - 2 - (title_label.len() + 2);
 
@@ -66,16 +126,81 @@ impl PopupForm {
             title: StringField::new(title_label, &transaction.title, max_len, item_style.clone()),
             category: CategoryField::new(
                 "Category",
-                match &transaction.group {
-                    Some(category) => category.clone(),
-                    None => Category::Other,
+                match (&transaction.group, &suggestion) {
+                    (Some(category), _) | (None, Some((category, _))) => category.clone(),
+                    (None, None) => categories
+                        .default_category()
+                        .unwrap_or_else(|| Category::from("Other")),
                 },
+                categories,
+                PopupFocus::Category,
                 item_style.clone(),
             ),
-            button: Button::new("Ok", item_style.clone()),
+            notes: StringField::new("Notes : ", &transaction.notes, max_len, item_style.clone()),
+            tags: StringField::new(
+                "Tags : ",
+                &transaction.manual_tags.join(", "),
+                max_len,
+                item_style.clone(),
+            ),
+            as_rule: false,
+            splits: transaction
+                .splits
+                .iter()
+                .enumerate()
+                .map(|(index, split)| SplitRow::new(index, split, categories, &item_style))
+                .collect(),
+            add_button: Button::new("Add split", PopupFocus::AddSplit, item_style.clone()),
+            button: Button::new("Ok", PopupFocus::Ok, item_style.clone()),
             focus: PopupFocus::default(),
-            transaction: transaction,
+            transaction,
+            suggestion,
+            categories: categories.clone(),
             colors,
+            item_style,
+        }
+    }
+
+    fn add_split(&mut self) {
+        let split = Split {
+            amount: Money::zero(self.transaction.amount.currency),
+            category: self.category.value(),
+            note: String::new(),
+        };
+        self.splits.push(SplitRow::new(
+            self.splits.len(),
+            &split,
+            &self.categories,
+            &self.item_style,
+        ));
+        self.focus = PopupFocus::SplitAmount(self.splits.len() - 1);
+    }
+
+    // Splits with a cleared amount are dropped, which is how a split is removed.
+    fn get_splits(&self) -> Result<Vec<Split>, &'static str> {
+        let mut splits = Vec::new();
+        for row in &self.splits {
+            let value = row.amount.get_value();
+            if value.trim().is_empty() {
+                continue;
+            }
+            match Money::parse(&value.replace(',', "."), self.transaction.amount.currency) {
+                Some(amount) if !amount.is_negative() && !amount.is_zero() => splits.push(Split {
+                    amount,
+                    category: row.category.value(),
+                    note: row.note.get_value().trim().to_string(),
+                }),
+                _ => return Err("Split amounts must be positive numbers"),
+            }
+        }
+
+        let total = splits.iter().try_fold(
+            Money::zero(self.transaction.amount.currency),
+            |total, split| total.checked_add(split.amount),
+        );
+        match total.is_none_or(|total| total > self.transaction.amount.abs()) {
+            true => Err("Splits add up to more than the amount"),
+            false => Ok(splits),
         }
     }
 
@@ -85,15 +210,36 @@ impl PopupForm {
                 self.next_field();
                 return None;
             }
-            KeyCode::Esc => return Some(self.get_original_transaction()),
+            KeyCode::Esc => {
+                self.as_rule = false;
+                return Some(self.get_original_transaction());
+            }
             _ => (),
         };
         match self.focus {
             PopupFocus::Title => self.title.handle_key_event(key_event),
             PopupFocus::Category => self.category.handle_key_event(key_event),
+            PopupFocus::Notes => self.notes.handle_key_event(key_event),
+            PopupFocus::Tags => self.tags.handle_key_event(key_event),
+            PopupFocus::Scope => match key_event.code {
+                KeyCode::Right | KeyCode::Char(';') | KeyCode::Left | KeyCode::Char('j') => {
+                    self.as_rule = !self.as_rule
+                }
+                _ => (),
+            },
+            PopupFocus::SplitAmount(index) => self.splits[index].amount.handle_key_event(key_event),
+            PopupFocus::SplitCategory(index) => {
+                self.splits[index].category.handle_key_event(key_event)
+            }
+            PopupFocus::SplitNote(index) => self.splits[index].note.handle_key_event(key_event),
+            PopupFocus::AddSplit => {
+                if self.add_button.handle_key_event(key_event) {
+                    self.add_split();
+                }
+            }
             PopupFocus::Ok => {
                 if self.button.handle_key_event(key_event) {
-                    if self.validate_title() {
+                    if self.validate_title() && self.get_splits().is_ok() {
                         return Some(self.get_transaction());
                     } else {
                         return None;
@@ -101,7 +247,7 @@ impl PopupForm {
                 }
             }
         }
-        return None;
+        None
     }
 
     fn validate_title(&self) -> bool {
@@ -111,17 +257,36 @@ impl PopupForm {
         true
     }
     fn next_field(&mut self) {
-        self.focus = self.focus.next();
+        self.focus = self.focus.next(self.splits.len());
     }
 
     pub fn get_original_transaction(&self) -> Transaction {
         self.transaction.clone()
     }
 
+    // Edits stay on the transaction unless they are saved as rules for every similar title.
+    pub fn saves_rule(&self) -> bool {
+        self.as_rule
+    }
+
     pub fn get_transaction(&self) -> Transaction {
         let mut transaction = self.transaction.clone();
         transaction.title = self.title.get_value();
-        transaction.group = Some(self.category.value());
+        if self.category.is_chosen() {
+            transaction.group = Some(self.category.value());
+        }
+        transaction.notes = self.notes.get_value().trim().to_string();
+        // Tags are separated by commas and always stay on this transaction, whatever the scope.
+        transaction.manual_tags = self
+            .tags
+            .get_value()
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .unique()
+            .map(str::to_string)
+            .collect();
+        transaction.splits = self.get_splits().unwrap_or_default();
         transaction
     }
 
@@ -132,7 +297,7 @@ impl PopupForm {
             .border_type(BorderType::Rounded)
             .style(Style::default());
 
-        let area = centered_rect(40, 10, frame.area());
+        let area = centered_area(76, 12 + self.splits.len() as u16, frame.area());
 
         frame.render_widget(Clear, area);
         frame.render_widget(popup_block, area);
@@ -143,27 +308,103 @@ impl PopupForm {
             .constraints([
                 Constraint::Length(1),
                 Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Length(self.splits.len() as u16),
                 Constraint::Length(3),
                 Constraint::Length(2),
             ])
             .split(area);
 
-        let button_area = Layout::horizontal([
+        let button_areas = Layout::horizontal([
             Constraint::Fill(1),
+            Constraint::Length(13),
+            Constraint::Length(2),
             Constraint::Length(10),
             Constraint::Fill(1),
         ])
-        .split(layout[2])[1];
+        .split(layout[6]);
 
         frame.render_stateful_widget(&self.title, layout[0], &mut self.focus.clone());
         frame.render_stateful_widget(&self.category, layout[1], &mut self.focus.clone());
-        frame.render_stateful_widget(&self.button, button_area, &mut self.focus.clone());
+        frame.render_stateful_widget(&self.notes, layout[2], &mut self.focus.clone());
+        frame.render_stateful_widget(&self.tags, layout[3], &mut self.focus.clone());
+        let scope = match self.as_rule {
+            true => "< every title like this one, as a rule >",
+            false => "< this transaction only >",
+        };
+        let scope_style = match self.focus == PopupFocus::Scope {
+            true => self.item_style.selected,
+            false => self.item_style.non_selected,
+        };
+        let scope_areas = Layout::horizontal([
+            Constraint::Length(10),
+            Constraint::Length(scope.len() as u16),
+        ])
+        .split(layout[4]);
+        frame.render_widget(Paragraph::new("Apply to: ").bold(), scope_areas[0]);
+        frame.render_widget(Paragraph::new(scope).style(scope_style), scope_areas[1]);
+        frame.render_stateful_widget(&self.add_button, button_areas[1], &mut self.focus.clone());
+        frame.render_stateful_widget(&self.button, button_areas[3], &mut self.focus.clone());
+
+        let split_rows =
+            Layout::vertical(vec![Constraint::Length(1); self.splits.len()]).split(layout[5]);
+        let split_areas: Vec<_> = split_rows
+            .iter()
+            .map(|row| {
+                Layout::horizontal([
+                    Constraint::Length(20),
+                    Constraint::Length(30),
+                    Constraint::Fill(1),
+                ])
+                .split(*row)
+            })
+            .collect();
+        for (split, areas) in self.splits.iter().zip(&split_areas) {
+            frame.render_stateful_widget(&split.amount, areas[0], &mut self.focus.clone());
+            frame.render_stateful_widget(&split.category, areas[1], &mut self.focus.clone());
+            frame.render_stateful_widget(&split.note, areas[2], &mut self.focus.clone());
+        }
 
-        let message = match !self.validate_title() {
-            true => Paragraph::new("Use 3 or more chars")
+        let message = match (self.validate_title(), self.get_splits()) {
+            (false, _) => Paragraph::new("Use 3 or more chars")
                 .fg(Color::Red)
                 .centered(),
-            false => Paragraph::new(
+            (true, Err(error)) => Paragraph::new(error).fg(Color::Red).centered(),
+            (true, Ok(splits)) if !splits.is_empty() => {
+                // The splits were checked to fit in the amount, so the remainder is always there.
+                let remainder = Transaction {
+                    splits,
+                    ..self.transaction.clone()
+                }
+                .remainder()
+                .unwrap_or(self.transaction.amount);
+                let stays = match self.get_transaction().group {
+                    Some(category) => format!("stays in {category}"),
+                    None => "stays uncategorized".to_string(),
+                };
+                Paragraph::new(format!(
+                    "{remainder} {stays}.\nClear a split amount to remove it."
+                ))
+                .fg(self.colors.row_fg)
+                .centered()
+            }
+            (true, Ok(_))
+                if !self.category.is_chosen()
+                    && self
+                        .suggestion
+                        .as_ref()
+                        .is_some_and(|(category, _)| *category == self.category.value()) =>
+            {
+                let confidence = self.suggestion.as_ref().map_or(0.0, |(_, p)| p * 100.0);
+                Paragraph::new(format!(
+                    "Category suggested from similar transactions,\n{confidence:.0}% sure, Enter accepts it."
+                ))
+                .fg(self.colors.row_fg)
+                .centered()
+            }
+            (true, Ok(_)) => Paragraph::new(
                 "Use Tab to navigate between fields\n
                  and arrows to choose a Category.",
             )
@@ -171,19 +412,37 @@ impl PopupForm {
             .centered()
             .wrap(Wrap { trim: true }),
         };
-        frame.render_widget(message, layout[3]);
+        frame.render_widget(message, layout[7]);
 
-        match self.focus {
-            PopupFocus::Title => {
-                let cursor_position = layout[0].offset(self.title.cursor_offset());
-                frame.set_cursor_position(cursor_position);
+        let cursor_position = match self.focus {
+            PopupFocus::Title => Some(layout[0].offset(self.title.cursor_offset())),
+            PopupFocus::Notes => Some(layout[2].offset(self.notes.cursor_offset())),
+            PopupFocus::Tags => Some(layout[3].offset(self.tags.cursor_offset())),
+            PopupFocus::SplitAmount(index) => {
+                Some(split_areas[index][0].offset(self.splits[index].amount.cursor_offset()))
             }
-            _ => (),
+            PopupFocus::SplitNote(index) => {
+                Some(split_areas[index][2].offset(self.splits[index].note.cursor_offset()))
+            }
+            _ => None,
         };
+        if let Some(cursor_position) = cursor_position {
+            frame.set_cursor_position(cursor_position);
+        }
     }
 }
 
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+pub fn centered_area(width: u16, height: u16, area: Rect) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width.min(area.width))])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height.min(area.height))])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -202,3 +461,67 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CategoryInfo, Kind};
+
+    fn popup(group: Option<&str>) -> PopupForm {
+        let categories = CategoryList::new(
+            ["Food", "Other", "Transport"]
+                .into_iter()
+                .map(|name| CategoryInfo::new(Category::from(name)))
+                .collect(),
+        );
+        let transaction = Transaction {
+            group: group.map(Category::from),
+            ..Transaction::sample(1, "2024-01-10", "PADARIA", 1000, Kind::CreditPurchase)
+        };
+        PopupForm::new(
+            transaction,
+            &categories,
+            Some((Category::from("Food"), 0.9)),
+        )
+    }
+
+    fn press(form: &mut PopupForm, keys: &[KeyCode]) {
+        for key in keys {
+            form.handle_key_event(KeyEvent::from(*key));
+        }
+    }
+
+    #[test]
+    fn suggestion_is_not_saved_with_other_edits() {
+        let mut form = popup(None);
+        press(&mut form, &[KeyCode::Tab, KeyCode::Tab, KeyCode::Char('x')]);
+        let transaction = form.get_transaction();
+        assert_eq!(transaction.notes, "x");
+        assert_eq!(transaction.group, None);
+    }
+
+    #[test]
+    fn suggestion_is_saved_once_accepted() {
+        let mut form = popup(None);
+        press(&mut form, &[KeyCode::Tab, KeyCode::Enter]);
+        assert_eq!(form.get_transaction().group, Some(Category::from("Food")));
+
+        let mut form = popup(None);
+        press(&mut form, &[KeyCode::Tab, KeyCode::Right]);
+        assert_eq!(form.get_transaction().group, Some(Category::from("Other")));
+    }
+
+    #[test]
+    fn category_is_kept_unless_changed() {
+        let mut form = popup(Some("Transport"));
+        press(&mut form, &[KeyCode::Tab, KeyCode::Tab, KeyCode::Char('x')]);
+        assert_eq!(
+            form.get_transaction().group,
+            Some(Category::from("Transport"))
+        );
+
+        let mut form = popup(Some("Transport"));
+        press(&mut form, &[KeyCode::Tab, KeyCode::Left]);
+        assert_eq!(form.get_transaction().group, Some(Category::from("Other")));
+    }
+}
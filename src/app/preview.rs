@@ -0,0 +1,278 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::widgets::{Block, BorderType, Borders, Cell, Clear, Paragraph, Row, Table};
+
+use crate::app::color::{PALETTES, TableColors};
+use crate::app::popup::{ItemStyle, PopupFocus, centered_rect};
+use crate::app::stringfield::StringField;
+use crate::models::Transaction;
+
+const FIELD_LEN: usize = 40;
+
+// The text each rule made from a form edit looks for in titles. A part the form left alone
+// makes no rule.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditKeywords {
+    pub title: Option<String>,
+    pub category: Option<String>,
+}
+
+impl EditKeywords {
+    // A new title is keyed on the statement title, and a new category on the title shown.
+    pub fn new(unedited: &Transaction, transaction: &Transaction) -> Self {
+        EditKeywords {
+            title: (transaction.title != unedited.title).then(|| unedited.statement_title.clone()),
+            category: (transaction.group != unedited.group && transaction.group.is_some())
+                .then(|| transaction.title.clone()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.category.is_none()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PreviewFocus {
+    Title,
+    Category,
+    Scope,
+}
+
+pub enum PreviewAction {
+    Narrowed,
+    SaveRules,
+    SaveOnce,
+    Cancel,
+}
+
+// Lists what a rule made from the edit form would relabel before it is saved, so its keywords
+// can be narrowed down or the edit kept on the one transaction instead.
+pub struct RulePreview {
+    pub transaction: Transaction,
+    pub unedited: Transaction,
+    title: Option<StringField>,
+    category: Option<StringField>,
+    focus: PreviewFocus,
+    as_rule: bool,
+    affected: Vec<(Transaction, Transaction)>,
+    item_style: ItemStyle,
+}
+
+impl RulePreview {
+    pub fn new(transaction: Transaction, unedited: Transaction, keywords: &EditKeywords) -> Self {
+        let item_style = ItemStyle::new(&TableColors::new(&PALETTES[0]));
+        let field = |label, keyword: &Option<String>| {
+            keyword
+                .as_ref()
+                .map(|keyword| StringField::new(label, keyword, FIELD_LEN, item_style.clone()))
+        };
+        let mut preview = RulePreview {
+            title: field("Rename titles containing     : ", &keywords.title),
+            category: field("Categorize titles containing : ", &keywords.category),
+            transaction,
+            unedited,
+            focus: PreviewFocus::Scope,
+            as_rule: true,
+            affected: Vec::new(),
+            item_style,
+        };
+        preview.focus = preview.focus_order()[0];
+        preview
+    }
+
+    fn focus_order(&self) -> Vec<PreviewFocus> {
+        self.title
+            .as_ref()
+            .map(|_| PreviewFocus::Title)
+            .into_iter()
+            .chain(self.category.as_ref().map(|_| PreviewFocus::Category))
+            .chain([PreviewFocus::Scope])
+            .collect()
+    }
+
+    pub fn keywords(&self) -> EditKeywords {
+        EditKeywords {
+            title: self
+                .title
+                .as_ref()
+                .map(|field| field.get_value().trim().to_string()),
+            category: self
+                .category
+                .as_ref()
+                .map(|field| field.get_value().trim().to_string()),
+        }
+    }
+
+    // Whatever the keywords are narrowed to, the rules must still label this transaction.
+    pub fn check(&self) -> Result<(), &'static str> {
+        let keywords = self.keywords();
+        let contains =
+            |title: &str, keyword: &str| title.to_lowercase().contains(&keyword.to_lowercase());
+        if keywords.title.as_ref().is_some_and(|keyword| {
+            keyword.is_empty() || !contains(&self.unedited.statement_title, keyword)
+        }) {
+            return Err("The title rule must match this statement title");
+        }
+        if keywords.category.as_ref().is_some_and(|keyword| {
+            keyword.is_empty()
+                || !(contains(&self.transaction.title, keyword)
+                    || contains(&self.unedited.statement_title, keyword))
+        }) {
+            return Err("The category rule must match this title");
+        }
+        Ok(())
+    }
+
+    // Pairs of each transaction as it is now and as the rules would label it.
+    pub fn set_affected(&mut self, affected: Vec<(Transaction, Transaction)>) {
+        self.affected = affected;
+    }
+
+    pub fn handle_key_event(&mut self, key_event: KeyEvent) -> Option<PreviewAction> {
+        let order = self.focus_order();
+        let index = order
+            .iter()
+            .position(|focus| *focus == self.focus)
+            .unwrap_or(0);
+        match key_event.code {
+            KeyCode::Esc => return Some(PreviewAction::Cancel),
+            KeyCode::Enter => {
+                return match self.as_rule {
+                    true => self.check().ok().map(|_| PreviewAction::SaveRules),
+                    false => Some(PreviewAction::SaveOnce),
+                };
+            }
+            KeyCode::Tab => self.focus = order[(index + 1) % order.len()],
+            KeyCode::BackTab => self.focus = order[(index + order.len() - 1) % order.len()],
+            _ => match self.focus {
+                PreviewFocus::Title => {
+                    self.title.as_mut()?.handle_key_event(key_event);
+                    return Some(PreviewAction::Narrowed);
+                }
+                PreviewFocus::Category => {
+                    self.category.as_mut()?.handle_key_event(key_event);
+                    return Some(PreviewAction::Narrowed);
+                }
+                PreviewFocus::Scope => match key_event.code {
+                    KeyCode::Right | KeyCode::Char(';') | KeyCode::Left | KeyCode::Char('j') => {
+                        self.as_rule = !self.as_rule
+                    }
+                    _ => (),
+                },
+            },
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let area = centered_rect(80, 60, frame.area());
+        frame.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title("Save as a rule?")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        let inner_area = block.inner(area);
+        frame.render_widget(block, area);
+
+        let fields: Vec<(PreviewFocus, &StringField)> = [
+            (PreviewFocus::Title, self.title.as_ref()),
+            (PreviewFocus::Category, self.category.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(focus, field)| Some((focus, field?)))
+        .collect();
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(fields.len() as u16),
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Fill(1),
+                Constraint::Length(1),
+            ])
+            .split(inner_area);
+
+        let field_rows =
+            Layout::vertical(vec![Constraint::Length(1); fields.len()]).split(layout[0]);
+        for ((focus, field), row) in fields.iter().zip(field_rows.iter()) {
+            frame.render_stateful_widget(*field, *row, &mut PopupFocus::default());
+            if *focus == self.focus {
+                frame.set_cursor_position(row.offset(field.cursor_offset()));
+            }
+        }
+
+        let scope = match self.as_rule {
+            true => "< the rules above >",
+            false => "< this transaction only >",
+        };
+        let scope_style = match self.focus == PreviewFocus::Scope {
+            true => self.item_style.selected,
+            false => self.item_style.non_selected,
+        };
+        let scope_areas = Layout::horizontal([
+            Constraint::Length(10),
+            Constraint::Length(scope.len() as u16),
+        ])
+        .split(layout[1]);
+        frame.render_widget(Paragraph::new("Apply to: ").bold(), scope_areas[0]);
+        frame.render_widget(Paragraph::new(scope).style(scope_style), scope_areas[1]);
+
+        let header = ["Date", "Account", "Title", "Category", "Amount"]
+            .into_iter()
+            .map(Cell::from)
+            .collect::<Row>()
+            .bold();
+        let change = |before: String, after: String| match before == after {
+            true => after,
+            false => format!("{before} -> {after}"),
+        };
+        let label = |transaction: &Transaction| match &transaction.group {
+            Some(category) => category.to_string(),
+            None => "N/A".to_string(),
+        };
+        // The transaction being edited comes first and stands out.
+        let rows = self
+            .affected
+            .iter()
+            .filter(|_| self.as_rule)
+            .map(|(before, after)| {
+                let row = Row::new([
+                    Cell::from(after.date.to_string()),
+                    Cell::from(after.account.clone()),
+                    Cell::from(change(before.title.clone(), after.title.clone())),
+                    Cell::from(change(label(before), label(after))),
+                    Cell::from(format!("{} {}", after.amount, after.amount.currency)),
+                ]);
+                match after.id == self.transaction.id {
+                    true => row.style(Style::default().add_modifier(Modifier::BOLD)),
+                    false => row,
+                }
+            });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(16),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Length(16),
+            ],
+        )
+        .header(header);
+        frame.render_widget(table, layout[3]);
+
+        let message = match (self.as_rule, self.check()) {
+            (true, Err(error)) => Paragraph::new(error).fg(Color::Red),
+            (true, Ok(_)) => Paragraph::new(format!(
+                "{} transactions change   Enter: save   Tab: next field   Esc: cancel",
+                self.affected.len()
+            )),
+            (false, _) => Paragraph::new("Enter: save on this transaction   Esc: cancel"),
+        };
+        frame.render_widget(message.centered(), layout[4]);
+    }
+}
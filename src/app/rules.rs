@@ -0,0 +1,403 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use itertools::Itertools;
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Cell, Clear, Paragraph, Row, Table, TableState,
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::app::color::{PALETTES, TableColors};
+use crate::app::popup::{ItemStyle, PopupFocus, centered_area};
+use crate::app::stringfield::StringField;
+use crate::import::parse_date;
+use crate::labeling::{check_rule, move_rule};
+use crate::models::{Category, Currency, Kind, Money, Rule, Transaction};
+
+const FIELD_LEN: usize = 48;
+
+// Labels are padded to the same width so the values line up.
+const LABELS: [&str; 12] = [
+    "Priority   : ",
+    "Contains   : ",
+    "Regex      : ",
+    "Min amount : ",
+    "Max amount : ",
+    "Kind       : ",
+    "Account    : ",
+    "From       : ",
+    "To         : ",
+    "Category   : ",
+    "Rename     : ",
+    "Tags       : ",
+];
+
+impl Rule {
+    fn ref_array(&self, matches: usize) -> [Cow<'_, str>; 6] {
+        [
+            Cow::Owned(self.id.to_string()),
+            Cow::Owned(self.priority.to_string()),
+            Cow::Owned(matches.to_string()),
+            Cow::Borrowed(match self.disabled {
+                true => "off",
+                false => "on",
+            }),
+            Cow::Owned(match self.conditions().is_empty() {
+                true => "always".to_string(),
+                false => self.conditions().join(", "),
+            }),
+            Cow::Owned(self.actions().join(", ")),
+        ]
+    }
+}
+
+fn optional(value: String) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        value => Some(value.to_string()),
+    }
+}
+
+fn amount_value(cents: Option<i64>) -> String {
+    cents
+        .map(|cents| Money::new(cents, Currency::default()).to_string())
+        .unwrap_or_default()
+}
+
+fn parse_amount(value: String) -> Result<Option<i64>, String> {
+    optional(value)
+        .map(|value| {
+            Money::parse(&value.replace(',', "."), Currency::default())
+                .map(|amount| amount.abs().cents)
+                .ok_or_else(|| format!("Invalid amount {value}"))
+        })
+        .transpose()
+}
+
+fn parse_rule_date(value: String) -> Result<Option<chrono::NaiveDate>, String> {
+    optional(value)
+        .map(|value| parse_date(&value).ok_or_else(|| format!("Invalid date {value}")))
+        .transpose()
+}
+
+struct RuleForm {
+    id: u32,
+    disabled: bool,
+    fields: Vec<StringField>,
+    focus: usize,
+}
+
+impl RuleForm {
+    fn new(rule: &Rule) -> Self {
+        let style = ItemStyle::new(&TableColors::new(&PALETTES[0]));
+        let values = [
+            rule.priority.to_string(),
+            rule.contains.clone().unwrap_or_default(),
+            rule.pattern.clone().unwrap_or_default(),
+            amount_value(rule.min_amount),
+            amount_value(rule.max_amount),
+            rule.kind.as_ref().map(Kind::to_string).unwrap_or_default(),
+            rule.account.clone().unwrap_or_default(),
+            rule.from_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+            rule.to_date
+                .map(|date| date.to_string())
+                .unwrap_or_default(),
+            rule.category
+                .as_ref()
+                .map(Category::to_string)
+                .unwrap_or_default(),
+            rule.rename.clone().unwrap_or_default(),
+            rule.tags.join(", "),
+        ];
+        RuleForm {
+            id: rule.id,
+            disabled: rule.disabled,
+            fields: LABELS
+                .iter()
+                .zip(values.iter())
+                .map(|(label, value)| StringField::new(label, value, FIELD_LEN, style.clone()))
+                .collect(),
+            // Most edits are to the conditions, so the form opens on the first one.
+            focus: 1,
+        }
+    }
+
+    fn value(&self, index: usize) -> String {
+        self.fields[index].get_value()
+    }
+
+    fn get_rule(&self) -> Result<Rule, String> {
+        let rule = Rule {
+            id: self.id,
+            priority: match optional(self.value(0)) {
+                Some(priority) => priority
+                    .parse()
+                    .map_err(|_| "Priority must be a whole number".to_string())?,
+                None => 0,
+            },
+            contains: optional(self.value(1)).map(|contains| contains.to_lowercase()),
+            pattern: optional(self.value(2)),
+            min_amount: parse_amount(self.value(3))?,
+            max_amount: parse_amount(self.value(4))?,
+            kind: optional(self.value(5))
+                .map(|kind| Kind::from_str(&kind).map_err(|_| format!("Unknown kind {kind}")))
+                .transpose()?,
+            account: optional(self.value(6)),
+            from_date: parse_rule_date(self.value(7))?,
+            to_date: parse_rule_date(self.value(8))?,
+            category: optional(self.value(9)).map(|category| Category::from(category.as_str())),
+            rename: optional(self.value(10)),
+            tags: self
+                .value(11)
+                .split(',')
+                .filter_map(|tag| optional(tag.to_string()))
+                .unique()
+                .collect(),
+            disabled: self.disabled,
+        };
+        check_rule(&rule)?;
+        Ok(rule)
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Tab | KeyCode::Down => self.focus = (self.focus + 1) % self.fields.len(),
+            KeyCode::BackTab | KeyCode::Up => {
+                self.focus = (self.focus + self.fields.len() - 1) % self.fields.len()
+            }
+            _ => self.fields[self.focus].handle_key_event(key_event),
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let area = centered_area(76, self.fields.len() as u16 + 4, frame.area());
+        frame.render_widget(Clear, area);
+
+        let block = Block::default()
+            .title(match self.id {
+                0 => "New rule".to_string(),
+                id => format!("Edit rule {id}"),
+            })
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded);
+        let inner_area = block.inner(area);
+        frame.render_widget(block, area);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(self.fields.len() as u16),
+                Constraint::Length(1),
+                Constraint::Length(1),
+            ])
+            .split(inner_area);
+        let rows =
+            Layout::vertical(vec![Constraint::Length(1); self.fields.len()]).split(layout[0]);
+        for (field, row) in self.fields.iter().zip(rows.iter()) {
+            frame.render_stateful_widget(field, *row, &mut PopupFocus::default());
+        }
+        frame.set_cursor_position(rows[self.focus].offset(self.fields[self.focus].cursor_offset()));
+
+        let message = match self.get_rule() {
+            Ok(_) => Paragraph::new("Enter: save   Tab: next field   Esc: cancel"),
+            Err(error) => Paragraph::new(error).fg(Color::Red),
+        };
+        frame.render_widget(message.centered(), layout[2]);
+    }
+}
+
+pub enum RulesAction {
+    Save(Vec<Rule>),
+    Delete(u32),
+}
+
+pub struct RulesComponent {
+    state: TableState,
+    items: Vec<Rule>,
+    matches: HashMap<u32, usize>,
+    form: Option<RuleForm>,
+    confirming: bool,
+}
+
+impl RulesComponent {
+    pub fn new(items: Vec<Rule>, transactions: &[Transaction]) -> Self {
+        let mut rules = Self {
+            state: TableState::default().with_selected(0),
+            items: Vec::new(),
+            matches: HashMap::new(),
+            form: None,
+            confirming: false,
+        };
+        rules.set_items(items, transactions);
+        rules
+    }
+
+    // Transactions are labeled before the rules are set, so each count is the number of
+    // transactions the rule actually labeled.
+    pub fn set_items(&mut self, items: Vec<Rule>, transactions: &[Transaction]) {
+        self.matches = transactions
+            .iter()
+            .flat_map(|transaction| transaction.rules.iter())
+            .counts()
+            .into_iter()
+            .map(|(id, count)| (*id, count))
+            .collect();
+        self.items = items;
+        if self.state.selected().is_none_or(|i| i >= self.items.len()) {
+            self.state.select(self.items.len().checked_sub(1));
+        }
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.form.is_some() || self.confirming
+    }
+
+    pub fn next_row(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) if i + 1 < self.items.len() => i + 1,
+            _ => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    pub fn previous_row(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(0) | None => self.items.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.state.select(Some(i));
+    }
+
+    fn selected(&self) -> Option<&Rule> {
+        self.items.get(self.state.selected()?)
+    }
+
+    fn move_selected(&mut self, up: bool) -> Option<RulesAction> {
+        let index = self.state.selected()?;
+        let changed = move_rule(&mut self.items, index, up);
+        if changed.is_empty() {
+            return None;
+        }
+        match up {
+            true => self.previous_row(),
+            false => self.next_row(),
+        }
+        Some(RulesAction::Save(changed))
+    }
+
+    fn handle_form_key_events(&mut self, key_event: KeyEvent) -> Option<RulesAction> {
+        let form = self.form.as_mut()?;
+        match key_event.code {
+            KeyCode::Esc => self.form = None,
+            KeyCode::Enter => {
+                let rule = form.get_rule().ok()?;
+                self.form = None;
+                return Some(RulesAction::Save(vec![rule]));
+            }
+            _ => form.handle_key_event(key_event),
+        }
+        None
+    }
+
+    pub fn handle_key_events(&mut self, key_event: KeyEvent) -> Option<RulesAction> {
+        if self.form.is_some() {
+            return self.handle_form_key_events(key_event);
+        }
+        if self.confirming {
+            self.confirming = false;
+            return match key_event.code {
+                KeyCode::Char('y') => self.selected().map(|rule| RulesAction::Delete(rule.id)),
+                _ => None,
+            };
+        }
+
+        match key_event.code {
+            KeyCode::Char('k') | KeyCode::Down => self.next_row(),
+            KeyCode::Char('l') | KeyCode::Up => self.previous_row(),
+            KeyCode::Char('K') => return self.move_selected(false),
+            KeyCode::Char('L') => return self.move_selected(true),
+            KeyCode::Enter | KeyCode::Char('e') => {
+                self.form = self.selected().map(RuleForm::new);
+            }
+            KeyCode::Char('n') => self.form = Some(RuleForm::new(&Rule::default())),
+            KeyCode::Char(' ') => {
+                let mut rule = self.selected()?.clone();
+                rule.disabled = !rule.disabled;
+                return Some(RulesAction::Save(vec![rule]));
+            }
+            KeyCode::Char('d') => self.confirming = self.selected().is_some(),
+            _ => (),
+        }
+        None
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Fill(1), Constraint::Length(1)])
+            .split(area);
+
+        let header = ["ID", "Priority", "Matches", "", "Conditions", "Actions"]
+            .into_iter()
+            .map(Cell::from)
+            .collect::<Row>()
+            .bold();
+        let rows = self.items.iter().map(|rule| {
+            let row = rule
+                .ref_array(self.matches.get(&rule.id).copied().unwrap_or(0))
+                .into_iter()
+                .map(Cell::from)
+                .collect::<Row>();
+            match rule.disabled {
+                true => row.fg(Color::DarkGray),
+                false => row,
+            }
+        });
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .title("Rules, checked from top to bottom");
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(6),
+                Constraint::Length(9),
+                Constraint::Length(8),
+                Constraint::Length(4),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(block);
+        frame.render_stateful_widget(table, layout[0], &mut self.state);
+
+        let message = match (self.confirming, self.selected()) {
+            (true, Some(rule)) => Paragraph::new(format!(
+                "Delete rule {} and relabel its {} transactions? (y/n)",
+                rule.id,
+                self.matches.get(&rule.id).copied().unwrap_or(0)
+            ))
+            .fg(Color::Red),
+            _ => Paragraph::new(
+                "e: edit   n: new   Space: enable/disable   L/K: move up/down   d: delete",
+            ),
+        };
+        frame.render_widget(message.centered(), layout[1]);
+
+        if let Some(form) = &self.form {
+            form.render(frame);
+        }
+    }
+}
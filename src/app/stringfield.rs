@@ -15,10 +15,10 @@ pub struct StringField {
 }
 
 impl StringField {
-    pub fn new(label: &'static str, value: &String, max_length: usize, style: ItemStyle) -> Self {
+    pub fn new(label: &'static str, value: &str, max_length: usize, style: ItemStyle) -> Self {
         Self {
             label,
-            value: value.clone(),
+            value: value.to_string(),
             max_length,
             style,
         }
@@ -34,10 +34,8 @@ impl StringField {
 
     pub fn handle_key_event(&mut self, key_event: KeyEvent) {
         match key_event.code {
-            KeyCode::Char(c) => {
-                if self.value.len() < self.max_length {
-                    self.value.push(c);
-                }
+            KeyCode::Char(c) if self.value.len() < self.max_length => {
+                self.value.push(c);
             }
             KeyCode::Backspace => {
                 self.value.pop();
@@ -48,7 +46,7 @@ impl StringField {
 
     pub fn cursor_offset(&self) -> Offset {
         let x = (self.label.len() + self.value.len() + 2) as i32;
-        Offset { x: x, y: 0 }
+        Offset { x, y: 0 }
     }
 }
 
@@ -1,4 +1,5 @@
 use crossterm::event::{KeyCode, KeyEvent};
+use itertools::Itertools;
 use ratatui::Frame;
 use ratatui::layout::Direction;
 use ratatui::{
@@ -9,27 +10,54 @@ use ratatui::{
 };
 use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 
-use crate::app::color::{PALETTES, TableColors};
+use crate::app::bulk::{BulkAction, BulkForm};
 use crate::app::footer::Footer;
 use crate::app::popup::PopupForm;
-use crate::labeling::FieldMap;
-use crate::models::{Category, Transaction};
+use crate::app::preview::{EditKeywords, PreviewAction, RulePreview};
+use crate::classifier::Classifier;
+use crate::labeling::{EDIT_PRIORITY, RuleSet, label_transactions};
+use crate::models::{Category, CategoryList, Rule, Split, Transaction};
 
 impl Transaction {
-    fn ref_array(&self, index: u32) -> [Cow<str>; 6] {
-        let group_string = match &self.group {
-            Some(s) => s.to_string(),
-            None => "N/A".to_string(),
+    fn ref_array(&self, index: u32) -> [Cow<'_, str>; 9] {
+        let group_string = match (&self.group, self.splits.len()) {
+            (_, 0) => match &self.group {
+                Some(s) => s.to_string(),
+                None => "N/A".to_string(),
+            },
+            (Some(s), splits) => format!("{s} + {splits} splits"),
+            (None, splits) => format!("{splits} splits"),
+        };
+        let amount_string = match self.original {
+            Some(original) => format!(
+                "{} {} ({} {})",
+                self.amount, self.amount.currency, original, original.currency
+            ),
+            None => format!("{} {}", self.amount, self.amount.currency),
+        };
+        let kind_string = match self.is_transfer() {
+            true => "Transfer".to_string(),
+            false => self.kind.to_string(),
         };
-        let amount_string = format!("{:.2}", self.amount);
         [
             Cow::Owned(index.to_string()),
             Cow::Owned(self.date.to_string()),
             Cow::Borrowed(&self.title),
+            Cow::Borrowed(&self.account),
             Cow::Owned(amount_string),
-            Cow::Owned(self.kind.to_string()),
+            Cow::Owned(kind_string),
             Cow::Owned(group_string),
+            Cow::Owned(self.tags.join(", ")),
+            Cow::Owned(
+                self.rules
+                    .iter()
+                    .map(|id| format!("#{id}"))
+                    .chain(self.is_edited().then(|| "edited".to_string()))
+                    .chain(self.hidden.then(|| "hidden".to_string()))
+                    .join(" "),
+            ),
         ]
     }
 }
@@ -51,56 +79,88 @@ pub enum TableMode {
     Help,
     Search,
     Searched,
+    Bulk,
+    Preview,
 }
 
 pub struct TableComponent {
     state: TableState,
     pub items: Vec<Transaction>,
     filtered_items: Vec<Transaction>,
-    colors: TableColors,
     popup: PopupForm,
     mode: TableMode,
     footer: Footer,
-    title_map: FieldMap<String>,
-    category_map: FieldMap<Category>,
+    rules: Vec<Rule>,
+    rule_edits: Vec<Rule>,
+    category_list: CategoryList,
+    account_filter: Option<String>,
+    category_filter: Option<Category>,
+    split_edit: Option<(u32, Vec<Split>)>,
+    tag_edit: Option<(u32, Vec<String>)>,
+    override_edit: Option<Transaction>,
+    marked: HashSet<u32>,
+    bulk: Option<BulkForm>,
+    bulk_edit: Vec<Transaction>,
+    preview: Option<RulePreview>,
+    classifier: Classifier,
 }
 
 impl TableComponent {
-    pub fn new(transactions: &Vec<Transaction>) -> Self {
-        let items = transactions.clone();
+    pub fn new(transactions: &[Transaction]) -> Self {
         Self {
             state: TableState::default().with_selected(0),
-            colors: TableColors::new(&PALETTES[0]),
             items: transactions.to_vec(),
             filtered_items: transactions.to_vec(),
             mode: TableMode::Normal,
-            title_map: FieldMap::<String>::new(),
-            category_map: FieldMap::<Category>::new(),
-            popup: PopupForm::new(transactions[0].clone()),
+            rules: Vec::new(),
+            rule_edits: Vec::new(),
+            category_list: CategoryList::default(),
+            popup: PopupForm::new(transactions[0].clone(), &CategoryList::default(), None),
             footer: Footer::new(),
+            account_filter: None,
+            category_filter: None,
+            split_edit: None,
+            tag_edit: None,
+            override_edit: None,
+            marked: HashSet::new(),
+            bulk: None,
+            bulk_edit: Vec::new(),
+            preview: None,
+            classifier: Classifier::default(),
         }
     }
 
-    pub fn set_categories(&mut self, category_map: FieldMap<Category>) {
-        self.category_map = category_map;
+    pub fn set_rules(&mut self, rules: Vec<Rule>) {
+        self.rules = rules;
     }
 
-    pub fn get_categories(&self) -> FieldMap<Category> {
-        self.category_map.clone()
+    pub fn set_category_list(&mut self, category_list: CategoryList) {
+        self.category_list = category_list;
     }
 
-    pub fn set_titlemap(&mut self, title_map: FieldMap<String>) {
-        self.title_map = title_map;
+    pub fn set_items(&mut self, transactions: Vec<Transaction>) {
+        self.filtered_items = transactions.clone();
+        self.items = transactions;
+        self.mode = TableMode::Normal;
+        self.account_filter = None;
+        self.category_filter = None;
+        self.state.select_first();
     }
 
-    pub fn get_titlemap(&self) -> FieldMap<String> {
-        self.title_map.clone()
+    fn visible_len(&self) -> usize {
+        match self.mode {
+            TableMode::Search | TableMode::Searched | TableMode::Bulk => self.filtered_items.len(),
+            _ => self.items.len(),
+        }
     }
 
     pub fn next_row(&mut self) {
+        if self.visible_len() == 0 {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
-                if i >= self.items.len() - 1 {
+                if i >= self.visible_len() - 1 {
                     0
                 } else {
                     i + 1
@@ -112,10 +172,13 @@ impl TableComponent {
     }
 
     pub fn previous_row(&mut self) {
+        if self.visible_len() == 0 {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.items.len() - 1
+                    self.visible_len() - 1
                 } else {
                     i - 1
                 }
@@ -134,41 +197,339 @@ impl TableComponent {
         }
     }
 
-    pub fn set_current_row(&mut self, transaction: &Transaction) {
-        self.category_map
-            .insert(&transaction.title, &transaction.group);
+    pub fn take_split_edit(&mut self) -> Option<(u32, Vec<Split>)> {
+        self.split_edit.take()
+    }
+
+    fn set_splits(&mut self, transaction: &Transaction) {
+        let mut changed = false;
+        for item in self
+            .items
+            .iter_mut()
+            .chain(self.filtered_items.iter_mut())
+            .filter(|item| item.id == transaction.id && item.splits != transaction.splits)
+        {
+            item.splits = transaction.splits.clone();
+            changed = true;
+        }
+        if changed {
+            self.split_edit = Some((transaction.id, transaction.splits.clone()));
+        }
+    }
+
+    pub fn take_tag_edit(&mut self) -> Option<(u32, Vec<String>)> {
+        self.tag_edit.take()
+    }
+
+    fn set_tags(&mut self, transaction: &Transaction) {
+        let mut changed = false;
+        for item in self
+            .items
+            .iter_mut()
+            .chain(self.filtered_items.iter_mut())
+            .filter(|item| item.id == transaction.id && item.manual_tags != transaction.manual_tags)
+        {
+            item.manual_tags = transaction.manual_tags.clone();
+            changed = true;
+        }
+        if changed {
+            self.tag_edit = Some((transaction.id, transaction.manual_tags.clone()));
+        }
+    }
+
+    pub fn take_rule_edits(&mut self) -> Vec<Rule> {
+        std::mem::take(&mut self.rule_edits)
+    }
+
+    pub fn take_override_edit(&mut self) -> Option<Transaction> {
+        self.override_edit.take()
+    }
+
+    // Edits made in the form become keyword rules checked before the imported ones. A keyword
+    // rule for the same title that only does the same thing is reused, and enabled again, so
+    // editing the same title again does not pile up rules.
+    fn edit_rule(
+        rules: &mut Vec<Rule>,
+        edits: &mut Vec<Rule>,
+        keyword: &str,
+        renames: bool,
+        update: impl Fn(&mut Rule),
+    ) {
+        let keyword = Rule::keyword(keyword);
+        let existing = rules.iter().position(|rule| {
+            rule.conditions() == keyword.conditions()
+                && rule.rename.is_some() == renames
+                && rule.category.is_some() != renames
+                && rule.tags.is_empty()
+        });
+        let rule = match existing {
+            Some(index) => &mut rules[index],
+            None => {
+                rules.push(Rule {
+                    priority: EDIT_PRIORITY,
+                    ..keyword
+                });
+                rules.last_mut().expect("rule was just added")
+            }
+        };
+        update(rule);
+        rule.disabled = false;
+        edits.push(rule.clone());
+    }
+
+    // The rules that would carry what the form changed, keyed on the given keywords. `unedited`
+    // is labeled by the rules alone, so only what the form changed becomes a rule.
+    fn edit_rules(
+        &self,
+        unedited: &Transaction,
+        transaction: &Transaction,
+        keywords: &EditKeywords,
+    ) -> Vec<Rule> {
+        let mut rules = self.rules.clone();
+        let mut edits = Vec::new();
+        if let Some(keyword) = &keywords.title {
+            // Every statement title that was shown under the old name follows it to the new one.
+            for rule in rules
+                .iter_mut()
+                .filter(|rule| rule.rename.as_ref() == Some(&unedited.title))
+            {
+                rule.rename = Some(transaction.title.clone());
+                edits.push(rule.clone());
+            }
+            Self::edit_rule(&mut rules, &mut edits, keyword, true, |rule| {
+                rule.rename = Some(transaction.title.clone())
+            });
+        }
+
+        if let Some(keyword) = &keywords.category
+            && let Some(group) = &transaction.group
+        {
+            Self::edit_rule(&mut rules, &mut edits, keyword, false, |rule| {
+                rule.category = Some(group.clone())
+            });
+        }
+        edits
+    }
+
+    // Rules keep their ID when edited and new ones have none yet.
+    fn apply_rule_edits(rules: &mut Vec<Rule>, edits: &[Rule]) {
+        for edit in edits {
+            match rules
+                .iter_mut()
+                .find(|rule| edit.id != 0 && rule.id == edit.id)
+            {
+                Some(rule) => *rule = edit.clone(),
+                None => rules.push(edit.clone()),
+            }
+        }
+    }
+
+    // The transaction as the rules alone label it, without what was kept on it.
+    fn unedited(&self, item: &Transaction) -> Transaction {
+        let mut unedited = [Transaction {
+            title_override: None,
+            category_override: None,
+            ..item.clone()
+        }];
+        label_transactions(&mut unedited, &RuleSet::new(self.rules.clone()));
+        unedited[0].clone()
+    }
+
+    // Every transaction whose title or category the rules would change, as it is now and as it
+    // would be, starting with the one being edited.
+    fn affected(&self, transaction_id: u32, edits: &[Rule]) -> Vec<(Transaction, Transaction)> {
+        let mut rules = self.rules.clone();
+        Self::apply_rule_edits(&mut rules, edits);
+        let mut relabeled = self.items.clone();
+        for row in relabeled.iter_mut().filter(|row| row.id == transaction_id) {
+            row.title_override = None;
+            row.category_override = None;
+        }
+        label_transactions(&mut relabeled, &RuleSet::new(rules));
+        self.items
+            .iter()
+            .zip(relabeled)
+            .filter(|(before, after)| before.title != after.title || before.group != after.group)
+            .map(|(before, after)| (before.clone(), after))
+            .sorted_by_key(|(_, after)| after.id != transaction_id)
+            .collect()
+    }
 
-        let mut item_title = "".to_string();
-        for mut item in self.items.iter_mut() {
-            if item.id == transaction.id {
-                item_title = item.title.clone();
-                item = &mut transaction.clone();
+    // Edits saved as rules are previewed first, unless they change no title or category.
+    fn open_preview(&mut self, transaction: Transaction) {
+        let Some(item) = self.items.iter().find(|item| item.id == transaction.id) else {
+            return;
+        };
+        let unedited = self.unedited(item);
+        let keywords = EditKeywords::new(&unedited, &transaction);
+        if keywords.is_empty() {
+            self.set_current_row(&transaction, Some(Vec::new()));
+            return;
+        }
+        self.preview = Some(RulePreview::new(transaction, unedited, &keywords));
+        self.refresh_preview();
+        self.mode = TableMode::Preview;
+    }
+
+    fn refresh_preview(&mut self) {
+        let Some(preview) = &self.preview else {
+            return;
+        };
+        let affected = match preview.check() {
+            Ok(()) => self.affected(
+                preview.transaction.id,
+                &self.edit_rules(&preview.unedited, &preview.transaction, &preview.keywords()),
+            ),
+            Err(_) => Vec::new(),
+        };
+        if let Some(preview) = self.preview.as_mut() {
+            preview.set_affected(affected);
+        }
+    }
+
+    fn handle_preview_key_events(&mut self, key_event: KeyEvent) {
+        let Some(mut preview) = self.preview.take() else {
+            self.mode = TableMode::Normal;
+            return;
+        };
+        match preview.handle_key_event(key_event) {
+            Some(PreviewAction::Narrowed) => {
+                self.preview = Some(preview);
+                return self.refresh_preview();
+            }
+            Some(PreviewAction::SaveRules) => {
+                let edits =
+                    self.edit_rules(&preview.unedited, &preview.transaction, &preview.keywords());
+                self.set_current_row(&preview.transaction, Some(edits));
+            }
+            Some(PreviewAction::SaveOnce) => self.set_current_row(&preview.transaction, None),
+            Some(PreviewAction::Cancel) => (),
+            None => {
+                self.preview = Some(preview);
+                return;
             }
         }
+        self.mode = TableMode::Normal;
+    }
 
-        for (key, value) in &self.title_map.map.clone() {
-            if *value == item_title {
-                self.title_map
-                    .insert(&key.to_string(), &Some(transaction.title.clone()));
+    // A title or category changed in the form is kept on the transaction, ahead of any rule,
+    // unless it is saved as rules. Then whatever was kept on the transaction is dropped so the
+    // rules apply to it again.
+    pub fn set_current_row(&mut self, transaction: &Transaction, rule_edits: Option<Vec<Rule>>) {
+        self.set_splits(transaction);
+        self.set_tags(transaction);
+        let Some(item) = self
+            .items
+            .iter()
+            .find(|item| item.id == transaction.id)
+            .cloned()
+        else {
+            return;
+        };
+
+        let mut edited = Transaction {
+            notes: transaction.notes.clone(),
+            ..item.clone()
+        };
+        match rule_edits {
+            Some(rule_edits) => {
+                Self::apply_rule_edits(&mut self.rules, &rule_edits);
+                self.rule_edits.extend(rule_edits);
+                edited.title_override = None;
+                edited.category_override = None;
+            }
+            None => {
+                if transaction.title != item.title {
+                    edited.title_override = Some(transaction.title.clone());
+                }
+                if transaction.group != item.group {
+                    edited.category_override = transaction.group.clone();
+                }
             }
         }
 
-        self.title_map
-            .insert(&item_title, &Some(transaction.title.clone()));
+        if edited.title_override != item.title_override
+            || edited.category_override != item.category_override
+            || edited.notes != item.notes
+        {
+            for row in self
+                .items
+                .iter_mut()
+                .chain(self.filtered_items.iter_mut())
+                .filter(|row| row.id == transaction.id)
+            {
+                row.title_override = edited.title_override.clone();
+                row.category_override = edited.category_override.clone();
+                row.notes = edited.notes.clone();
+            }
+            self.override_edit = Some(edited);
+        }
         self.update_transactions();
     }
 
+    // Every change of labels is something more to learn from. Search results are copies, so
+    // they are refreshed to show the new labels too.
     pub fn update_transactions(&mut self) {
-        for transaction in self.items.iter_mut() {
-            match self.title_map.get(&transaction.title) {
-                Some(title) => {
-                    transaction.title = title.clone();
-                }
-                None => (),
+        label_transactions(&mut self.items, &RuleSet::new(self.rules.clone()));
+        self.classifier = Classifier::train(&self.items);
+        let labeled: HashMap<u32, &Transaction> =
+            self.items.iter().map(|item| (item.id, item)).collect();
+        for row in self.filtered_items.iter_mut() {
+            if let Some(item) = labeled.get(&row.id) {
+                *row = (*item).clone();
             }
+        }
+    }
 
-            transaction.group = self.category_map.get(&transaction.title);
+    pub fn take_bulk_edit(&mut self) -> Vec<Transaction> {
+        std::mem::take(&mut self.bulk_edit)
+    }
+
+    fn toggle_mark(&mut self) {
+        let Some(row) = self
+            .state
+            .selected()
+            .and_then(|index| self.filtered_items.get(index))
+        else {
+            return;
+        };
+        if !self.marked.remove(&row.id) {
+            self.marked.insert(row.id);
         }
+        self.next_row();
+    }
+
+    // Marks every match, or clears the marks when they already cover every match.
+    fn mark_all(&mut self) {
+        let matches: HashSet<u32> = self.filtered_items.iter().map(|row| row.id).collect();
+        self.marked = match self.marked == matches {
+            true => HashSet::new(),
+            false => matches,
+        };
+    }
+
+    fn apply_bulk(&mut self, action: &BulkAction) {
+        let edited = action.apply(&mut self.items, &self.marked);
+        self.bulk_edit.extend(edited);
+        self.update_transactions();
+    }
+
+    // Rows without a category get the most likely of the categories still offered.
+    fn suggestion(&self, transaction: &Transaction) -> Option<(Category, f64)> {
+        if transaction.group.is_some() {
+            return None;
+        }
+        let active = self.category_list.active();
+        self.classifier
+            .suggest(transaction)
+            .into_iter()
+            .find(|(category, _)| active.contains(category))
+    }
+
+    fn open_popup(&mut self) {
+        let transaction = self.get_current_row();
+        let suggestion = self.suggestion(&transaction);
+        self.popup = PopupForm::new(transaction, &self.category_list, suggestion);
     }
 
     fn search_items(&mut self, substring: String) {
@@ -176,23 +537,94 @@ impl TableComponent {
             .items
             .clone()
             .into_iter()
+            .filter(|row| {
+                self.account_filter
+                    .as_ref()
+                    .is_none_or(|account| row.account == *account)
+            })
+            // A category also matches everything filed under its subcategories.
+            .filter(|row| {
+                self.category_filter.as_ref().is_none_or(|filter| {
+                    row.parts().iter().any(|(category, _)| {
+                        category
+                            .as_ref()
+                            .is_some_and(|category| self.category_list.contains(filter, category))
+                    })
+                })
+            })
             .filter(|row| {
                 row.title
                     .to_ascii_lowercase()
                     .contains(&substring.to_ascii_lowercase())
+                    || row
+                        .notes
+                        .to_ascii_lowercase()
+                        .contains(&substring.to_ascii_lowercase())
+                    || row.tags.iter().any(|tag| {
+                        tag.to_ascii_lowercase()
+                            .contains(&substring.to_ascii_lowercase())
+                    })
             })
             .collect();
+        // Only what is shown can stay marked, so a bulk edit never reaches rows out of sight.
+        let shown: HashSet<u32> = self.filtered_items.iter().map(|row| row.id).collect();
+        self.marked.retain(|id| shown.contains(id));
         self.state.select_first();
     }
 
+    fn next_account(&mut self) {
+        let accounts: Vec<&String> = self
+            .items
+            .iter()
+            .map(|row| &row.account)
+            .unique()
+            .sorted()
+            .collect();
+        let next = match &self.account_filter {
+            None => accounts.first(),
+            Some(current) => accounts
+                .iter()
+                .position(|account| *account == current)
+                .and_then(|index| accounts.get(index + 1)),
+        };
+        self.account_filter = next.map(|account| account.to_string());
+        self.apply_filters();
+    }
+
+    fn next_category(&mut self) {
+        let categories: Vec<Category> = self.category_list.active();
+        let next = match &self.category_filter {
+            None => categories.first(),
+            Some(current) => categories
+                .iter()
+                .position(|category| category == current)
+                .and_then(|index| categories.get(index + 1)),
+        };
+        self.category_filter = next.cloned();
+        self.apply_filters();
+    }
+
+    fn apply_filters(&mut self) {
+        match self.account_filter.is_some() || self.category_filter.is_some() {
+            true => {
+                self.search_items(String::new());
+                self.mode = TableMode::Searched;
+            }
+            false => {
+                self.mode = TableMode::Normal;
+                self.state.select_first();
+            }
+        }
+    }
+
     fn sort_items(&mut self, sort_option: SortOptions) {
         let sort_closure: fn(&Transaction, &Transaction) -> Ordering = match sort_option {
             SortOptions::DateAsc => |a, b| a.date.cmp(&b.date),
             SortOptions::DateDesc => |a, b| b.date.cmp(&a.date),
             SortOptions::TitleAsc => |a, b| a.title.cmp(&b.title),
             SortOptions::TitleDesc => |a, b| b.title.cmp(&a.title),
-            SortOptions::AmountAsc => |a, b| a.amount.partial_cmp(&b.amount).unwrap(),
-            SortOptions::AmountDesc => |a, b| b.amount.partial_cmp(&a.amount).unwrap(),
+            SortOptions::AmountAsc => |a, b| a.amount.cmp(&b.amount),
+            SortOptions::AmountDesc => |a, b| b.amount.cmp(&a.amount),
         };
         self.items.sort_by(sort_closure);
         self.state.select_first();
@@ -205,13 +637,15 @@ impl TableComponent {
     pub fn handle_key_events(&mut self, key_event: KeyEvent) {
         match self.mode {
             TableMode::Normal => match key_event.code {
-                KeyCode::Enter => {
+                KeyCode::Enter if !self.items.is_empty() => {
                     self.mode = TableMode::Popup;
-                    self.popup = PopupForm::new(self.get_current_row());
+                    self.open_popup();
                 }
                 KeyCode::Char('k') | KeyCode::Down => self.next_row(),
                 KeyCode::Char('l') | KeyCode::Up => self.previous_row(),
                 KeyCode::Char('o') => self.mode = TableMode::Ordering,
+                KeyCode::Char('a') => self.next_account(),
+                KeyCode::Char('c') => self.next_category(),
                 KeyCode::Char('?') => self.mode = TableMode::Help,
                 KeyCode::Char('/') => {
                     self.filtered_items = self.items.clone();
@@ -219,13 +653,16 @@ impl TableComponent {
                 }
                 _ => (),
             },
-            TableMode::Popup => match self.popup.handle_key_event(key_event) {
-                Some(transaction) => {
-                    self.set_current_row(&transaction);
+            TableMode::Popup => {
+                if let Some(transaction) = self.popup.handle_key_event(key_event) {
                     self.mode = TableMode::Normal;
+                    match self.popup.saves_rule() {
+                        true => self.open_preview(transaction),
+                        false => self.set_current_row(&transaction, None),
+                    }
                 }
-                None => (),
-            },
+            }
+            TableMode::Preview => self.handle_preview_key_events(key_event),
             TableMode::Ordering => {
                 match key_event.code {
                     KeyCode::Char('d') => self.sort_items(SortOptions::DateAsc),
@@ -259,15 +696,45 @@ impl TableComponent {
             TableMode::Searched => match key_event.code {
                 KeyCode::Esc => {
                     self.mode = TableMode::Normal;
+                    self.account_filter = None;
+                    self.category_filter = None;
+                    self.marked.clear();
+                }
+                KeyCode::Char(' ') => self.toggle_mark(),
+                KeyCode::Char('*') => self.mark_all(),
+                KeyCode::Char('b') if !self.marked.is_empty() => {
+                    self.bulk = Some(BulkForm::new(self.marked.len(), &self.category_list));
+                    self.mode = TableMode::Bulk;
                 }
-                KeyCode::Enter => {
-                    self.popup = PopupForm::new(self.get_current_row());
+                KeyCode::Char('a') => self.next_account(),
+                KeyCode::Char('c') => self.next_category(),
+                KeyCode::Enter if !self.filtered_items.is_empty() => {
+                    self.open_popup();
                     self.mode = TableMode::Popup;
                 }
                 KeyCode::Char('k') | KeyCode::Down => self.next_row(),
                 KeyCode::Char('l') | KeyCode::Up => self.previous_row(),
                 _ => (),
             },
+            TableMode::Bulk => {
+                let action = match key_event.code {
+                    KeyCode::Esc => None,
+                    _ => match self
+                        .bulk
+                        .as_mut()
+                        .map(|bulk| bulk.handle_key_event(key_event))
+                    {
+                        Some(None) => return,
+                        Some(action) => action,
+                        None => None,
+                    },
+                };
+                if let Some(action) = action {
+                    self.apply_bulk(&action);
+                }
+                self.bulk = None;
+                self.mode = TableMode::Searched;
+            }
             TableMode::Help => self.mode = TableMode::Normal,
         }
     }
@@ -276,10 +743,11 @@ impl TableComponent {
         let footer_size = match self.mode {
             TableMode::Normal => 0,
             TableMode::Ordering => 6 + 2,
-            TableMode::Popup => 0,
-            TableMode::Help => 5 + 2,
+            TableMode::Popup | TableMode::Preview => 0,
+            TableMode::Help => 8 + 2,
             TableMode::Search => 1,
             TableMode::Searched => 1,
+            TableMode::Bulk => 1,
         };
 
         let layout = Layout::default()
@@ -293,34 +761,63 @@ impl TableComponent {
         let selected_row_style = Style::default().add_modifier(Modifier::REVERSED);
         // .fg(self.colors.selected_row_style_fg);
 
-        let header = ["", "Date", "Title", "Amount (R$)", "Kind", "Group"]
-            .into_iter()
-            .map(Cell::from)
-            .collect::<Row>()
-            .style(header_style)
-            .height(1);
+        let header = [
+            "", "Date", "Title", "Account", "Amount", "Kind", "Group", "Tags", "Rules",
+        ]
+        .into_iter()
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(header_style)
+        .height(1);
 
         let item_list = match self.mode {
             TableMode::Searched => &self.filtered_items,
             TableMode::Search => &self.filtered_items,
+            TableMode::Bulk => &self.filtered_items,
             _ => &self.items,
         };
 
+        // Marked rows are starred and hidden ones dimmed.
         let rows = item_list.iter().enumerate().map(|(i, data)| {
-            let item = data.ref_array(i as u32);
-            item.into_iter().map(Cell::from).collect::<Row>().height(1)
+            let mut item = data.ref_array(i as u32);
+            let mut style = Style::default();
+            if self.marked.contains(&data.id) {
+                item[0] = Cow::Owned(format!("*{}", item[0]));
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            if data.hidden {
+                style = style.add_modifier(Modifier::DIM);
+            }
+            item.into_iter()
+                .map(Cell::from)
+                .collect::<Row>()
+                .style(style)
+                .height(1)
         });
         let bar = " █ ";
+        let title = ["Transactions".to_string()]
+            .into_iter()
+            .chain(self.account_filter.clone())
+            .chain(
+                self.category_filter
+                    .as_ref()
+                    .map(|category| self.category_list.label(category)),
+            )
+            .chain((!self.marked.is_empty()).then(|| format!("{} marked", self.marked.len())))
+            .join(" - ");
         let block = Block::bordered()
             .border_type(BorderType::Rounded)
-            .title("Transactions");
+            .title(title);
         let t = Table::new(
             rows,
             [
                 Constraint::Max(5),
                 Constraint::Length(12),
                 Constraint::Min(20),
-                Constraint::Length(12),
+                Constraint::Length(16),
+                Constraint::Length(24),
+                Constraint::Length(20),
+                Constraint::Length(20),
                 Constraint::Length(20),
                 Constraint::Length(20),
             ],
@@ -346,6 +843,16 @@ impl TableComponent {
             TableMode::Popup => {
                 self.popup.render(frame);
             }
+            TableMode::Bulk => {
+                if let Some(bulk) = &self.bulk {
+                    bulk.render(frame);
+                }
+            }
+            TableMode::Preview => {
+                if let Some(preview) = &self.preview {
+                    preview.render(frame);
+                }
+            }
             _ => (),
         }
     }
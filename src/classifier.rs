@@ -0,0 +1,208 @@
+use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+
+use crate::labeling::STOP_WORDS;
+use crate::models::{Category, Transaction};
+
+// Both titles are used, so a merchant is recognized by its statement name and by the name it
+// was given. Numbers are mostly dates, card digits and installment counts, so they are left out.
+fn features(transaction: &Transaction) -> Vec<String> {
+    let amount = transaction.amount.abs().cents / 100;
+    format!("{} {}", transaction.statement_title, transaction.title)
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .filter(|word| !word.chars().all(|c| c.is_ascii_digit()))
+        .filter(|word| !STOP_WORDS.contains(word))
+        .map(str::to_string)
+        .unique()
+        // Amounts fall in buckets that double in size: 1, 2-3, 4-7, 8-15 and so on.
+        .chain([format!("amount:{}", amount.max(1).ilog2())])
+        .collect()
+}
+
+#[derive(Default, Clone)]
+struct ClassCounts {
+    transactions: usize,
+    features: HashMap<String, usize>,
+    total_features: usize,
+}
+
+// Naive Bayes over title words and an amount bucket, trained on the transactions that already
+// have a category.
+#[derive(Default, Clone)]
+pub struct Classifier {
+    classes: HashMap<Category, ClassCounts>,
+    vocabulary: HashSet<String>,
+    transactions: usize,
+}
+
+impl Classifier {
+    pub fn train(transactions: &[Transaction]) -> Self {
+        let mut classifier = Classifier::default();
+        for transaction in transactions
+            .iter()
+            .filter(|row| !row.is_transfer() && row.category_confirmed)
+        {
+            let Some(category) = &transaction.group else {
+                continue;
+            };
+            let counts = classifier.classes.entry(category.clone()).or_default();
+            counts.transactions += 1;
+            for feature in features(transaction) {
+                *counts.features.entry(feature.clone()).or_default() += 1;
+                counts.total_features += 1;
+                classifier.vocabulary.insert(feature);
+            }
+            classifier.transactions += 1;
+        }
+        classifier
+    }
+
+    // Categories ordered from the most to the least likely, each with its probability. Without
+    // a single known word in the title there is nothing to go on but the amount, so there is no
+    // guess at all.
+    pub fn suggest(&self, transaction: &Transaction) -> Vec<(Category, f64)> {
+        let features = features(transaction);
+        if !features
+            .iter()
+            .any(|feature| !feature.starts_with("amount:") && self.vocabulary.contains(feature))
+        {
+            return Vec::new();
+        }
+
+        // Laplace smoothing keeps a word never seen with a category from ruling it out.
+        let vocabulary = self.vocabulary.len() as f64;
+        let scores: Vec<(Category, f64)> = self
+            .classes
+            .iter()
+            .map(|(category, counts)| {
+                let prior = (counts.transactions as f64 / self.transactions as f64).ln();
+                let likelihood: f64 = features
+                    .iter()
+                    .map(|feature| {
+                        let count = counts.features.get(feature).copied().unwrap_or(0) as f64;
+                        ((count + 1.0) / (counts.total_features as f64 + vocabulary)).ln()
+                    })
+                    .sum();
+                (category.clone(), prior + likelihood)
+            })
+            .collect();
+
+        let best = scores
+            .iter()
+            .map(|(_, score)| *score)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let total: f64 = scores.iter().map(|(_, score)| (score - best).exp()).sum();
+        scores
+            .into_iter()
+            .map(|(category, score)| (category, (score - best).exp() / total))
+            .sorted_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Kind;
+
+    fn labeled(id: u32, title: &str, cents: i64, category: &str) -> Transaction {
+        Transaction {
+            group: Some(Category::from(category)),
+            category_confirmed: true,
+            ..Transaction::sample(id, "2024-03-01", title, cents, Kind::CreditPurchase)
+        }
+    }
+
+    fn training() -> Vec<Transaction> {
+        vec![
+            labeled(1, "IFOOD *RESTAURANTE SABOR", 4500, "Food"),
+            labeled(2, "IFOOD *PIZZARIA", 6000, "Food"),
+            labeled(3, "PADARIA PAO QUENTE", 1200, "Food"),
+            labeled(4, "UBER TRIP", 2300, "Transportation"),
+            labeled(5, "UBER TRIP HELP", 1800, "Transportation"),
+            labeled(6, "POSTO SHELL", 20000, "Transportation"),
+            Transaction::sample(
+                7,
+                "2024-03-01",
+                "IFOOD *LANCHES",
+                3000,
+                Kind::CreditPurchase,
+            ),
+        ]
+    }
+
+    fn unlabeled(title: &str, cents: i64) -> Transaction {
+        Transaction::sample(100, "2024-03-10", title, cents, Kind::CreditPurchase)
+    }
+
+    #[test]
+    fn suggests_the_category_of_similar_titles_first() {
+        let classifier = Classifier::train(&training());
+        let suggestions = classifier.suggest(&unlabeled("IFOOD *HAMBURGUERIA", 5000));
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0].0, Category::from("Food"));
+        assert!(suggestions[0].1 > suggestions[1].1);
+        let total: f64 = suggestions.iter().map(|(_, probability)| probability).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        let suggestions = classifier.suggest(&unlabeled("UBER *TRIP 1234", 2000));
+        assert_eq!(suggestions[0].0, Category::from("Transportation"));
+    }
+
+    #[test]
+    fn no_guess_without_a_known_word() {
+        let classifier = Classifier::train(&training());
+        assert!(
+            classifier
+                .suggest(&unlabeled("LIVRARIA CULTURA", 4500))
+                .is_empty()
+        );
+        assert!(
+            classifier
+                .suggest(&unlabeled("COMPRA 2024", 4500))
+                .is_empty()
+        );
+        assert!(
+            Classifier::default()
+                .suggest(&unlabeled("IFOOD", 4500))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn unconfirmed_categories_are_not_learned() {
+        let carried_over = Transaction {
+            category_confirmed: false,
+            ..labeled(8, "LIVRARIA CULTURA", 4500, "Books")
+        };
+        let classifier = Classifier::train(&[carried_over]);
+        assert!(
+            classifier
+                .suggest(&unlabeled("LIVRARIA CULTURA", 4500))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn transfers_are_not_learned() {
+        let transfer = Transaction {
+            transfer_of: Some(1),
+            ..labeled(8, "PAGAMENTO FATURA", 50000, "Bills")
+        };
+        let classifier = Classifier::train(&[transfer]);
+        assert!(
+            classifier
+                .suggest(&unlabeled("PAGAMENTO FATURA", 50000))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn features_leave_out_numbers_and_stop_words() {
+        let transaction = unlabeled("Compra PIX de 12/03 Padaria 42", 350);
+        assert_eq!(features(&transaction), vec!["padaria", "amount:1"]);
+        assert_eq!(features(&unlabeled("X", 0)), vec!["amount:0"]);
+    }
+}
@@ -0,0 +1,313 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::models::{AccountKind, BudgetGroup, Category, Kind};
+
+#[derive(Parser)]
+#[command(
+    name = "easymoney",
+    version,
+    about = "Terminal-based personal finance tracker"
+)]
+pub struct Cli {
+    /// SQLite database to use instead of the one in the user data directory
+    #[arg(long, global = true)]
+    pub database: Option<PathBuf>,
+
+    /// Configuration file with CSV mapping profiles and the reporting currency
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Import bank statements into the database
+    Import(ImportArgs),
+    /// Open the terminal interface (default)
+    Tui,
+    /// Print monthly income and expenses
+    Report(ReportArgs),
+    /// List, add or remove labeling rules
+    Rules(RulesArgs),
+    /// Suggest one name for titles of the same merchant and turn them into rename rules
+    Merchants(MerchantsArgs),
+    /// List accounts and their balances
+    Accounts(AccountsArgs),
+    /// List categories or change how they are budgeted and shown
+    Categories(CategoriesArgs),
+    /// List exchange rates or load them from a CSV file
+    Rates(RatesArgs),
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// Statement files or folders containing statements
+    #[arg(required_unless_present_any = ["undo", "list"])]
+    pub paths: Vec<PathBuf>,
+
+    /// Remove an import batch and every transaction it added
+    #[arg(long, value_name = "BATCH", conflicts_with_all = ["paths", "list"])]
+    pub undo: Option<u32>,
+
+    /// List previous import batches
+    #[arg(long, conflicts_with = "paths")]
+    pub list: bool,
+
+    /// Import statements even if the same file was imported before
+    #[arg(long)]
+    pub force: bool,
+
+    /// Only show what would be imported, without writing to the database
+    #[arg(long, conflicts_with = "yes")]
+    pub dry_run: bool,
+
+    /// Commit the import without asking for confirmation, as scheduled imports must
+    #[arg(long, short)]
+    pub yes: bool,
+
+    /// List every row in the preview, not only rejected and transformed ones
+    #[arg(long, short)]
+    pub verbose: bool,
+
+    /// Print the parsed statements before inserting them
+    #[arg(long)]
+    pub print: bool,
+
+    /// Account the statements belong to, defaults to the importer name
+    #[arg(long)]
+    pub account: Option<String>,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    /// Only report a single month, formatted as YYYY-MM
+    #[arg(long)]
+    pub month: Option<String>,
+
+    /// Number of most recent months to report
+    #[arg(long, default_value_t = 12)]
+    pub last: usize,
+
+    /// Break expenses down by category
+    #[arg(long)]
+    pub categories: bool,
+}
+
+#[derive(Args)]
+pub struct RulesArgs {
+    #[command(subcommand)]
+    pub command: Option<RulesCommand>,
+
+    /// Only show rules whose keyword or pattern contains this text
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Only show rules that set a category
+    #[arg(long, conflicts_with = "titles")]
+    pub categories: bool,
+
+    /// Only show rules that rename titles
+    #[arg(long)]
+    pub titles: bool,
+}
+
+#[derive(Subcommand)]
+pub enum RulesCommand {
+    /// Add a rule, checked before every rule with a lower priority
+    Add(Box<RuleArgs>),
+    /// Remove a rule by its ID
+    Remove(RuleRemoveArgs),
+    /// Write every rule and category to a TOML file that can be shared
+    Export(RulesExportArgs),
+    /// Merge rules and categories from a file written by `rules export`
+    Import(RulesImportArgs),
+}
+
+#[derive(Args)]
+pub struct RuleArgs {
+    /// Rules with a higher priority are checked first, ties go to the oldest rule
+    #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+    pub priority: i64,
+
+    /// Match titles containing this text, ignoring case
+    #[arg(long)]
+    pub contains: Option<String>,
+
+    /// Match titles against a regular expression, ignoring case
+    #[arg(long)]
+    pub regex: Option<String>,
+
+    /// Match amounts of at least this value, without sign
+    #[arg(long)]
+    pub min_amount: Option<String>,
+
+    /// Match amounts of at most this value, without sign
+    #[arg(long)]
+    pub max_amount: Option<String>,
+
+    /// Match one kind of transaction, such as CreditPurchase or Income
+    #[arg(long)]
+    pub kind: Option<Kind>,
+
+    /// Match transactions of one account
+    #[arg(long)]
+    pub account: Option<String>,
+
+    /// Match transactions on or after this date
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// Match transactions on or before this date
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// Category given to matching transactions
+    #[arg(long)]
+    pub category: Option<Category>,
+
+    /// Title shown instead of the statement title
+    #[arg(long)]
+    pub rename: Option<String>,
+
+    /// Tag added to matching transactions, can be repeated
+    #[arg(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct RuleRemoveArgs {
+    /// Rule ID, as listed by `rules`
+    pub id: u32,
+}
+
+#[derive(Args)]
+pub struct RulesExportArgs {
+    /// File to write, replaced if it exists
+    pub path: PathBuf,
+}
+
+#[derive(Args)]
+pub struct RulesImportArgs {
+    /// File written by `rules export`
+    pub path: PathBuf,
+
+    /// Only report what would be added and what conflicts, without saving anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Take the file's version of rules and categories that differ from the database's
+    #[arg(long)]
+    pub replace: bool,
+}
+
+#[derive(Args)]
+pub struct MerchantsArgs {
+    /// Accept a suggestion by its number, as listed by `merchants`, can be repeated
+    #[arg(long, value_name = "NUMBER", conflicts_with = "all")]
+    pub accept: Vec<usize>,
+
+    /// Accept every suggestion
+    #[arg(long)]
+    pub all: bool,
+
+    /// Only list what accepting would rename, without saving anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args)]
+pub struct AccountsArgs {
+    #[command(subcommand)]
+    pub command: Option<AccountsCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum AccountsCommand {
+    /// Create an account or update its details
+    Set(AccountArgs),
+}
+
+#[derive(Args)]
+pub struct AccountArgs {
+    /// Account name, as given to `import --account`
+    pub name: String,
+
+    /// Bank or institution holding the account
+    #[arg(long)]
+    pub institution: Option<String>,
+
+    /// Account type: checking, savings, credit-card, cash or investment
+    #[arg(long = "type", value_name = "TYPE")]
+    pub kind: Option<AccountKind>,
+
+    /// Currency code, such as BRL, which cannot change once the account has transactions
+    #[arg(long)]
+    pub currency: Option<String>,
+
+    /// Balance before the first imported transaction
+    #[arg(long, allow_hyphen_values = true)]
+    pub opening_balance: Option<String>,
+}
+
+#[derive(Args)]
+pub struct CategoriesArgs {
+    #[command(subcommand)]
+    pub command: Option<CategoriesCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum CategoriesCommand {
+    /// Create a category or update its details
+    Set(CategoryArgs),
+}
+
+#[derive(Args)]
+pub struct CategoryArgs {
+    /// Category name, as shown in reports and the edit form
+    pub name: String,
+
+    /// Make it a subcategory of another category, such as Food for Restaurants
+    #[arg(long, conflicts_with = "top_level")]
+    pub parent: Option<String>,
+
+    /// Move a subcategory back to the top level
+    #[arg(long)]
+    pub top_level: bool,
+
+    /// Color of the category in charts, a name such as blue or a hex code such as #3b82f6
+    #[arg(long)]
+    pub color: Option<String>,
+
+    /// Part of the 50/30/20 rule it counts towards: needs, wants, savings or none
+    #[arg(long)]
+    pub budget: Option<BudgetGroup>,
+
+    /// Stop offering the category for new labels, keeping its history
+    #[arg(long, conflicts_with = "unarchive")]
+    pub archive: bool,
+
+    /// Offer an archived category again
+    #[arg(long)]
+    pub unarchive: bool,
+}
+
+#[derive(Args)]
+pub struct RatesArgs {
+    #[command(subcommand)]
+    pub command: Option<RatesCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum RatesCommand {
+    /// Load rates from a CSV file with date, from, to and rate columns
+    Import(RatesImportArgs),
+}
+
+#[derive(Args)]
+pub struct RatesImportArgs {
+    /// CSV file where each line says one unit of `from` is worth `rate` units of `to`
+    pub path: PathBuf,
+}
@@ -0,0 +1,36 @@
+use color_eyre::eyre::{Result, eyre};
+use dirs_next::config_dir;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::format::Profile;
+use crate::models::Currency;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub reporting_currency: Currency,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("easymoney").join("config.toml"))
+    }
+
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Config::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path).map_err(|e| eyre!("{}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| eyre!("{}: {e}", path.display()))
+    }
+}
@@ -1,11 +1,16 @@
-use crate::labeling::FieldMap;
-use crate::models::Category;
-use crate::models::{NewTransaction, Transaction};
+use crate::labeling;
+use crate::models::{
+    Account, AccountKind, CategoryInfo, CategoryList, Currency, ExchangeRate, ImportBatch,
+    Installment, Kind, Money, NewTransaction, Rule, Split, Transaction,
+};
+use crate::refund::{self, Purchase};
+use crate::settlement::{Settlement, settle_bill_payments};
 use dirs_next::data_dir;
-use rusqlite::{Connection, Result};
+use itertools::Itertools;
+use rusqlite::{Connection, OptionalExtension, Result};
 use std::collections::HashMap;
 use std::fs;
-use std::str::FromStr;
+use std::path::Path;
 
 pub struct Database {
     conn: Connection,
@@ -14,15 +19,19 @@ pub struct Database {
 impl Database {
     pub fn new() -> Result<Self> {
         let database_dir = data_dir().unwrap().join("easymoney");
-        let database_path = database_dir.join("database.db");
+        Database::open(&database_dir.join("database.db"))
+    }
 
-        if !database_dir.exists() {
-            fs::create_dir_all(&database_dir).ok();
+    pub fn open(database_path: &Path) -> Result<Self> {
+        if let Some(database_dir) = database_path.parent()
+            && !database_dir.exists()
+        {
+            fs::create_dir_all(database_dir).ok();
         }
 
         let conn = Connection::open(database_path)?;
         let database = Database { conn };
-        database.initialize_database().ok();
+        database.initialize_database()?;
         Ok(database)
     }
 
@@ -30,6 +39,482 @@ impl Database {
         let _ = self.create_transactions();
         let _ = self.create_categories();
         let _ = self.create_titlemaps();
+        self.migrate()
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let migrations: [fn(&Database) -> Result<()>; 18] = [
+            Database::add_fitid,
+            Database::add_fingerprint,
+            Database::add_imports,
+            Database::add_installments,
+            Database::add_settlements,
+            Database::add_accounts,
+            Database::add_money,
+            Database::add_original_amounts,
+            Database::add_exchange_rates,
+            Database::add_refunds,
+            Database::add_splits,
+            Database::add_category_list,
+            Database::add_category_parents,
+            Database::add_rules,
+            Database::add_rule_switch,
+            Database::add_overrides,
+            Database::add_tags,
+            Database::add_hidden,
+        ];
+
+        let version: usize = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (index, migration) in migrations.iter().enumerate().skip(version) {
+            let transaction = self.conn.unchecked_transaction()?;
+            migration(self)?;
+            self.conn.pragma_update(None, "user_version", index + 1)?;
+            transaction.commit()?;
+        }
+
+        // Links are derived from the whole table, so they are rebuilt once the schema is current.
+        if version < migrations.len() {
+            let transaction = self.conn.unchecked_transaction()?;
+            self.link_transactions()?;
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    // FITIDs are only unique within one account, and there are no accounts yet, so the index
+    // only speeds up lookups. The fingerprint added next keys an OFX row by account and FITID.
+    fn add_fitid(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "ALTER TABLE transactions ADD COLUMN fitid TEXT NULL;
+            CREATE INDEX transactions_fitid ON transactions (fitid);",
+        )
+    }
+
+    // Rows imported before fingerprints were unique by (date, title), so each one is the
+    // first occurrence of its line. They came from the CSV importers or, with a FITID, OFX.
+    // Amounts are still REAL here; they are read into `Money` only because `NewTransaction` holds
+    // one since `add_money`, and written back as they were. This migration was edited in place
+    // for that, which is fine only because no release has shipped it: once one has, a change like
+    // this belongs in a new migration.
+    fn add_fingerprint(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE transactions_fingerprint (
+                id          INTEGER PRIMARY KEY,
+                date        DATE,
+                title       TEXT,
+                amount      REAL NOT NULL,
+                kind        TEXT NOT NULL,
+                tgroup      TEXT NULL,
+                fitid       TEXT NULL,
+                account     TEXT NOT NULL,
+                fingerprint TEXT NOT NULL UNIQUE
+            )",
+        )?;
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT rowid, date, title, amount, kind, tgroup, fitid FROM transactions")?;
+        let rows = statement.query_map([], |row| {
+            let kind: Kind = row.get(4)?;
+            let fitid: Option<String> = row.get(6)?;
+            let account = match (&fitid, &kind) {
+                (Some(_), _) => "ofx",
+                (None, Kind::CreditPurchase | Kind::CreditBillPayment) => "credit",
+                (None, _) => "debit",
+            };
+            let transaction = NewTransaction {
+                date: row.get(1)?,
+                title: row.get(2)?,
+                amount: Money::from_f64(row.get(3)?, Currency::default()).unwrap_or_default(),
+                kind,
+                fitid,
+                account: account.to_string(),
+                fingerprint: String::new(),
+                installment: None,
+                original: None,
+            };
+            let group: Option<String> = row.get(5)?;
+            Ok((row.get::<_, u32>(0)?, group, transaction))
+        })?;
+
+        for row in rows {
+            let (id, group, transaction) = row?;
+            self.conn.execute(
+                "INSERT INTO transactions_fingerprint
+                    (id, date, title, amount, kind, tgroup, fitid, account, fingerprint)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                (
+                    id,
+                    &transaction.date,
+                    &transaction.title,
+                    &transaction.amount.as_f64(),
+                    &transaction.kind,
+                    &group,
+                    &transaction.fitid,
+                    &transaction.account,
+                    &transaction.fingerprint(0),
+                ),
+            )?;
+        }
+        drop(statement);
+
+        self.conn.execute_batch(
+            "DROP TABLE transactions;
+            ALTER TABLE transactions_fingerprint RENAME TO transactions;",
+        )
+    }
+
+    fn add_imports(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE imports (
+                id              INTEGER PRIMARY KEY,
+                path            TEXT NOT NULL,
+                content_hash    TEXT NOT NULL,
+                importer        TEXT NOT NULL,
+                account         TEXT NOT NULL,
+                total_rows      INTEGER NOT NULL,
+                inserted_rows   INTEGER NOT NULL,
+                imported_at     DATETIME NOT NULL
+            );
+            ALTER TABLE transactions ADD COLUMN import_id INTEGER NULL REFERENCES imports (id);
+            CREATE INDEX transactions_import ON transactions (import_id);",
+        )
+    }
+
+    fn add_installments(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "ALTER TABLE transactions ADD COLUMN purchase_title TEXT NULL;
+            ALTER TABLE transactions ADD COLUMN installment_number INTEGER NULL;
+            ALTER TABLE transactions ADD COLUMN installment_total INTEGER NULL;",
+        )?;
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT id, title FROM transactions WHERE kind = ?1")?;
+        let rows = statement.query_map([Kind::CreditPurchase], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (id, title) = row?;
+            if let Some(installment) = Installment::parse(&title) {
+                self.conn.execute(
+                    "UPDATE transactions
+                        SET purchase_title = ?1, installment_number = ?2, installment_total = ?3
+                        WHERE id = ?4",
+                    (
+                        &installment.purchase_title,
+                        &installment.number,
+                        &installment.total,
+                        id,
+                    ),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_settlements(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "ALTER TABLE transactions ADD COLUMN settled_by INTEGER NULL;
+            ALTER TABLE transactions ADD COLUMN transfer_of INTEGER NULL;",
+        )
+    }
+
+    fn add_accounts(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE accounts (
+                id              INTEGER PRIMARY KEY,
+                name            TEXT NOT NULL UNIQUE,
+                institution     TEXT NULL,
+                type            TEXT NOT NULL,
+                currency        TEXT NOT NULL,
+                opening_balance REAL NOT NULL
+            );
+            INSERT INTO accounts (name, type, currency, opening_balance)
+                SELECT account,
+                    CASE WHEN SUM(kind IN ('CreditPurchase', 'CreditBillPayment')) > 0
+                        THEN 'credit-card' ELSE 'checking' END,
+                    'BRL',
+                    0
+                FROM transactions GROUP BY account;",
+        )
+    }
+
+    // REAL amounts were always written from two-decimal statement values, so rounding them to
+    // whole cents restores the exact amount.
+    fn add_money(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE transactions_money (
+                id                  INTEGER PRIMARY KEY,
+                date                DATE,
+                title               TEXT,
+                amount              INTEGER NOT NULL,
+                currency            TEXT NOT NULL,
+                kind                TEXT NOT NULL,
+                tgroup              TEXT NULL,
+                fitid               TEXT NULL,
+                account             TEXT NOT NULL,
+                fingerprint         TEXT NOT NULL UNIQUE,
+                import_id           INTEGER NULL REFERENCES imports (id),
+                purchase_title      TEXT NULL,
+                installment_number  INTEGER NULL,
+                installment_total   INTEGER NULL,
+                settled_by          INTEGER NULL,
+                transfer_of         INTEGER NULL
+            );
+            INSERT INTO transactions_money
+                SELECT id, date, title, CAST(ROUND(amount * 100) AS INTEGER),
+                    COALESCE((SELECT currency FROM accounts WHERE name = account), 'BRL'),
+                    kind, tgroup, fitid, account, fingerprint, import_id, purchase_title,
+                    installment_number, installment_total, settled_by, transfer_of
+                FROM transactions;
+            DROP TABLE transactions;
+            ALTER TABLE transactions_money RENAME TO transactions;
+            CREATE INDEX transactions_import ON transactions (import_id);
+
+            CREATE TABLE accounts_money (
+                id              INTEGER PRIMARY KEY,
+                name            TEXT NOT NULL UNIQUE,
+                institution     TEXT NULL,
+                type            TEXT NOT NULL,
+                currency        TEXT NOT NULL,
+                opening_balance INTEGER NOT NULL
+            );
+            INSERT INTO accounts_money
+                SELECT id, name, institution, type, UPPER(currency),
+                    CAST(ROUND(opening_balance * 100) AS INTEGER)
+                FROM accounts;
+            DROP TABLE accounts;
+            ALTER TABLE accounts_money RENAME TO accounts;",
+        )
+    }
+
+    fn add_original_amounts(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "ALTER TABLE transactions ADD COLUMN original_amount INTEGER NULL;
+            ALTER TABLE transactions ADD COLUMN original_currency TEXT NULL;",
+        )
+    }
+
+    fn add_exchange_rates(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE exchange_rates (
+                date            DATE NOT NULL,
+                from_currency   TEXT NOT NULL,
+                to_currency     TEXT NOT NULL,
+                rate            REAL NOT NULL,
+                PRIMARY KEY (date, from_currency, to_currency)
+            );",
+        )
+    }
+
+    // Refunds used to be stored as bill payments or income, so existing credits are classified
+    // again with the same rules the importer applies.
+    fn add_refunds(&self) -> Result<()> {
+        self.conn.execute(
+            "ALTER TABLE transactions ADD COLUMN refund_of INTEGER NULL",
+            (),
+        )?;
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT id, date, title, amount, currency, kind, account FROM transactions")?;
+        let rows = statement
+            .query_map([], |row| {
+                let kind: Kind = row.get(5)?;
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    kind,
+                    Purchase {
+                        id: Some(row.get(0)?),
+                        date: row.get(1)?,
+                        title: row.get(2)?,
+                        amount: Money::new(row.get(3)?, row.get(4)?),
+                        account: row.get(6)?,
+                    },
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let purchases: Vec<Purchase> = rows
+            .iter()
+            .filter(|(_, kind, _)| matches!(kind, Kind::CreditPurchase | Kind::DebitPurchase))
+            .map(|(_, _, row)| row.clone())
+            .collect();
+        for (id, kind, row) in &rows {
+            if *kind != Kind::Refund
+                && refund::is_refund(
+                    kind,
+                    row.date,
+                    &row.title,
+                    row.amount,
+                    &row.account,
+                    &purchases,
+                )
+            {
+                self.conn.execute(
+                    "UPDATE transactions SET kind = ?1 WHERE id = ?2",
+                    (Kind::Refund, id),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_splits(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE splits (
+                id              INTEGER PRIMARY KEY,
+                transaction_id  INTEGER NOT NULL REFERENCES transactions (id),
+                amount          INTEGER NOT NULL,
+                category        TEXT NOT NULL,
+                note            TEXT NOT NULL
+            );
+            CREATE INDEX splits_transaction ON splits (transaction_id);",
+        )
+    }
+
+    // Categories used to be a fixed list in the code. It is seeded here along with any other name
+    // rules and splits already point at, so nothing labeled before goes missing.
+    fn add_category_list(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE category_list (
+                id              INTEGER PRIMARY KEY,
+                name            TEXT NOT NULL UNIQUE,
+                color           TEXT NULL,
+                budget          TEXT NOT NULL,
+                archived        INTEGER NOT NULL DEFAULT 0
+            );
+            INSERT INTO category_list (name, color, budget) VALUES
+                ('Housing', 'blue', 'needs'),
+                ('Transportation', 'yellow', 'needs'),
+                ('Food', 'red', 'needs'),
+                ('Supermarket', 'green', 'needs'),
+                ('Savings', 'cyan', 'savings'),
+                ('Health', 'magenta', 'needs'),
+                ('Personal', 'lightblue', 'wants'),
+                ('Trips', 'lightyellow', 'wants'),
+                ('Other', 'gray', 'wants');
+            INSERT OR IGNORE INTO category_list (name, budget)
+                SELECT category, 'none' FROM categories WHERE category IS NOT NULL
+                UNION SELECT category, 'none' FROM splits;",
+        )
+    }
+
+    fn add_category_parents(&self) -> Result<()> {
+        self.conn
+            .execute("ALTER TABLE category_list ADD COLUMN parent TEXT NULL", ())?;
+        Ok(())
+    }
+
+    fn add_rule_switch(&self) -> Result<()> {
+        self.conn.execute(
+            "ALTER TABLE rules ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+        Ok(())
+    }
+
+    // Manual categories go in the tgroup column, which nothing wrote before.
+    fn add_overrides(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "ALTER TABLE transactions ADD COLUMN title_override TEXT NULL;
+            ALTER TABLE transactions ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+        )?;
+        Ok(())
+    }
+
+    // Each tag name is stored once and shared by every transaction carrying it.
+    fn add_tags(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE tags (
+                id      INTEGER PRIMARY KEY,
+                name    TEXT NOT NULL UNIQUE
+            );
+            CREATE TABLE transaction_tags (
+                transaction_id  INTEGER NOT NULL REFERENCES transactions (id),
+                tag_id          INTEGER NOT NULL REFERENCES tags (id),
+                PRIMARY KEY (transaction_id, tag_id)
+            );
+            CREATE INDEX transaction_tags_tag ON transaction_tags (tag_id);",
+        )
+    }
+
+    fn add_hidden(&self) -> Result<()> {
+        self.conn.execute(
+            "ALTER TABLE transactions ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0",
+            (),
+        )?;
+        Ok(())
+    }
+
+    // Keyword maps become rules. When a keyword was given several categories the latest one is
+    // kept, and longer keywords are checked first. A database without category keywords gets the
+    // default rules it used to fall back to.
+    fn add_rules(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "CREATE TABLE rules (
+                id              INTEGER PRIMARY KEY,
+                priority        INTEGER NOT NULL DEFAULT 0,
+                contains        TEXT NULL,
+                pattern         TEXT NULL,
+                min_amount      INTEGER NULL,
+                max_amount      INTEGER NULL,
+                kind            TEXT NULL,
+                account         TEXT NULL,
+                from_date       DATE NULL,
+                to_date         DATE NULL,
+                category        TEXT NULL,
+                rename          TEXT NULL,
+                tags            TEXT NOT NULL DEFAULT ''
+            );",
+        )?;
+
+        let keywords = |query: &str| -> Result<Vec<(String, String)>> {
+            let mut statement = self.conn.prepare(query)?;
+            let rows = statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<(String, String)>>>()?;
+            Ok(rows
+                .into_iter()
+                .filter(|(keyword, value)| !keyword.trim().is_empty() && !value.trim().is_empty())
+                .unique_by(|(keyword, _)| keyword.clone())
+                .sorted_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(&b.0)))
+                .collect())
+        };
+
+        let mut rules: Vec<Rule> = keywords(
+            "SELECT title, new_title FROM titlemaps WHERE title IS NOT NULL
+                AND new_title IS NOT NULL ORDER BY rowid DESC",
+        )?
+        .into_iter()
+        .map(|(keyword, title)| Rule {
+            rename: Some(title),
+            ..Rule::keyword(&keyword)
+        })
+        .collect();
+        let categories = keywords(
+            "SELECT title, category FROM categories WHERE title IS NOT NULL
+                AND category IS NOT NULL ORDER BY rowid DESC",
+        )?;
+        match categories.is_empty() {
+            true => rules.extend(labeling::default_rules()),
+            false => rules.extend(categories.into_iter().filter_map(|(keyword, category)| {
+                Some(Rule {
+                    category: Some(category.parse().ok()?),
+                    ..Rule::keyword(&keyword)
+                })
+            })),
+        }
+        // Only the columns of this version, later migrations add to the table.
+        for rule in &rules {
+            self.conn.execute(
+                "INSERT INTO rules (contains, category, rename) VALUES (?1, ?2, ?3)",
+                (&rule.contains, &rule.category, &rule.rename),
+            )?;
+        }
         Ok(())
     }
 
@@ -48,40 +533,323 @@ impl Database {
         Ok(())
     }
 
-    pub fn insert_transactions(&self, transactions: Vec<NewTransaction>) -> Result<()> {
+    pub fn insert_import(
+        &self,
+        mut batch: ImportBatch,
+        transactions: Vec<NewTransaction>,
+    ) -> Result<ImportBatch> {
+        let sql_transaction = self.conn.unchecked_transaction()?;
+        self.conn.execute(
+            "INSERT INTO imports
+                (path, content_hash, importer, account, total_rows, inserted_rows, imported_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6)",
+            (
+                &batch.path,
+                &batch.content_hash,
+                &batch.importer,
+                &batch.account,
+                &batch.total_rows,
+                &batch.imported_at,
+            ),
+        )?;
+        batch.id = self.conn.last_insert_rowid() as u32;
+
+        let kind = AccountKind::guess(transactions.iter().map(|row| &row.kind));
+        let currency = transactions
+            .first()
+            .map(|row| row.amount.currency)
+            .unwrap_or_default();
+        self.conn.execute(
+            "INSERT OR IGNORE INTO accounts (name, type, currency, opening_balance)
+                VALUES (?1, ?2, ?3, 0)",
+            (&batch.account, &kind, &currency),
+        )?;
+
+        batch.inserted_rows = 0;
         for transaction in transactions {
-            self.insert_transaction(transaction);
+            batch.inserted_rows += self.insert_transaction(transaction, batch.id)?;
         }
-        Ok(())
+        self.conn.execute(
+            "UPDATE imports SET inserted_rows = ?1 WHERE id = ?2",
+            (&batch.inserted_rows, &batch.id),
+        )?;
+        self.link_transactions()?;
+
+        sql_transaction.commit()?;
+        Ok(batch)
+    }
+
+    pub fn insert_transaction(&self, transaction: NewTransaction, import_id: u32) -> Result<usize> {
+        let installment = &transaction.installment;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO transactions
+                    (date, title, amount, currency, kind, fitid, account, fingerprint, import_id,
+                    purchase_title, installment_number, installment_total,
+                    original_amount, original_currency)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            (
+                &transaction.date,
+                &transaction.title,
+                &transaction.amount.cents,
+                &transaction.amount.currency,
+                &transaction.kind,
+                &transaction.fitid,
+                &transaction.account,
+                &transaction.fingerprint,
+                &import_id,
+                installment.as_ref().map(|i| &i.purchase_title),
+                installment.as_ref().map(|i| i.number),
+                installment.as_ref().map(|i| i.total),
+                transaction.original.map(|original| original.cents),
+                transaction.original.map(|original| original.currency),
+            ),
+        )
+    }
+
+    pub fn has_fingerprint(&self, fingerprint: &str) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM transactions WHERE fingerprint = ?1",
+                [fingerprint],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|row| row.is_some())
+    }
+
+    fn import_from_row(row: &rusqlite::Row) -> Result<ImportBatch> {
+        Ok(ImportBatch {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            content_hash: row.get(2)?,
+            importer: row.get(3)?,
+            account: row.get(4)?,
+            total_rows: row.get(5)?,
+            inserted_rows: row.get(6)?,
+            imported_at: row.get(7)?,
+        })
     }
 
-    pub fn insert_transaction(&self, transaction: NewTransaction) {
+    pub fn get_imports(&self) -> Result<Vec<ImportBatch>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, path, content_hash, importer, account, total_rows, inserted_rows, imported_at
+                FROM imports ORDER BY id DESC",
+        )?;
+        statement
+            .query_map([], Database::import_from_row)?
+            .collect()
+    }
+
+    pub fn find_import(&self, content_hash: &str) -> Result<Option<ImportBatch>> {
         self.conn
-            .execute(
-                "INSERT OR IGNORE INTO transactions (date, title, amount, kind) VALUES (?1, ?2, ?3, ?4)",
+            .query_row(
+                "SELECT id, path, content_hash, importer, account, total_rows, inserted_rows, imported_at
+                    FROM imports WHERE content_hash = ?1 ORDER BY id DESC",
+                [content_hash],
+                Database::import_from_row,
+            )
+            .optional()
+    }
+
+    pub fn undo_import(&self, import_id: u32) -> Result<Option<usize>> {
+        let sql_transaction = self.conn.unchecked_transaction()?;
+        self.conn.execute(
+            "DELETE FROM splits WHERE transaction_id IN
+                (SELECT id FROM transactions WHERE import_id = ?1)",
+            [import_id],
+        )?;
+        self.conn.execute(
+            "DELETE FROM transaction_tags WHERE transaction_id IN
+                (SELECT id FROM transactions WHERE import_id = ?1)",
+            [import_id],
+        )?;
+        self.delete_unused_tags()?;
+        let deleted = self
+            .conn
+            .execute("DELETE FROM transactions WHERE import_id = ?1", [import_id])?;
+        let found = self
+            .conn
+            .execute("DELETE FROM imports WHERE id = ?1", [import_id])?;
+        self.link_transactions()?;
+        sql_transaction.commit()?;
+
+        match found {
+            0 => Ok(None),
+            _ => Ok(Some(deleted)),
+        }
+    }
+
+    fn get_splits(&self) -> Result<HashMap<u32, Vec<Split>>> {
+        // Splits are stored in the currency of their transaction.
+        let mut statement = self.conn.prepare(
+            "SELECT splits.transaction_id, splits.amount, transactions.currency,
+                    splits.category, splits.note
+                FROM splits JOIN transactions ON transactions.id = splits.transaction_id
+                ORDER BY splits.id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                Split {
+                    amount: Money::new(row.get(1)?, row.get(2)?),
+                    category: row.get(3)?,
+                    note: row.get(4)?,
+                },
+            ))
+        })?;
+
+        let mut splits: HashMap<u32, Vec<Split>> = HashMap::new();
+        for row in rows {
+            let (transaction_id, split) = row?;
+            splits.entry(transaction_id).or_default().push(split);
+        }
+        Ok(splits)
+    }
+
+    fn get_tags(&self) -> Result<HashMap<u32, Vec<String>>> {
+        let mut statement = self.conn.prepare(
+            "SELECT transaction_tags.transaction_id, tags.name
+                FROM transaction_tags JOIN tags ON tags.id = transaction_tags.tag_id
+                ORDER BY tags.name",
+        )?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get(1)?)))?;
+
+        let mut tags: HashMap<u32, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (transaction_id, tag) = row?;
+            tags.entry(transaction_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    // A tag no transaction carries any more is forgotten.
+    fn delete_unused_tags(&self) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM transaction_tags)",
+            (),
+        )?;
+        Ok(())
+    }
+
+    pub fn save_tags(&self, transaction_id: u32, tags: &[String]) -> Result<()> {
+        let transaction = self.conn.unchecked_transaction()?;
+        self.replace_tags(transaction_id, tags)?;
+        transaction.commit()
+    }
+
+    // Runs inside the caller's SQL transaction.
+    fn replace_tags(&self, transaction_id: u32, tags: &[String]) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM transaction_tags WHERE transaction_id = ?1",
+            [transaction_id],
+        )?;
+        for tag in tags {
+            self.conn
+                .execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [tag])?;
+            self.conn.execute(
+                "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id)
+                    SELECT ?1, id FROM tags WHERE name = ?2",
+                (&transaction_id, tag),
+            )?;
+        }
+        self.delete_unused_tags()
+    }
+
+    pub fn save_overrides(&self, transaction: &Transaction) -> Result<()> {
+        self.conn.execute(
+            "UPDATE transactions SET title_override = ?1, tgroup = ?2, notes = ?3, hidden = ?4
+                WHERE rowid = ?5",
+            (
+                &transaction.title_override,
+                &transaction.category_override,
+                &transaction.notes,
+                &transaction.hidden,
+                &transaction.id,
+            ),
+        )?;
+        Ok(())
+    }
+
+    // Bulk edits are saved all at once or not at all.
+    pub fn save_edits(&self, transactions: &[Transaction]) -> Result<()> {
+        let sql_transaction = self.conn.unchecked_transaction()?;
+        for transaction in transactions {
+            self.save_overrides(transaction)?;
+            self.replace_tags(transaction.id, &transaction.manual_tags)?;
+        }
+        sql_transaction.commit()
+    }
+
+    pub fn save_splits(&self, transaction_id: u32, splits: &[Split]) -> Result<()> {
+        let transaction = self.conn.unchecked_transaction()?;
+        self.conn.execute(
+            "DELETE FROM splits WHERE transaction_id = ?1",
+            [transaction_id],
+        )?;
+        for split in splits {
+            self.conn.execute(
+                "INSERT INTO splits (transaction_id, amount, category, note)
+                    VALUES (?1, ?2, ?3, ?4)",
                 (
-                    &transaction.date,
-                    &transaction.title,
-                    &transaction.amount,
-                    &transaction.kind,
+                    &transaction_id,
+                    &split.amount.cents,
+                    &split.category,
+                    &split.note,
                 ),
-            )
-            .ok();
+            )?;
+        }
+        transaction.commit()
     }
 
     pub fn get_transactions(&self) -> Result<Vec<Transaction>> {
-        let mut statement = self
-            .conn
-            .prepare("SELECT rowid, date, title, amount, kind, tgroup FROM transactions")?;
+        let mut splits = self.get_splits()?;
+        let mut tags = self.get_tags()?;
+        let mut statement = self.conn.prepare(
+            "SELECT rowid, date, title, amount, kind, tgroup,
+                    purchase_title, installment_number, installment_total,
+                    account, settled_by, transfer_of, currency,
+                    original_amount, original_currency, refund_of,
+                    title_override, notes, hidden
+                    FROM transactions",
+        )?;
 
         let rows = statement.query_map([], |row| {
+            let installment = match (row.get(6)?, row.get(7)?, row.get(8)?) {
+                (Some(purchase_title), Some(number), Some(total)) => Some(Installment {
+                    purchase_title,
+                    number,
+                    total,
+                }),
+                _ => None,
+            };
+            let original = match (row.get(13)?, row.get(14)?) {
+                (Some(cents), Some(currency)) => Some(Money::new(cents, currency)),
+                _ => None,
+            };
+            let id = row.get(0)?;
             Ok(Transaction {
-                id: row.get(0)?,
+                id,
                 date: row.get(1)?,
                 title: row.get(2)?,
-                amount: row.get(3)?,
+                statement_title: row.get(2)?,
+                amount: Money::new(row.get(3)?, row.get(12)?),
                 kind: row.get(4)?,
                 group: row.get(5)?,
+                category_confirmed: false,
+                installment,
+                account: row.get(9)?,
+                settled_by: row.get(10)?,
+                transfer_of: row.get(11)?,
+                refund_of: row.get(15)?,
+                original,
+                splits: splits.remove(&id).unwrap_or_default(),
+                tags: Vec::new(),
+                manual_tags: tags.remove(&id).unwrap_or_default(),
+                rules: Vec::new(),
+                title_override: row.get(16)?,
+                category_override: row.get(5)?,
+                notes: row.get(17)?,
+                hidden: row.get(18)?,
             })
         })?;
 
@@ -92,51 +860,286 @@ impl Database {
         Ok(transactions)
     }
 
-    fn create_categories(&self) -> Result<()> {
+    // Runs inside the caller's SQL transaction, so links never point at removed rows.
+    fn link_transactions(&self) -> Result<()> {
+        self.link_bill_payments()?;
+        self.link_refunds()
+    }
+
+    fn link_refunds(&self) -> Result<()> {
+        let links = refund::link_refunds(&self.get_transactions()?);
+        self.conn
+            .execute("UPDATE transactions SET refund_of = NULL", ())?;
+        for (refund, purchase) in links {
+            self.conn.execute(
+                "UPDATE transactions SET refund_of = ?1 WHERE id = ?2",
+                (purchase, refund),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn link_bill_payments(&self) -> Result<Vec<Settlement>> {
+        let settlements = settle_bill_payments(&self.get_transactions()?);
         self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS categories (
-                title       TEXT,
-                category    TEXT, 
-                PRIMARY KEY (title, category)
-            )",
+            "UPDATE transactions SET settled_by = NULL, transfer_of = NULL",
             (),
         )?;
+        for settlement in &settlements {
+            for purchase in &settlement.purchases {
+                self.conn.execute(
+                    "UPDATE transactions SET settled_by = ?1 WHERE id = ?2",
+                    (settlement.payment, purchase),
+                )?;
+            }
+            if let Some(transfer) = settlement.transfer {
+                self.conn.execute(
+                    "UPDATE transactions SET transfer_of = ?1 WHERE id = ?2",
+                    (settlement.payment, transfer),
+                )?;
+            }
+        }
+        Ok(settlements)
+    }
+
+    pub fn get_accounts(&self) -> Result<Vec<Account>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, name, institution, type, currency, opening_balance
+                FROM accounts ORDER BY name",
+        )?;
+        let rows = statement.query_map([], |row| {
+            let currency: Currency = row.get(4)?;
+            Ok(Account {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                institution: row.get(2)?,
+                kind: row.get(3)?,
+                currency,
+                opening_balance: Money::new(row.get(5)?, currency),
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn save_account(&self, account: &Account) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO accounts (name, institution, type, currency, opening_balance)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (name) DO UPDATE SET
+                    institution = excluded.institution,
+                    type = excluded.type,
+                    currency = excluded.currency,
+                    opening_balance = excluded.opening_balance",
+            (
+                &account.name,
+                &account.institution,
+                &account.kind,
+                &account.currency,
+                &account.opening_balance.cents,
+            ),
+        )?;
         Ok(())
     }
 
-    pub fn insert_categories(&self, categories: FieldMap<Category>) -> Result<()> {
-        for (title, category) in categories.map {
-            self.insert_category(title, category);
+    pub fn insert_exchange_rates(&self, rates: &[ExchangeRate]) -> Result<usize> {
+        let transaction = self.conn.unchecked_transaction()?;
+        for rate in rates {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO exchange_rates (date, from_currency, to_currency, rate)
+                    VALUES (?1, ?2, ?3, ?4)",
+                (&rate.date, &rate.from, &rate.to, &rate.rate),
+            )?;
         }
+        transaction.commit()?;
+        Ok(rates.len())
+    }
+
+    pub fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>> {
+        let mut statement = self.conn.prepare(
+            "SELECT date, from_currency, to_currency, rate
+                FROM exchange_rates ORDER BY from_currency, to_currency, date",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(ExchangeRate {
+                date: row.get(0)?,
+                from: row.get(1)?,
+                to: row.get(2)?,
+                rate: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn get_category_list(&self) -> Result<CategoryList> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, name, parent, color, budget, archived
+                FROM category_list ORDER BY name COLLATE NOCASE",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(CategoryInfo {
+                id: row.get(0)?,
+                category: row.get(1)?,
+                parent: row.get(2)?,
+                color: row.get(3)?,
+                budget: row.get(4)?,
+                archived: row.get(5)?,
+            })
+        })?;
+        Ok(CategoryList::new(rows.collect::<Result<_>>()?))
+    }
+
+    pub fn save_category_info(&self, info: &CategoryInfo) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO category_list (name, parent, color, budget, archived)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (name) DO UPDATE SET
+                    parent = excluded.parent,
+                    color = excluded.color,
+                    budget = excluded.budget,
+                    archived = excluded.archived",
+            (
+                &info.category,
+                &info.parent,
+                &info.color,
+                &info.budget,
+                &info.archived,
+            ),
+        )?;
         Ok(())
     }
 
-    pub fn insert_category(&self, title: String, category: Category) {
-        self.conn
-            .execute(
-                "INSERT OR IGNORE INTO categories (title, category) VALUES (?1, ?2)",
-                (&title, &category),
-            )
-            .ok();
+    fn rule_from_row(row: &rusqlite::Row) -> Result<Rule> {
+        let tags: String = row.get(12)?;
+        Ok(Rule {
+            id: row.get(0)?,
+            priority: row.get(1)?,
+            contains: row.get(2)?,
+            pattern: row.get(3)?,
+            min_amount: row.get(4)?,
+            max_amount: row.get(5)?,
+            kind: row.get(6)?,
+            account: row.get(7)?,
+            from_date: row.get(8)?,
+            to_date: row.get(9)?,
+            category: row.get(10)?,
+            rename: row.get(11)?,
+            tags: tags
+                .split(',')
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+            disabled: row.get(13)?,
+        })
     }
 
-    pub fn get_categories(&self) -> Result<FieldMap<Category>> {
-        let mut statement = self
-            .conn
-            .prepare("SELECT title, category FROM categories")?;
+    pub fn get_rules(&self) -> Result<Vec<Rule>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, priority, contains, pattern, min_amount, max_amount, kind, account,
+                from_date, to_date, category, rename, tags, disabled
+                FROM rules ORDER BY priority DESC, id",
+        )?;
+        let rows = statement.query_map([], Database::rule_from_row)?;
+        rows.collect()
+    }
 
-        let map = statement
-            .query_map([], |row| {
-                let category_str: String = row.get(1)?;
-                Ok((row.get(0)?, Category::from_str(&category_str)?))
-            })?
-            .collect::<Result<HashMap<String, Category>, _>>()?;
+    pub fn insert_rule(&self, rule: &Rule) -> Result<u32> {
+        self.conn.execute(
+            "INSERT INTO rules (priority, contains, pattern, min_amount, max_amount, kind,
+                account, from_date, to_date, category, rename, tags, disabled)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            (
+                &rule.priority,
+                &rule.contains,
+                &rule.pattern,
+                &rule.min_amount,
+                &rule.max_amount,
+                &rule.kind,
+                &rule.account,
+                &rule.from_date,
+                &rule.to_date,
+                &rule.category,
+                &rule.rename,
+                &rule.tags.join(","),
+                &rule.disabled,
+            ),
+        )?;
+        Ok(self.conn.last_insert_rowid() as u32)
+    }
+
+    pub fn update_rule(&self, rule: &Rule) -> Result<()> {
+        self.conn.execute(
+            "UPDATE rules SET priority = ?1, contains = ?2, pattern = ?3, min_amount = ?4,
+                max_amount = ?5, kind = ?6, account = ?7, from_date = ?8, to_date = ?9,
+                category = ?10, rename = ?11, tags = ?12, disabled = ?13
+                WHERE id = ?14",
+            (
+                &rule.priority,
+                &rule.contains,
+                &rule.pattern,
+                &rule.min_amount,
+                &rule.max_amount,
+                &rule.kind,
+                &rule.account,
+                &rule.from_date,
+                &rule.to_date,
+                &rule.category,
+                &rule.rename,
+                &rule.tags.join(","),
+                &rule.disabled,
+                &rule.id,
+            ),
+        )?;
+        Ok(())
+    }
 
-        if map.len() == 0 {
-            return Ok(FieldMap::<Category>::new());
+    // Rules without an ID are new and get one, the others replace the stored rule.
+    pub fn save_rules(&self, rules: &[Rule]) -> Result<()> {
+        let transaction = self.conn.unchecked_transaction()?;
+        self.write_rules(rules)?;
+        transaction.commit()
+    }
+
+    // A rule pack is merged whole or not at all.
+    pub fn save_rule_pack(&self, categories: &[CategoryInfo], rules: &[Rule]) -> Result<()> {
+        let transaction = self.conn.unchecked_transaction()?;
+        for info in categories {
+            self.save_category_info(info)?;
         }
+        self.write_rules(rules)?;
+        transaction.commit()
+    }
 
-        Ok(FieldMap { map })
+    fn write_rules(&self, rules: &[Rule]) -> Result<()> {
+        for rule in rules {
+            match rule.id {
+                0 => {
+                    self.insert_rule(rule)?;
+                }
+                _ => self.update_rule(rule)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete_rule(&self, rule_id: u32) -> Result<bool> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM rules WHERE id = ?1", (rule_id,))?
+            > 0)
+    }
+
+    // The keyword tables rules were kept in before the rule engine. Migrations still read them, so
+    // they are created on new databases too.
+    fn create_categories(&self) -> Result<()> {
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS categories (
+                title       TEXT,
+                category    TEXT, 
+                PRIMARY KEY (title, category)
+            )",
+            (),
+        )?;
+        Ok(())
     }
 
     fn create_titlemaps(&self) -> Result<()> {
@@ -150,32 +1153,118 @@ impl Database {
         )?;
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn insert_titlemaps(&self, categories: FieldMap<String>) -> Result<()> {
-        for (title, category) in categories.map {
-            self.insert_titlemap(title, category);
+    // A database from before any migration, when amounts were REAL.
+    fn legacy_database(rows: &[(&str, &str, f64, Kind)]) -> Database {
+        let database = Database {
+            conn: Connection::open_in_memory().unwrap(),
+        };
+        database.create_transactions().unwrap();
+        database.create_categories().unwrap();
+        database.create_titlemaps().unwrap();
+        for (date, title, amount, kind) in rows {
+            database
+                .conn
+                .execute(
+                    "INSERT INTO transactions (date, title, amount, kind) VALUES (?1, ?2, ?3, ?4)",
+                    (date, title, amount, kind),
+                )
+                .unwrap();
         }
-        Ok(())
+        database
     }
 
-    pub fn insert_titlemap(&self, title: String, category: String) {
-        self.conn
-            .execute(
-                "INSERT OR IGNORE INTO titlemaps (title, new_title) VALUES (?1, ?2)",
-                (&title, &category),
-            )
-            .ok();
+    #[test]
+    fn migration_rounds_real_amounts_to_cents() {
+        let database = legacy_database(&[
+            ("2024-01-05", "PADARIA", 0.29, Kind::DebitPurchase),
+            ("2024-01-06", "MERCADO", 1234.56, Kind::CreditPurchase),
+            ("2024-01-07", "SALARIO", 10.1, Kind::Income),
+            ("2024-01-08", "FARMACIA", 0.57, Kind::DebitPurchase),
+        ]);
+        database.migrate().unwrap();
+
+        let amounts: Vec<(String, Money)> = database
+            .get_transactions()
+            .unwrap()
+            .into_iter()
+            .map(|row| (row.statement_title, row.amount))
+            .sorted()
+            .collect();
+        let brl = |cents| Money::new(cents, Currency::BRL);
+        assert_eq!(
+            amounts,
+            vec![
+                ("FARMACIA".to_string(), brl(57)),
+                ("MERCADO".to_string(), brl(123456)),
+                ("PADARIA".to_string(), brl(29)),
+                ("SALARIO".to_string(), brl(1010)),
+            ]
+        );
+
+        let accounts = database.get_accounts().unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert!(
+            accounts
+                .iter()
+                .all(|account| account.opening_balance == brl(0))
+        );
     }
 
-    pub fn get_titlemaps(&self) -> Result<FieldMap<String>> {
-        let mut statement = self
-            .conn
-            .prepare("SELECT title, new_title FROM titlemaps")?;
+    #[test]
+    fn migration_runs_once() {
+        let database = legacy_database(&[("2024-01-05", "PADARIA", 12.34, Kind::DebitPurchase)]);
+        database.migrate().unwrap();
+        database.migrate().unwrap();
+        let rows = database.get_transactions().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].amount, Money::new(1234, Currency::BRL));
+    }
 
-        let map = statement
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
-            .collect::<Result<HashMap<String, String>, _>>()?;
+    #[test]
+    fn rule_pack_is_saved_whole_or_not_at_all() {
+        let database = Database::open(Path::new(":memory:")).unwrap();
+        let rules_before = database.get_rules().unwrap().len();
+        let sailing = CategoryInfo::new("Sailing".parse().unwrap());
+        let rule = Rule {
+            category: Some("Sailing".parse().unwrap()),
+            ..Rule::keyword("airbnb")
+        };
+        let has_sailing = |database: &Database| {
+            database
+                .get_category_list()
+                .unwrap()
+                .get(&sailing.category)
+                .is_some()
+        };
 
-        Ok(FieldMap { map })
+        database
+            .conn
+            .execute_batch(
+                "CREATE TRIGGER no_rules BEFORE INSERT ON rules BEGIN SELECT RAISE(ABORT, 'full'); END;",
+            )
+            .unwrap();
+        assert!(
+            database
+                .save_rule_pack(std::slice::from_ref(&sailing), std::slice::from_ref(&rule))
+                .is_err()
+        );
+        assert!(!has_sailing(&database));
+        assert_eq!(database.get_rules().unwrap().len(), rules_before);
+
+        database
+            .conn
+            .execute_batch("DROP TRIGGER no_rules")
+            .unwrap();
+        database
+            .save_rule_pack(std::slice::from_ref(&sailing), &[rule])
+            .unwrap();
+        assert!(has_sailing(&database));
+        assert_eq!(database.get_rules().unwrap().len(), rules_before + 1);
     }
 }
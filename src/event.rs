@@ -17,6 +17,12 @@ pub struct EventHandler {
     receiver: mpsc::UnboundedReceiver<AppEvent>,
 }
 
+impl Default for EventHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EventHandler {
     pub fn new() -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
@@ -0,0 +1,266 @@
+use chrono::NaiveDate;
+use color_eyre::eyre::{Result, eyre};
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::import::parse_date;
+use crate::models::{Currency, ExchangeRate, Split, Transaction};
+
+pub struct ExchangeRates {
+    rates: HashMap<(Currency, Currency), Vec<(NaiveDate, f64)>>,
+}
+
+impl ExchangeRates {
+    pub fn new(rates: &[ExchangeRate]) -> Self {
+        let mut by_pair: HashMap<(Currency, Currency), Vec<(NaiveDate, f64)>> = HashMap::new();
+        for rate in rates {
+            by_pair
+                .entry((rate.from, rate.to))
+                .or_default()
+                .push((rate.date, rate.rate));
+        }
+        for rates in by_pair.values_mut() {
+            rates.sort_by_key(|(date, _)| *date);
+        }
+        ExchangeRates { rates: by_pair }
+    }
+
+    // The latest rate published on or before the date, falling back to the earliest one for
+    // transactions older than the table. Rates quoted the other way around are inverted.
+    fn rate(&self, from: Currency, to: Currency, date: NaiveDate) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+
+        let find = |rates: &Vec<(NaiveDate, f64)>| {
+            rates
+                .iter()
+                .rev()
+                .find(|(rate_date, _)| *rate_date <= date)
+                .or(rates.first())
+                .map(|(_, rate)| *rate)
+        };
+        match self.rates.get(&(from, to)).and_then(find) {
+            Some(rate) => Some(rate),
+            None => self
+                .rates
+                .get(&(to, from))
+                .and_then(find)
+                .map(|rate| 1.0 / rate),
+        }
+    }
+
+    fn convert_transaction(
+        &self,
+        transaction: &Transaction,
+        currency: Currency,
+    ) -> Option<Transaction> {
+        let rate = self.rate(transaction.amount.currency, currency, transaction.date)?;
+        let amount = transaction.amount.convert(rate, currency)?;
+        let mut splits = transaction
+            .splits
+            .iter()
+            .map(|split| {
+                Some(Split {
+                    amount: split.amount.convert(rate, currency)?,
+                    ..split.clone()
+                })
+            })
+            .collect::<Option<Vec<Split>>>()?;
+        // Each amount is rounded on its own, so splits that took the whole amount could leave a
+        // cent over or short. The last split takes the difference and they still add up exactly.
+        if transaction
+            .remainder()
+            .is_some_and(|remainder| remainder.is_zero())
+            && let Some((last, others)) = splits.split_last_mut()
+        {
+            last.amount = others
+                .iter()
+                .try_fold(amount, |left, split| left.checked_sub(split.amount))?;
+        }
+        Some(Transaction {
+            amount,
+            splits,
+            ..transaction.clone()
+        })
+    }
+
+    // Returns the transactions that could be converted along with how many were left out.
+    pub fn convert_transactions(
+        &self,
+        transactions: &[Transaction],
+        currency: Currency,
+    ) -> (Vec<Transaction>, usize) {
+        let converted: Vec<Transaction> = transactions
+            .iter()
+            .filter_map(|transaction| self.convert_transaction(transaction, currency))
+            .collect();
+        let missing = transactions.len() - converted.len();
+        (converted, missing)
+    }
+}
+
+fn column<'a>(df: &'a DataFrame, name: &str) -> Result<&'a StringChunked> {
+    let column = df
+        .get_column_names()
+        .into_iter()
+        .find(|column| column.trim().eq_ignore_ascii_case(name))
+        .ok_or_else(|| eyre!("missing column {name}, expected date,from,to,rate"))?;
+    Ok(df.column(column)?.str()?)
+}
+
+fn currency(value: Option<&str>, row: usize) -> Result<Currency> {
+    let value = value.unwrap_or_default();
+    value
+        .parse()
+        .map_err(|_| eyre!("line {row}: invalid currency {value:?}"))
+}
+
+pub fn read_rates(path: &Path) -> Result<Vec<ExchangeRate>> {
+    let bytes = fs::read(path).map_err(|e| eyre!("{}: {e}", path.display()))?;
+    let df = CsvReadOptions::default()
+        .with_has_header(true)
+        .with_infer_schema_length(Some(0))
+        .into_reader_with_file_handle(Cursor::new(bytes))
+        .finish()?;
+
+    let dates = column(&df, "date")?;
+    let from = column(&df, "from")?;
+    let to = column(&df, "to")?;
+    let rates = column(&df, "rate")?;
+
+    let mut exchange_rates = Vec::with_capacity(df.height());
+    for index in 0..df.height() {
+        let row = index + 2;
+        let date = dates.get(index).unwrap_or_default();
+        let rate = rates.get(index).unwrap_or_default();
+        let exchange_rate = ExchangeRate {
+            date: parse_date(date).ok_or_else(|| eyre!("line {row}: invalid date {date:?}"))?,
+            from: currency(from.get(index), row)?,
+            to: currency(to.get(index), row)?,
+            rate: rate
+                .trim()
+                .parse()
+                .ok()
+                .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                .ok_or_else(|| eyre!("line {row}: invalid rate {rate:?}"))?,
+        };
+        exchange_rates.push(exchange_rate);
+    }
+    Ok(exchange_rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Category, Kind, Money};
+
+    fn currency(code: &str) -> Currency {
+        code.parse().unwrap()
+    }
+
+    fn rate(date: &str, from: &str, to: &str, rate: f64) -> ExchangeRate {
+        ExchangeRate {
+            date: date.parse().unwrap(),
+            from: currency(from),
+            to: currency(to),
+            rate,
+        }
+    }
+
+    fn in_dollars(date: &str, cents: i64) -> Transaction {
+        Transaction {
+            amount: Money::new(cents, currency("USD")),
+            ..Transaction::sample(1, date, "STEAM", cents, Kind::CreditPurchase)
+        }
+    }
+
+    fn to_reais(rates: &ExchangeRates, transaction: &Transaction) -> Option<Money> {
+        rates
+            .convert_transaction(transaction, Currency::BRL)
+            .map(|row| row.amount)
+    }
+
+    #[test]
+    fn latest_rate_on_or_before_the_date() {
+        let rates = ExchangeRates::new(&[
+            rate("2024-02-01", "USD", "BRL", 5.5),
+            rate("2024-01-01", "USD", "BRL", 5.0),
+        ]);
+        let brl = |cents| Some(Money::new(cents, Currency::BRL));
+        assert_eq!(to_reais(&rates, &in_dollars("2024-01-31", 1000)), brl(5000));
+        assert_eq!(to_reais(&rates, &in_dollars("2024-02-01", 1000)), brl(5500));
+        assert_eq!(to_reais(&rates, &in_dollars("2024-03-15", 1000)), brl(5500));
+        // Older than the table, the earliest rate is used.
+        assert_eq!(to_reais(&rates, &in_dollars("2023-12-01", 1000)), brl(5000));
+    }
+
+    #[test]
+    fn rate_quoted_the_other_way_is_inverted() {
+        let rates = ExchangeRates::new(&[rate("2024-01-01", "BRL", "USD", 0.2)]);
+        assert_eq!(
+            to_reais(&rates, &in_dollars("2024-01-10", 1000)),
+            Some(Money::new(5000, Currency::BRL))
+        );
+    }
+
+    #[test]
+    fn transactions_without_a_rate_are_counted() {
+        let rates = ExchangeRates::new(&[rate("2024-01-01", "EUR", "BRL", 6.0)]);
+        let transactions = [
+            Transaction::sample(1, "2024-01-10", "PADARIA", 1000, Kind::CreditPurchase),
+            in_dollars("2024-01-11", 1000),
+        ];
+        let (converted, missing) = rates.convert_transactions(&transactions, Currency::BRL);
+        assert_eq!(missing, 1);
+        assert_eq!(converted.len(), 1);
+        assert_eq!(converted[0].amount, Money::new(1000, Currency::BRL));
+    }
+
+    #[test]
+    fn converted_splits_still_add_up_to_the_amount() {
+        let rates = ExchangeRates::new(&[rate("2024-01-01", "USD", "BRL", 1.5)]);
+        let split = |cents, category: &str| Split {
+            amount: Money::new(cents, currency("USD")),
+            category: Category::from(category),
+            note: String::new(),
+        };
+        let transaction = Transaction {
+            splits: vec![split(1, "Food"), split(1, "Health"), split(1, "Home")],
+            ..in_dollars("2024-01-10", 3)
+        };
+        assert!(transaction.remainder().unwrap().is_zero());
+
+        let converted = rates
+            .convert_transaction(&transaction, Currency::BRL)
+            .unwrap();
+        assert!(converted.remainder().unwrap().is_zero());
+        assert_eq!(converted.parts().len(), 3);
+    }
+
+    #[test]
+    fn split_remainder_takes_the_rounding() {
+        let rates = ExchangeRates::new(&[rate("2024-01-01", "USD", "BRL", 1.5)]);
+        let transaction = Transaction {
+            splits: vec![Split {
+                amount: Money::new(1, currency("USD")),
+                category: Category::from("Food"),
+                note: String::new(),
+            }],
+            ..in_dollars("2024-01-10", 3)
+        };
+        let converted = rates
+            .convert_transaction(&transaction, Currency::BRL)
+            .unwrap();
+        let total = converted
+            .parts()
+            .into_iter()
+            .try_fold(Money::zero(Currency::BRL), |total, (_, amount)| {
+                total.checked_add(amount)
+            });
+        assert_eq!(total, Some(converted.amount));
+    }
+}
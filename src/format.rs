@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod format;
 pub use format::Formatter;
 
@@ -6,3 +7,12 @@ pub use credit::CreditFormatter;
 
 mod debit;
 pub use debit::DebitFormatter;
+
+mod ofx;
+pub use ofx::OfxFormatter;
+
+mod profile;
+pub use profile::{Profile, ProfileFormatter, SignConvention};
+
+mod importer;
+pub use importer::{Importer, ImporterRegistry, Statement};
@@ -1,5 +1,12 @@
 use polars::prelude::*;
 
+use crate::format::importer::{
+    FIRST_CSV_LINE, Importer, Statement, header_matches, is_date, is_numeric,
+};
+
+const HEADER: [&str; 3] = ["date", "title", "amount"];
+
+#[derive(Default)]
 pub struct CreditFormatter {
     df: DataFrame,
 }
@@ -9,7 +16,7 @@ impl CreditFormatter {
         CreditFormatter { df }
     }
 
-    fn select_columns(mut self) -> Self {
+    fn select_columns(mut self) -> PolarsResult<Self> {
         let kind = when(col("amount").lt(0))
             .then(lit("CreditBillPayment"))
             .otherwise(lit("CreditPurchase"));
@@ -17,17 +24,47 @@ impl CreditFormatter {
         self.df = self
             .df
             .lazy()
+            .with_column(col("amount").cast(DataType::Float64))
             .with_columns([kind.alias("kind"), col("amount").abs()])
-            .collect()
-            .expect("DataFrame select error");
-        self
+            .collect()?;
+        Ok(self)
     }
 
     pub fn format(self) -> PolarsResult<DataFrame> {
-        self.select_columns().build()
+        self.select_columns()?.build()
     }
 
     pub fn build(self) -> PolarsResult<DataFrame> {
         Ok(self.df)
     }
 }
+
+impl Importer for CreditFormatter {
+    fn name(&self) -> &str {
+        "credit"
+    }
+
+    fn detect(&self, statement: &Statement) -> u32 {
+        let Some(sample) = statement.sample_csv() else {
+            return 0;
+        };
+        if header_matches(&sample, &HEADER) < HEADER.len() || !is_numeric(&sample, "amount") {
+            return 0;
+        }
+
+        let mut score = 10 * HEADER.len() as u32;
+        if sample.width() == HEADER.len() {
+            score += 5;
+        }
+        if is_date(&sample, "date") {
+            score += 5;
+        }
+        score
+    }
+
+    fn import(&self, statement: &Statement) -> PolarsResult<DataFrame> {
+        CreditFormatter::new(statement.read_csv()?)
+            .format()?
+            .with_row_index("line", Some(FIRST_CSV_LINE))
+    }
+}
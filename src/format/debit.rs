@@ -1,5 +1,12 @@
 use polars::prelude::*;
 
+use crate::format::importer::{
+    FIRST_CSV_LINE, Importer, Statement, header_matches, is_date, is_numeric,
+};
+
+const HEADER: [&str; 4] = ["data", "valor", "identificador", "descrição"];
+
+#[derive(Default)]
 pub struct DebitFormatter {
     df: DataFrame,
 }
@@ -9,14 +16,13 @@ impl DebitFormatter {
         DebitFormatter { df }
     }
 
-    fn rename_columns(mut self) -> Self {
+    fn rename_columns(mut self) -> PolarsResult<Self> {
         self.df
-            .set_column_names(&["date", "amount", "index", "description"])
-            .unwrap();
-        self
+            .set_column_names(&["date", "amount", "index", "description"])?;
+        Ok(self)
     }
 
-    fn add_aux_columns(mut self) -> Self {
+    fn add_aux_columns(mut self) -> PolarsResult<Self> {
         let split_name_series = col("description").str().split(lit("-")).alias("split");
         let length_series = col("description")
             .str()
@@ -28,30 +34,23 @@ impl DebitFormatter {
             .df
             .lazy()
             .with_columns(vec![split_name_series, length_series])
-            .collect()
-            .expect("DataFrame error");
-
-        self
+            .collect()?;
+        Ok(self)
     }
 
-    fn add_short_description(mut self) -> Self {
-        self = self.add_aux_columns();
+    fn add_short_description(mut self) -> PolarsResult<Self> {
+        self = self.add_aux_columns()?;
 
         let short_description = when(col("length").gt_eq(2))
             .then(col("split").list().get(1.into(), true))
             .otherwise(col("split").list().get(2.into(), true))
             .alias("description");
 
-        self.df = self
-            .df
-            .lazy()
-            .with_column(short_description)
-            .collect()
-            .expect("DataFrame new columns error");
-        self
+        self.df = self.df.lazy().with_column(short_description).collect()?;
+        Ok(self)
     }
 
-    fn add_kind(mut self) -> Self {
+    fn add_kind(mut self) -> PolarsResult<Self> {
         let kind = when(col("amount").gt(0))
             .then(lit("Income"))
             .otherwise(lit("DebitPurchase"))
@@ -60,13 +59,13 @@ impl DebitFormatter {
         self.df = self
             .df
             .lazy()
+            .with_column(col("amount").cast(DataType::Float64))
             .with_columns([kind, col("amount").abs()])
-            .collect()
-            .expect("DataFrame new columns error");
-        self
+            .collect()?;
+        Ok(self)
     }
 
-    fn select_columns(mut self) -> Self {
+    fn select_columns(mut self) -> PolarsResult<Self> {
         self.df = self
             .df
             .lazy()
@@ -76,16 +75,16 @@ impl DebitFormatter {
                 col("amount"),
                 col("kind"),
             ])
-            .collect()
-            .expect("DataFrame select error");
-        self
+            .collect()?;
+        Ok(self)
     }
 
+    // A malformed statement fails here with an error instead of a panic.
     pub fn format(self) -> PolarsResult<DataFrame> {
-        self.rename_columns()
-            .add_short_description()
-            .add_kind()
-            .select_columns()
+        self.rename_columns()?
+            .add_short_description()?
+            .add_kind()?
+            .select_columns()?
             .build()
     }
 
@@ -93,3 +92,42 @@ impl DebitFormatter {
         Ok(self.df)
     }
 }
+
+impl Importer for DebitFormatter {
+    fn name(&self) -> &str {
+        "debit"
+    }
+
+    fn detect(&self, statement: &Statement) -> u32 {
+        let Some(sample) = statement.sample_csv() else {
+            return 0;
+        };
+        if sample.width() != HEADER.len()
+            || header_matches(&sample, &HEADER) < HEADER.len()
+            || !is_numeric(&sample, sample.get_column_names()[1])
+        {
+            return 0;
+        }
+
+        let mut score = 10 * HEADER.len() as u32;
+        if is_date(&sample, sample.get_column_names()[0]) {
+            score += 5;
+        }
+        let has_short_description = sample
+            .column(sample.get_column_names()[3])
+            .ok()
+            .and_then(|series| series.str().ok())
+            .map(|descriptions| descriptions.into_iter().flatten().any(|d| d.contains('-')))
+            .unwrap_or(false);
+        if has_short_description {
+            score += 5;
+        }
+        score
+    }
+
+    fn import(&self, statement: &Statement) -> PolarsResult<DataFrame> {
+        DebitFormatter::new(statement.read_csv()?)
+            .format()?
+            .with_row_index("line", Some(FIRST_CSV_LINE))
+    }
+}
@@ -1,5 +1,5 @@
-use crate::format::{CreditFormatter, DebitFormatter};
-use crate::models::CsvType;
+use crate::format::{ImporterRegistry, Statement};
+use color_eyre::eyre::{Result, eyre};
 use polars::prelude::*;
 
 #[derive(Clone)]
@@ -16,41 +16,64 @@ impl Formatter {
         }
     }
 
-    fn check_kind(&self, df: &DataFrame) -> Option<CsvType> {
-        match df.shape().1 {
-            3 => Some(CsvType::Credit),
-            4 => Some(CsvType::Debit),
-            _ => None,
-        }
+    pub fn add(&mut self, registry: &ImporterRegistry, statement: &Statement) -> Result<String> {
+        let importer = registry.detect(statement)?;
+        let df = importer
+            .import(statement)
+            .and_then(Formatter::normalize)
+            .map_err(|e| eyre!("{}: {e}", statement.path.display()))?;
+        self.concat(df)?;
+        Ok(importer.name().to_string())
     }
 
-    pub fn add(&mut self, df: DataFrame) {
-        match self.check_kind(&df) {
-            Some(CsvType::Credit) => {
-                self.concat(CreditFormatter::new(df).format().expect("Concat error"))
-            }
-            Some(CsvType::Debit) => {
-                self.concat(DebitFormatter::new(df).format().expect("Concat error"))
-            }
-            None => {}
+    fn normalize(df: DataFrame) -> PolarsResult<DataFrame> {
+        let columns: Vec<String> = df
+            .get_column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let optional = |name: &str| match columns.iter().any(|column| column == name) {
+            true => col(name).cast(DataType::String),
+            false => lit(NULL).cast(DataType::String).alias(name),
         };
+
+        df.lazy()
+            .select([
+                col("date"),
+                col("title"),
+                col("amount"),
+                col("kind"),
+                optional("fitid"),
+                // Statements that do not say which currency they are in, such as the built-in
+                // CSV ones, are taken to be in the account's.
+                optional("currency"),
+                optional("original_amount"),
+                optional("original_currency"),
+                match columns.iter().any(|column| column == "line") {
+                    true => col("line").cast(DataType::Int64),
+                    false => lit(NULL).cast(DataType::Int64).alias("line"),
+                },
+            ])
+            .collect()
     }
 
-    fn concat(&mut self, df: DataFrame) {
+    fn concat(&mut self, df: DataFrame) -> PolarsResult<()> {
         if self.empty {
             self.df = df.lazy();
             self.empty = false;
         } else {
-            self.df =
-                concat([df.lazy(), self.df.clone()], UnionArgs::default()).expect("Concat error")
+            self.df = concat([df.lazy(), self.df.clone()], UnionArgs::default())?;
         }
+        Ok(())
     }
 
     pub fn build(self) -> PolarsResult<DataFrame> {
-        Ok(self
-            .df
+        if self.empty {
+            return Ok(DataFrame::default());
+        }
+
+        self.df
             .with_columns([col("title").str().strip_chars(lit(" "))])
-            .collect()?
-            .drop_nulls::<String>(None)?)
+            .collect()
     }
 }
@@ -0,0 +1,231 @@
+use color_eyre::eyre::{Result, eyre};
+use polars::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::format::{CreditFormatter, DebitFormatter, OfxFormatter, Profile, ProfileFormatter};
+
+pub const SAMPLE_ROWS: usize = 20;
+// The header takes the first line of a CSV statement, so rows start on the second.
+pub const FIRST_CSV_LINE: IdxSize = 2;
+
+pub struct Statement {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+}
+
+impl Statement {
+    pub fn open(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| eyre!("{}: {e}", path.display()))?;
+        Ok(Statement {
+            path: path.to_path_buf(),
+            bytes,
+        })
+    }
+
+    pub fn content_hash(&self) -> String {
+        format!("{:x}", Sha256::digest(&self.bytes))
+    }
+
+    pub fn text(&self) -> String {
+        match String::from_utf8(self.bytes.clone()) {
+            Ok(text) => text,
+            Err(_) => self.bytes.iter().map(|&byte| byte as char).collect(),
+        }
+    }
+
+    fn csv_reader(&self, n_rows: Option<usize>) -> CsvReader<Cursor<&[u8]>> {
+        CsvReadOptions::default()
+            .with_has_header(true)
+            .with_n_rows(n_rows)
+            .map_parse_options(|parse_options| parse_options.with_try_parse_dates(true))
+            .into_reader_with_file_handle(Cursor::new(self.bytes.as_slice()))
+    }
+
+    pub fn read_csv(&self) -> PolarsResult<DataFrame> {
+        self.csv_reader(None).finish()
+    }
+
+    pub fn sample_csv(&self) -> Option<DataFrame> {
+        self.csv_reader(Some(SAMPLE_ROWS)).finish().ok()
+    }
+}
+
+pub trait Importer {
+    fn name(&self) -> &str;
+
+    /// Confidence that this importer understands the statement, 0 meaning it does not.
+    fn detect(&self, statement: &Statement) -> u32;
+
+    /// Converts the statement into the `date/title/amount/kind` frame `convert_df` expects,
+    /// with a `line` column telling where in the file each row is when the format knows.
+    fn import(&self, statement: &Statement) -> PolarsResult<DataFrame>;
+}
+
+pub fn header_matches(df: &DataFrame, expected: &[&str]) -> usize {
+    let names: Vec<String> = df
+        .get_column_names()
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+
+    expected
+        .iter()
+        .filter(|name| names.contains(&name.to_lowercase()))
+        .count()
+}
+
+// A few malformed amounts turn the whole column into strings, so a column still counts as
+// numeric when most of its values parse. The preview rejects the rest row by row.
+pub fn is_numeric(df: &DataFrame, column: &str) -> bool {
+    let Ok(series) = df.column(column) else {
+        return false;
+    };
+    if series.dtype().is_numeric() {
+        return true;
+    }
+    let Ok(values) = series.str() else {
+        return false;
+    };
+
+    let values: Vec<&str> = values.into_iter().flatten().collect();
+    let parsed = values
+        .iter()
+        .filter(|value| value.trim().parse::<f64>().is_ok())
+        .count();
+    parsed > 0 && 2 * parsed >= values.len()
+}
+
+pub fn is_date(df: &DataFrame, column: &str) -> bool {
+    df.column(column)
+        .map(|series| series.dtype() == &DataType::Date)
+        .unwrap_or(false)
+}
+
+pub struct ImporterRegistry {
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl ImporterRegistry {
+    pub fn new() -> Self {
+        let mut registry = ImporterRegistry {
+            importers: Vec::new(),
+        };
+        registry.register(Box::new(CreditFormatter::default()));
+        registry.register(Box::new(DebitFormatter::default()));
+        registry.register(Box::new(OfxFormatter));
+        registry
+    }
+
+    pub fn with_profiles(profiles: &[Profile]) -> Result<Self> {
+        let mut registry = ImporterRegistry::new();
+        for profile in profiles {
+            registry.register(Box::new(ProfileFormatter::new(profile.clone())?));
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, importer: Box<dyn Importer>) {
+        self.importers.push(importer);
+    }
+
+    pub fn detect(&self, statement: &Statement) -> Result<&dyn Importer> {
+        self.importers
+            .iter()
+            .map(|importer| (importer.detect(statement), importer))
+            .filter(|(score, _)| *score > 0)
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, importer)| importer.as_ref())
+            .ok_or_else(|| {
+                let columns = match statement.sample_csv() {
+                    Some(df) => df.get_column_names().join(", "),
+                    None => "unreadable".to_string(),
+                };
+                eyre!(
+                    "{}: no importer recognizes this statement (columns: {columns})",
+                    statement.path.display()
+                )
+            })
+    }
+}
+
+impl Default for ImporterRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(&'static str, u32);
+
+    impl Importer for Fixed {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn detect(&self, _: &Statement) -> u32 {
+            self.1
+        }
+
+        fn import(&self, _: &Statement) -> PolarsResult<DataFrame> {
+            Ok(DataFrame::default())
+        }
+    }
+
+    fn statement(text: &str) -> Statement {
+        Statement {
+            path: PathBuf::from("statement.csv"),
+            bytes: text.as_bytes().to_vec(),
+        }
+    }
+
+    fn detected(registry: &ImporterRegistry, text: &str) -> Result<String> {
+        registry
+            .detect(&statement(text))
+            .map(|importer| importer.name().to_string())
+    }
+
+    #[test]
+    fn highest_score_wins() {
+        let mut registry = ImporterRegistry {
+            importers: Vec::new(),
+        };
+        registry.register(Box::new(Fixed("none", 0)));
+        registry.register(Box::new(Fixed("strong", 50)));
+        registry.register(Box::new(Fixed("weak", 10)));
+        assert_eq!(detected(&registry, "a,b\n1,2\n").unwrap(), "strong");
+    }
+
+    #[test]
+    fn built_in_importers_tell_statements_apart() {
+        let registry = ImporterRegistry::new();
+        let credit = "date,title,amount\n2024-01-05,PADARIA,12.50\n2024-01-06,MERCADO,80\n";
+        let debit = "Data,Valor,Identificador,Descrição\n\
+                     05/01/2024,-12.50,abc,Compra no débito - PADARIA\n";
+        let ofx = "OFXHEADER:100\n<OFX><STMTTRN><TRNAMT>-1.00</STMTTRN></OFX>\n";
+        assert_eq!(detected(&registry, credit).unwrap(), "credit");
+        assert_eq!(detected(&registry, debit).unwrap(), "debit");
+        assert_eq!(detected(&registry, ofx).unwrap(), "ofx");
+
+        let mut registry = ImporterRegistry::new();
+        registry.register(Box::new(Fixed("bank", 100)));
+        assert_eq!(detected(&registry, credit).unwrap(), "bank");
+    }
+
+    #[test]
+    fn unrecognized_statement_is_an_error() {
+        let registry = ImporterRegistry::new();
+        let error = detected(&registry, "when,what,how much\n2024-01-05,PADARIA,12.50\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "statement.csv: no importer recognizes this statement (columns: when, what, how much)"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use chrono::NaiveDate;
+use polars::prelude::*;
+
+use crate::format::importer::{Importer, Statement};
+use crate::models::{Currency, Kind, Money};
+
+const INCOME_TYPES: [&str; 5] = ["CREDIT", "DEP", "DIRECTDEP", "INT", "DIV"];
+const PURCHASE_TYPES: [&str; 10] = [
+    "DEBIT",
+    "POS",
+    "ATM",
+    "CHECK",
+    "PAYMENT",
+    "FEE",
+    "SRVCHG",
+    "DIRECTDEBIT",
+    "REPEATPMT",
+    "CASH",
+];
+
+#[derive(Default)]
+pub struct OfxFormatter;
+
+struct OfxTransaction {
+    line: u32,
+    date: Option<NaiveDate>,
+    title: String,
+    amount: Option<Money>,
+    kind: Option<Kind>,
+    fitid: Option<String>,
+    original: Option<Money>,
+}
+
+// Each block comes with the line its opening tag is on.
+fn blocks<'a>(text: &'a str, upper: &str, tag: &str) -> Vec<(u32, &'a str)> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = upper[offset..].find(&open) {
+        let start = offset + start + open.len();
+        let end = match upper[start..].find(&close) {
+            Some(end) => start + end,
+            None => upper.len(),
+        };
+        let line = text[..start].matches('\n').count() as u32 + 1;
+        blocks.push((line, &text[start..end]));
+        offset = end;
+    }
+    blocks
+}
+
+// Values escape markup characters as entities, such as &amp; in "C&amp;A". An unknown entity
+// is kept as written.
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .map(|end| &rest[1..end])
+            .and_then(|entity| match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|code| code.parse().ok()),
+                }
+                .and_then(char::from_u32),
+            });
+        match entity {
+            Some(c) => {
+                text.push(c);
+                rest = &rest[rest.find(';').unwrap_or(0) + 1..];
+            }
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+// OFX 1.x is SGML and leaves leaf elements unclosed, so a value runs until the next tag.
+fn field(block: &str, tag: &str) -> Option<String> {
+    let open = format!("<{tag}>");
+    let start = block.to_ascii_uppercase().find(&open)? + open.len();
+    let value = block[start..].split('<').next()?.trim();
+    match value.is_empty() {
+        true => None,
+        false => Some(unescape(value)),
+    }
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+// Banks write amounts as 1234.56, 1234,56, 1.234,56 or 1,234.56. The last separator marks the
+// decimals, unless it is repeated as in 1.234.567, and the others group thousands.
+fn decimal(value: &str) -> String {
+    let value = value.trim();
+    let digits = |part: &str| part.replace(['.', ','], "");
+    match value.rfind(['.', ',']) {
+        Some(index) if value.matches(&value[index..index + 1]).count() == 1 => {
+            format!("{}.{}", digits(&value[..index]), &value[index + 1..])
+        }
+        _ => digits(value),
+    }
+}
+
+fn parse_amount(value: &str, currency: Currency) -> Option<Money> {
+    Money::parse(&decimal(value), currency)
+}
+
+// A <CURRENCY> or <ORIGCURRENCY> aggregate inside a transaction: the currency it names and how
+// many units of the statement currency one unit of it is worth.
+fn foreign_currency(block: &str, tag: &str) -> Option<(Currency, Option<f64>)> {
+    let upper = block.to_ascii_uppercase();
+    let aggregate = blocks(block, &upper, tag).into_iter().next()?.1;
+    let currency = field(aggregate, "CURSYM")?.parse().ok()?;
+    let rate = field(aggregate, "CURRATE")
+        .and_then(|rate| decimal(&rate).parse::<f64>().ok())
+        .filter(|rate| *rate > 0.0);
+    Some((currency, rate))
+}
+
+// Amounts are in the statement currency, unless <CURRENCY> says the transaction was made in
+// another one and gives the rate to convert it. <ORIGCURRENCY> keeps the amount in the statement
+// currency and tells what it was converted from. Either way the foreign amount is kept as the
+// original one.
+fn amounts(block: &str, currency: Currency) -> (Option<Money>, Option<Money>) {
+    let Some(value) = field(block, "TRNAMT") else {
+        return (None, None);
+    };
+    if let Some((foreign, rate)) = foreign_currency(block, "CURRENCY") {
+        let original = parse_amount(&value, foreign);
+        let amount = original
+            .zip(rate)
+            .and_then(|(original, rate)| original.convert(rate, currency));
+        return (amount, original.filter(|_| foreign != currency));
+    }
+    let amount = parse_amount(&value, currency);
+    let original = foreign_currency(block, "ORIGCURRENCY").and_then(|(foreign, rate)| {
+        amount
+            .zip(rate)
+            .and_then(|(amount, rate)| amount.convert(1.0 / rate, foreign))
+            .filter(|_| foreign != currency)
+    });
+    (amount, original)
+}
+
+fn kind(transaction_type: &str, amount: Money, credit_card: bool) -> Kind {
+    if credit_card {
+        return match amount.is_negative() {
+            true => Kind::CreditPurchase,
+            false => Kind::CreditBillPayment,
+        };
+    }
+
+    if INCOME_TYPES.contains(&transaction_type) {
+        Kind::Income
+    } else if PURCHASE_TYPES.contains(&transaction_type) || amount.is_negative() {
+        Kind::DebitPurchase
+    } else {
+        Kind::Income
+    }
+}
+
+impl OfxFormatter {
+    // The currency the statement's amounts are in.
+    fn currency(text: &str) -> Option<Currency> {
+        field(text, "CURDEF").and_then(|currency| currency.parse().ok())
+    }
+
+    fn parse(text: &str) -> Vec<OfxTransaction> {
+        let upper = text.to_ascii_uppercase();
+        let credit_card = upper.contains("<CCSTMTRS>");
+        let currency = OfxFormatter::currency(text);
+
+        blocks(text, &upper, "STMTTRN")
+            .into_iter()
+            .map(|(line, block)| {
+                let transaction_type = field(block, "TRNTYPE").unwrap_or_default();
+                let date = field(block, "DTPOSTED").as_deref().and_then(parse_date);
+                let (amount, original) = amounts(block, currency.unwrap_or_default());
+                let title = field(block, "NAME")
+                    .or_else(|| field(block, "MEMO"))
+                    .unwrap_or_else(|| transaction_type.clone());
+
+                OfxTransaction {
+                    line,
+                    date,
+                    title,
+                    amount: amount.map(Money::abs),
+                    kind: amount.map(|amount| {
+                        kind(&transaction_type.to_ascii_uppercase(), amount, credit_card)
+                    }),
+                    fitid: field(block, "FITID"),
+                    original: original.map(Money::abs),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Importer for OfxFormatter {
+    fn name(&self) -> &str {
+        "ofx"
+    }
+
+    fn detect(&self, statement: &Statement) -> u32 {
+        let text = statement.text();
+        let header = text
+            .get(..text.len().min(512))
+            .unwrap_or(&text)
+            .to_ascii_uppercase();
+        if header.contains("OFXHEADER") || header.contains("<?OFX") || header.contains("<OFX>") {
+            100
+        } else {
+            0
+        }
+    }
+
+    fn import(&self, statement: &Statement) -> PolarsResult<DataFrame> {
+        let text = statement.text();
+        let transactions = OfxFormatter::parse(&text);
+        let currency = OfxFormatter::currency(&text).map(|currency| currency.to_string());
+
+        df!(
+            "line" => transactions.iter().map(|row| row.line).collect::<Vec<u32>>(),
+            "date" => transactions.iter().map(|row| row.date).collect::<Vec<Option<NaiveDate>>>(),
+            "title" => transactions.iter().map(|row| row.title.clone()).collect::<Vec<String>>(),
+            "amount" => transactions.iter().map(|row| row.amount.map(|amount| amount.to_string())).collect::<Vec<Option<String>>>(),
+            "kind" => transactions.iter().map(|row| row.kind.as_ref().map(Kind::to_string)).collect::<Vec<Option<String>>>(),
+            "fitid" => transactions.iter().map(|row| row.fitid.clone()).collect::<Vec<Option<String>>>(),
+            "currency" => vec![currency; transactions.len()],
+            "original_amount" => transactions.iter().map(|row| row.original.map(|original| original.to_string())).collect::<Vec<Option<String>>>(),
+            "original_currency" => transactions.iter().map(|row| row.original.map(|original| original.currency.to_string())).collect::<Vec<Option<String>>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+
+<OFX>
+<BANKMSGSRSV1><STMTTRNRS><STMTRS>
+<CURDEF>BRL
+<BANKTRANLIST>
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20240105120000[-3:BRT]
+<TRNAMT>-12,34
+<FITID>A1
+<MEMO>PADARIA
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>CREDIT
+<DTPOSTED>20240106
+<TRNAMT>1500.00
+<FITID>A2
+<NAME>SALARIO
+</STMTTRN>
+</BANKTRANLIST>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+    const XML: &str = r#"<?xml version="1.0"?>
+<?OFX OFXHEADER="200" VERSION="220"?>
+<OFX>
+  <CREDITCARDMSGSRSV1><CCSTMTTRNRS><CCSTMTRS>
+    <CURDEF>USD</CURDEF>
+    <BANKTRANLIST>
+      <STMTTRN>
+        <TRNTYPE>DEBIT</TRNTYPE>
+        <DTPOSTED>20240110</DTPOSTED>
+        <TRNAMT>-110.00</TRNAMT>
+        <FITID>C1</FITID>
+        <NAME>HOTEL</NAME>
+        <ORIGCURRENCY><CURRATE>1.1</CURRATE><CURSYM>EUR</CURSYM></ORIGCURRENCY>
+      </STMTTRN>
+      <STMTTRN>
+        <TRNTYPE>CREDIT</TRNTYPE>
+        <DTPOSTED>20240120</DTPOSTED>
+        <TRNAMT>200.00</TRNAMT>
+        <FITID>C2</FITID>
+        <NAME>PAYMENT THANK YOU</NAME>
+      </STMTTRN>
+    </BANKTRANLIST>
+  </CCSTMTRS></CCSTMTTRNRS></CREDITCARDMSGSRSV1>
+</OFX>
+"#;
+
+    fn money(value: &str, currency: &str) -> Option<Money> {
+        Money::parse(value, currency.parse().unwrap())
+    }
+
+    #[test]
+    fn sgml_leaves_values_unclosed() {
+        let rows = OfxFormatter::parse(SGML);
+        assert_eq!(rows.len(), 2);
+
+        assert_eq!(rows[0].line, 8);
+        assert_eq!(rows[0].date, NaiveDate::from_ymd_opt(2024, 1, 5));
+        assert_eq!(rows[0].title, "PADARIA");
+        assert_eq!(rows[0].amount, money("12.34", "BRL"));
+        assert_eq!(rows[0].kind, Some(Kind::DebitPurchase));
+        assert_eq!(rows[0].fitid.as_deref(), Some("A1"));
+
+        assert_eq!(rows[1].line, 15);
+        assert_eq!(rows[1].title, "SALARIO");
+        assert_eq!(rows[1].amount, money("1500", "BRL"));
+        assert_eq!(rows[1].kind, Some(Kind::Income));
+        assert_eq!(rows[1].original, None);
+    }
+
+    #[test]
+    fn xml_closes_every_value() {
+        let rows = OfxFormatter::parse(XML);
+        assert_eq!(OfxFormatter::currency(XML), "USD".parse().ok());
+        assert_eq!(rows.len(), 2);
+
+        assert_eq!(rows[0].title, "HOTEL");
+        assert_eq!(rows[0].amount, money("110", "USD"));
+        assert_eq!(rows[0].kind, Some(Kind::CreditPurchase));
+        assert_eq!(rows[0].original, money("100", "EUR"));
+
+        assert_eq!(rows[1].fitid.as_deref(), Some("C2"));
+        assert_eq!(rows[1].kind, Some(Kind::CreditBillPayment));
+    }
+
+    #[test]
+    fn currency_aggregate_converts_to_the_statement_currency() {
+        let block = "<TRNAMT>-50.00<CURRENCY><CURRATE>5,5<CURSYM>USD</CURRENCY>";
+        let (amount, original) = amounts(block, Currency::BRL);
+        assert_eq!(amount, money("-275", "BRL"));
+        assert_eq!(original, money("-50", "USD"));
+    }
+
+    #[test]
+    fn comma_decimals() {
+        assert_eq!(
+            parse_amount("-1234,5", Currency::BRL),
+            money("-1234.50", "BRL")
+        );
+        assert_eq!(parse_amount("0,05", Currency::BRL), money("0.05", "BRL"));
+    }
+
+    #[test]
+    fn thousands_separators() {
+        assert_eq!(
+            parse_amount("-1.234,56", Currency::BRL),
+            money("-1234.56", "BRL")
+        );
+        assert_eq!(
+            parse_amount("1,234.50", Currency::BRL),
+            money("1234.50", "BRL")
+        );
+        assert_eq!(
+            parse_amount("1.234.567", Currency::BRL),
+            money("1234567", "BRL")
+        );
+        assert_eq!(
+            parse_amount("1,234,567.8", Currency::BRL),
+            money("1234567.80", "BRL")
+        );
+    }
+
+    #[test]
+    fn entities_are_unescaped() {
+        let rows = OfxFormatter::parse(
+            "<OFX><STMTTRN><TRNTYPE>POS<TRNAMT>-89,90\
+             <NAME>C&amp;A MODAS &lt;SP&gt;<MEMO>x</STMTTRN>\
+             <STMTTRN><TRNAMT>-1.00<MEMO>CAF&#201; &#x26; P&Atilde;O &amp</STMTTRN></OFX>",
+        );
+        assert_eq!(rows[0].title, "C&A MODAS <SP>");
+        assert_eq!(rows[0].amount, money("89.90", "BRL"));
+        assert_eq!(rows[1].title, "CAFÉ & P&Atilde;O &amp");
+    }
+
+    #[test]
+    fn title_falls_back_to_the_type() {
+        let rows = OfxFormatter::parse("<OFX><STMTTRN><TRNTYPE>FEE<TRNAMT>-1.00</STMTTRN></OFX>");
+        assert_eq!(rows[0].title, "FEE");
+        assert_eq!(rows[0].date, None);
+        assert_eq!(rows[0].amount, money("1", "BRL"));
+    }
+}
@@ -0,0 +1,472 @@
+use chrono::NaiveDate;
+use color_eyre::eyre::{Result, bail, eyre};
+use encoding_rs::Encoding;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str::FromStr;
+
+use crate::format::importer::{FIRST_CSV_LINE, Importer, SAMPLE_ROWS, Statement};
+use crate::models::{Currency, Kind, Money};
+
+const PROFILE_BONUS: u32 = 50;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignConvention {
+    #[default]
+    NegativeIsExpense,
+    PositiveIsExpense,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+fn default_encoding() -> String {
+    "utf-8".to_string()
+}
+
+fn default_income_kind() -> String {
+    Kind::Income.to_string()
+}
+
+fn default_expense_kind() -> String {
+    Kind::DebitPurchase.to_string()
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub header: Vec<String>,
+    pub date_column: String,
+    pub description_column: String,
+    pub amount_column: String,
+    pub date_format: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+    #[serde(default)]
+    pub thousands_separator: Option<char>,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    #[serde(default)]
+    pub skip_rows: usize,
+    #[serde(default)]
+    pub sign: SignConvention,
+    #[serde(default = "default_income_kind")]
+    pub income_kind: String,
+    #[serde(default = "default_expense_kind")]
+    pub expense_kind: String,
+    #[serde(default)]
+    pub kind_column: Option<String>,
+    #[serde(default)]
+    pub kinds: HashMap<String, String>,
+    #[serde(default)]
+    pub original_amount_column: Option<String>,
+    #[serde(default)]
+    pub original_currency_column: Option<String>,
+}
+
+pub struct ProfileFormatter {
+    profile: Profile,
+    encoding: &'static Encoding,
+    income_kind: Kind,
+    expense_kind: Kind,
+    kinds: HashMap<String, Kind>,
+}
+
+fn parse_kind(profile: &Profile, value: &str) -> Result<Kind> {
+    Kind::from_str(value).map_err(|_| eyre!("profile {}: unknown kind {value}", profile.name))
+}
+
+impl ProfileFormatter {
+    pub fn new(profile: Profile) -> Result<Self> {
+        let encoding = Encoding::for_label(profile.encoding.as_bytes()).ok_or_else(|| {
+            eyre!(
+                "profile {}: unknown encoding {}",
+                profile.name,
+                profile.encoding
+            )
+        })?;
+        if !profile.delimiter.is_ascii() {
+            bail!(
+                "profile {}: the delimiter must be an ASCII character",
+                profile.name
+            );
+        }
+        if Some(profile.decimal_separator) == profile.thousands_separator {
+            bail!(
+                "profile {}: decimal and thousands separators are equal",
+                profile.name
+            );
+        }
+
+        if profile.original_amount_column.is_some() != profile.original_currency_column.is_some() {
+            bail!(
+                "profile {}: original_amount_column and original_currency_column go together",
+                profile.name
+            );
+        }
+
+        let income_kind = parse_kind(&profile, &profile.income_kind)?;
+        let expense_kind = parse_kind(&profile, &profile.expense_kind)?;
+        let kinds = profile
+            .kinds
+            .iter()
+            .map(|(value, kind)| Ok((value.to_lowercase(), parse_kind(&profile, kind)?)))
+            .collect::<Result<HashMap<String, Kind>>>()?;
+
+        Ok(ProfileFormatter {
+            profile,
+            encoding,
+            income_kind,
+            expense_kind,
+            kinds,
+        })
+    }
+
+    fn header(&self) -> Vec<String> {
+        match self.profile.header.is_empty() {
+            true => vec![
+                self.profile.date_column.clone(),
+                self.profile.description_column.clone(),
+                self.profile.amount_column.clone(),
+            ],
+            false => self.profile.header.clone(),
+        }
+    }
+
+    fn read(&self, statement: &Statement, n_rows: Option<usize>) -> PolarsResult<DataFrame> {
+        let (text, _, _) = self.encoding.decode(&statement.bytes);
+        CsvReadOptions::default()
+            .with_has_header(true)
+            .with_skip_rows(self.profile.skip_rows)
+            .with_n_rows(n_rows)
+            .with_infer_schema_length(Some(0))
+            .map_parse_options(|parse_options| {
+                parse_options.with_separator(self.profile.delimiter as u8)
+            })
+            .into_reader_with_file_handle(Cursor::new(text.as_bytes()))
+            .finish()
+    }
+
+    fn column<'a>(&self, df: &'a DataFrame, name: &str) -> PolarsResult<&'a StringChunked> {
+        let column = df
+            .get_column_names()
+            .into_iter()
+            .find(|column| column.trim().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                polars_err!(ColumnNotFound: "profile {}: column {name} not found", self.profile.name)
+            })?;
+        df.column(column)?.str()
+    }
+
+    fn parse_date(&self, value: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(value.trim(), &self.profile.date_format).ok()
+    }
+
+    fn parse_amount(&self, value: &str) -> Option<Money> {
+        let value = value.trim();
+        let (value, negative) = match value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+            Some(inner) => (inner, true),
+            None => (value, false),
+        };
+
+        let normalized: String = value
+            .chars()
+            .filter(|c| Some(*c) != self.profile.thousands_separator)
+            .map(|c| match c == self.profile.decimal_separator {
+                true => '.',
+                false => c,
+            })
+            .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-' || *c == '+')
+            .collect();
+
+        let amount = Money::parse(&normalized, Currency::default())?;
+        match negative {
+            true => Some(-amount),
+            false => Some(amount),
+        }
+    }
+
+    fn kind(&self, amount: Money, kind_value: Option<&str>) -> Kind {
+        if let Some(kind) =
+            kind_value.and_then(|value| self.kinds.get(&value.trim().to_lowercase()))
+        {
+            return kind.clone();
+        }
+
+        let expense = match self.profile.sign {
+            SignConvention::NegativeIsExpense => amount.is_negative(),
+            SignConvention::PositiveIsExpense => !amount.is_negative() && !amount.is_zero(),
+        };
+        match expense {
+            true => self.expense_kind.clone(),
+            false => self.income_kind.clone(),
+        }
+    }
+}
+
+impl Importer for ProfileFormatter {
+    fn name(&self) -> &str {
+        &self.profile.name
+    }
+
+    fn detect(&self, statement: &Statement) -> u32 {
+        let Ok(sample) = self.read(statement, Some(SAMPLE_ROWS)) else {
+            return 0;
+        };
+        let names: Vec<String> = sample
+            .get_column_names()
+            .iter()
+            .map(|name| name.trim().to_lowercase())
+            .collect();
+        let header = self.header();
+        if !header
+            .iter()
+            .all(|name| names.contains(&name.trim().to_lowercase()))
+        {
+            return 0;
+        }
+
+        let mut score = PROFILE_BONUS + 10 * header.len() as u32;
+        let dates_parse = self
+            .column(&sample, &self.profile.date_column)
+            .map(|dates| {
+                dates
+                    .into_iter()
+                    .flatten()
+                    .all(|date| self.parse_date(date).is_some())
+            })
+            .unwrap_or(false);
+        if dates_parse {
+            score += 5;
+        }
+        score
+    }
+
+    fn import(&self, statement: &Statement) -> PolarsResult<DataFrame> {
+        let df = self.read(statement, None)?;
+        let dates = self.column(&df, &self.profile.date_column)?;
+        let descriptions = self.column(&df, &self.profile.description_column)?;
+        let amounts = self.column(&df, &self.profile.amount_column)?;
+        let kind_values = match &self.profile.kind_column {
+            Some(kind_column) => Some(self.column(&df, kind_column)?),
+            None => None,
+        };
+        let original_amounts = match &self.profile.original_amount_column {
+            Some(column) => Some(self.column(&df, column)?),
+            None => None,
+        };
+        let original_currencies = match &self.profile.original_currency_column {
+            Some(column) => Some(self.column(&df, column)?),
+            None => None,
+        };
+
+        let mut date_column = Vec::with_capacity(df.height());
+        let mut title_column = Vec::with_capacity(df.height());
+        let mut amount_column = Vec::with_capacity(df.height());
+        let mut kind_column = Vec::with_capacity(df.height());
+        let mut original_amount_column = Vec::with_capacity(df.height());
+        let mut original_currency_column = Vec::with_capacity(df.height());
+
+        // Rows that fail to parse are kept as nulls so the import preview can report them.
+        for index in 0..df.height() {
+            let date = dates.get(index).and_then(|value| self.parse_date(value));
+            let amount = amounts
+                .get(index)
+                .and_then(|value| self.parse_amount(value));
+            let kind_value = kind_values.and_then(|values| values.get(index));
+
+            date_column.push(date);
+            title_column.push(
+                descriptions
+                    .get(index)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+            );
+            amount_column.push(amount.map(|amount| amount.abs().to_string()));
+            kind_column.push(amount.map(|amount| self.kind(amount, kind_value).to_string()));
+
+            // Blank cells mean the charge was made in the account currency.
+            let original_amount = original_amounts
+                .and_then(|values| values.get(index))
+                .filter(|value| !value.trim().is_empty());
+            original_amount_column.push(original_amount.map(|value| {
+                self.parse_amount(value)
+                    .map(|amount| amount.abs().to_string())
+                    .unwrap_or_else(|| value.to_string())
+            }));
+            original_currency_column.push(
+                original_amount.and(
+                    original_currencies
+                        .and_then(|values| values.get(index))
+                        .map(|value| value.trim().to_string()),
+                ),
+            );
+        }
+
+        df!(
+            "date" => date_column,
+            "title" => title_column,
+            "amount" => amount_column,
+            "kind" => kind_column,
+            "original_amount" => original_amount_column,
+            "original_currency" => original_currency_column,
+        )?
+        .with_row_index(
+            "line",
+            Some(FIRST_CSV_LINE + self.profile.skip_rows as IdxSize),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::ImporterRegistry;
+    use std::path::PathBuf;
+
+    fn profile(settings: &str) -> Profile {
+        toml::from_str(&format!(
+            "name = \"bank\"\n\
+             date_column = \"Date\"\n\
+             description_column = \"Memo\"\n\
+             amount_column = \"Amount\"\n\
+             date_format = \"%d/%m/%Y\"\n\
+             {settings}"
+        ))
+        .unwrap()
+    }
+
+    fn formatter(settings: &str) -> ProfileFormatter {
+        ProfileFormatter::new(profile(settings)).unwrap()
+    }
+
+    fn statement(text: &str) -> Statement {
+        Statement {
+            path: PathBuf::from("statement.csv"),
+            bytes: text.as_bytes().to_vec(),
+        }
+    }
+
+    fn brl(cents: i64) -> Option<Money> {
+        Some(Money::new(cents, Currency::BRL))
+    }
+
+    #[test]
+    fn decimal_comma_and_point() {
+        let comma = formatter("decimal_separator = \",\"\nthousands_separator = \".\"");
+        assert_eq!(comma.parse_amount("-1.234,56"), brl(-123456));
+        assert_eq!(comma.parse_amount("R$ 12,5"), brl(1250));
+        assert_eq!(comma.parse_amount("(7,00)"), brl(-700));
+
+        let point = formatter("thousands_separator = \",\"");
+        assert_eq!(point.parse_amount("-1,234.56"), brl(-123456));
+        assert_eq!(point.parse_amount("12.5"), brl(1250));
+        assert_eq!(point.parse_amount("abc"), None);
+    }
+
+    #[test]
+    fn sign_convention_decides_expenses() {
+        let negative = formatter("");
+        assert_eq!(negative.kind(brl(-500).unwrap(), None), Kind::DebitPurchase);
+        assert_eq!(negative.kind(brl(500).unwrap(), None), Kind::Income);
+
+        let positive =
+            formatter("sign = \"positive-is-expense\"\nexpense_kind = \"CreditPurchase\"");
+        assert_eq!(positive.kind(brl(500).unwrap(), None), Kind::CreditPurchase);
+        assert_eq!(positive.kind(brl(-500).unwrap(), None), Kind::Income);
+        assert_eq!(positive.kind(brl(0).unwrap(), None), Kind::Income);
+    }
+
+    #[test]
+    fn import_keeps_amounts_unsigned_with_their_kind() {
+        let formatter = formatter(
+            "sign = \"positive-is-expense\"\ndelimiter = \";\"\ndecimal_separator = \",\"\nskip_rows = 1",
+        );
+        let df = formatter
+            .import(&statement(
+                "Extrato\nDate;Memo;Amount\n05/01/2024;PADARIA;12,50\n06/01/2024;ESTORNO;-3,00\nx;BAD;y\n",
+            ))
+            .unwrap();
+        let column = |name: &str| -> Vec<Option<String>> {
+            df.column(name)
+                .unwrap()
+                .cast(&DataType::String)
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_iter()
+                .map(|value| value.map(str::to_string))
+                .collect()
+        };
+        let some = |value: &str| Some(value.to_string());
+        assert_eq!(column("line"), vec![some("3"), some("4"), some("5")]);
+        assert_eq!(column("amount"), vec![some("12.50"), some("3.00"), None]);
+        assert_eq!(
+            column("kind"),
+            vec![some("DebitPurchase"), some("Income"), None]
+        );
+        assert_eq!(column("date")[2], None);
+    }
+
+    #[test]
+    fn unknown_kinds() {
+        let error = ProfileFormatter::new(profile("expense_kind = \"Shopping\""))
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "profile bank: unknown kind Shopping");
+        let error = ProfileFormatter::new(profile("[kinds]\nPIX = \"Transfer\""))
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "profile bank: unknown kind Transfer");
+
+        // A value the profile does not map falls back to the sign.
+        let formatter = formatter("kind_column = \"Type\"\n[kinds]\nEstorno = \"Refund\"");
+        assert_eq!(
+            formatter.kind(brl(500).unwrap(), Some(" ESTORNO ")),
+            Kind::Refund
+        );
+        assert_eq!(
+            formatter.kind(brl(-500).unwrap(), Some("TED")),
+            Kind::DebitPurchase
+        );
+    }
+
+    #[test]
+    fn profile_beats_a_built_in_importer() {
+        let text = "date,title,amount\n2024-01-05,PADARIA,12.50\n";
+        let statement = statement(text);
+        let name =
+            |registry: &ImporterRegistry| registry.detect(&statement).unwrap().name().to_string();
+        assert_eq!(name(&ImporterRegistry::new()), "credit");
+
+        let profile = Profile {
+            date_column: "date".to_string(),
+            description_column: "title".to_string(),
+            amount_column: "amount".to_string(),
+            date_format: "%Y-%m-%d".to_string(),
+            ..profile("")
+        };
+        let registry = ImporterRegistry::with_profiles(std::slice::from_ref(&profile)).unwrap();
+        assert_eq!(name(&registry), "bank");
+
+        let unrelated = Profile {
+            header: vec!["date".to_string(), "balance".to_string()],
+            ..profile
+        };
+        let registry = ImporterRegistry::with_profiles(&[unrelated]).unwrap();
+        assert_eq!(name(&registry), "credit");
+    }
+}
@@ -0,0 +1,529 @@
+use chrono::{Local, NaiveDate};
+use color_eyre::eyre::{Result, eyre};
+use polars::prelude::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::db::Database;
+use crate::format::{Formatter, ImporterRegistry, Statement};
+use crate::labeling::RuleSet;
+use crate::models::{
+    Currency, ImportBatch, Installment, Kind, Money, NewTransaction, assign_fingerprints,
+};
+use crate::refund::{self, Purchase};
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%d/%m/%Y", "%Y/%m/%d"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowStatus {
+    New,
+    Duplicate,
+    Transformed(String),
+    Rejected(String),
+}
+
+impl RowStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RowStatus::New => "new",
+            RowStatus::Duplicate => "duplicate",
+            RowStatus::Transformed(_) => "transformed",
+            RowStatus::Rejected(_) => "rejected",
+        }
+    }
+}
+
+pub struct PreviewRow {
+    // The line of the statement file the row came from.
+    pub line: usize,
+    pub status: RowStatus,
+    pub transaction: Option<NewTransaction>,
+}
+
+pub struct ImportPreview {
+    pub path: PathBuf,
+    pub content_hash: String,
+    pub importer: String,
+    pub account: String,
+    pub rows: Vec<PreviewRow>,
+}
+
+impl ImportPreview {
+    pub fn count(&self, label: &str) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| row.status.label() == label)
+            .count()
+    }
+
+    pub fn transactions(&self) -> Vec<NewTransaction> {
+        self.rows
+            .iter()
+            .filter(|row| matches!(row.status, RowStatus::New | RowStatus::Transformed(_)))
+            .filter_map(|row| row.transaction.clone())
+            .collect()
+    }
+
+    // The account may have been created after the preview, by another statement imported along
+    // with this one, so its currency is checked again before anything is written.
+    pub fn commit(&self, database: &Database) -> Result<ImportBatch> {
+        let transactions = self.transactions();
+        if let Some(account) = database
+            .get_accounts()?
+            .into_iter()
+            .find(|row| row.name == self.account)
+            && let Some(row) = transactions
+                .iter()
+                .find(|row| row.amount.currency != account.currency)
+        {
+            return Err(eyre!(
+                "{}: amounts in {}, account {} is in {}, nothing was written",
+                self.path.display(),
+                row.amount.currency,
+                self.account,
+                account.currency
+            ));
+        }
+
+        let batch = ImportBatch {
+            id: 0,
+            path: fs::canonicalize(&self.path)?.display().to_string(),
+            content_hash: self.content_hash.clone(),
+            importer: self.importer.clone(),
+            account: self.account.clone(),
+            total_rows: self.rows.len(),
+            inserted_rows: 0,
+            imported_at: Local::now().naive_local(),
+        };
+        Ok(database.insert_import(batch, transactions)?)
+    }
+}
+
+pub fn read_folder(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn read_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut statements = Vec::new();
+    for path in paths {
+        match path.is_dir() {
+            true => statements.extend(read_folder(path)?),
+            false => statements.push(path.clone()),
+        }
+    }
+    Ok(statements)
+}
+
+pub fn read_statement(
+    statement: &Statement,
+    registry: &ImporterRegistry,
+) -> Result<(String, DataFrame)> {
+    let mut formatter = Formatter::new(DataFrame::default());
+    let importer = formatter.add(registry, statement)?;
+    let df = formatter
+        .build()
+        .map_err(|e| eyre!("{}: {e}", statement.path.display()))?;
+    Ok((importer, df))
+}
+
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value.trim(), format).ok())
+}
+
+fn date_column(df: &DataFrame) -> PolarsResult<Vec<Result<NaiveDate, String>>> {
+    let dates = df.column("date")?;
+    if dates.dtype() == &DataType::Date {
+        return Ok(dates
+            .date()?
+            .as_date_iter()
+            .map(|date| date.ok_or_else(|| "invalid or missing date".to_string()))
+            .collect());
+    }
+
+    Ok(dates
+        .cast(&DataType::String)?
+        .str()?
+        .iter()
+        .map(|value| match value {
+            Some(value) => parse_date(value).ok_or_else(|| format!("invalid date {value:?}")),
+            None => Err("missing date".to_string()),
+        })
+        .collect())
+}
+
+// Importers that parse amounts themselves hand them over as decimal strings, which convert to
+// cents exactly. Float columns inferred from CSV files are rounded to the nearest cent.
+fn amount_column(df: &DataFrame, currency: Currency) -> PolarsResult<Vec<Result<Money, String>>> {
+    let amounts = df.column("amount")?;
+    if amounts.dtype().is_float() {
+        return Ok(amounts
+            .cast(&DataType::Float64)?
+            .f64()?
+            .iter()
+            .map(|amount| {
+                amount
+                    .and_then(|amount| Money::from_f64(amount, currency))
+                    .ok_or_else(|| "invalid or missing amount".to_string())
+            })
+            .collect());
+    }
+
+    Ok(amounts
+        .cast(&DataType::String)?
+        .str()?
+        .iter()
+        .map(|raw| match raw {
+            Some(raw) => {
+                Money::parse(raw, currency).ok_or_else(|| format!("invalid amount {raw:?}"))
+            }
+            None => Err("invalid or missing amount".to_string()),
+        })
+        .collect())
+}
+
+// A foreign charge keeps the amount it had before the card converted it, when the statement
+// says so. Amounts already in the account currency carry no original.
+fn original_column(
+    df: &DataFrame,
+    currency: Currency,
+) -> PolarsResult<Vec<Result<Option<Money>, String>>> {
+    let amounts = df.column("original_amount")?.str()?;
+    let currencies = df.column("original_currency")?.str()?;
+    Ok(amounts
+        .iter()
+        .zip(currencies.iter())
+        .map(|(amount, original_currency)| {
+            let (Some(amount), Some(original_currency)) = (amount, original_currency) else {
+                return Ok(None);
+            };
+            let original_currency = Currency::from_str(original_currency)
+                .map_err(|_| format!("invalid original currency {original_currency:?}"))?;
+            match Money::parse(amount, original_currency) {
+                Some(_) if original_currency == currency => Ok(None),
+                Some(original) => Ok(Some(original)),
+                None => Err(format!("invalid original amount {amount:?}")),
+            }
+        })
+        .collect())
+}
+
+pub fn convert_df(
+    df: &DataFrame,
+    account: &str,
+    currency: Currency,
+) -> PolarsResult<Vec<Result<NewTransaction, String>>> {
+    if df.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let dates = date_column(df)?;
+    let amounts = amount_column(df, currency)?;
+    let originals = original_column(df, currency)?;
+    let titles = df.column("title")?.str()?;
+    let kinds = df.column("kind")?.str()?;
+    let currencies = df.column("currency")?.str()?;
+    let fitids = df.column("fitid")?.str()?;
+
+    let mut rows: Vec<Result<NewTransaction, String>> = (0..df.height())
+        .map(|index| {
+            if let Some(statement_currency) = currencies.get(index) {
+                match Currency::from_str(statement_currency) {
+                    Ok(statement_currency) if statement_currency == currency => (),
+                    Ok(statement_currency) => {
+                        return Err(format!(
+                            "amount in {statement_currency}, account {account} is in {currency}"
+                        ));
+                    }
+                    Err(_) => return Err(format!("invalid currency {statement_currency:?}")),
+                }
+            }
+            let date = dates[index].clone()?;
+            let amount = amounts[index].clone()?;
+            let original = originals[index].clone()?;
+            let title = match titles.get(index).map(str::trim) {
+                Some(title) if !title.is_empty() => title.to_string(),
+                _ => return Err("missing description".to_string()),
+            };
+            let kind = match kinds.get(index) {
+                Some(kind) => Kind::from_str(kind).map_err(|_| format!("unknown kind {kind:?}"))?,
+                None => return Err("missing kind".to_string()),
+            };
+
+            let installment = match kind {
+                Kind::CreditPurchase => Installment::parse(&title),
+                _ => None,
+            };
+
+            Ok(NewTransaction {
+                date,
+                title,
+                amount,
+                kind,
+                fitid: fitids.get(index).map(|fitid| fitid.to_string()),
+                account: account.to_string(),
+                fingerprint: String::new(),
+                installment,
+                original,
+            })
+        })
+        .collect();
+
+    assign_fingerprints(rows.iter_mut().filter_map(|row| row.as_mut().ok()));
+    Ok(rows)
+}
+
+pub fn preview(
+    database: &Database,
+    statement: &Statement,
+    importer: String,
+    df: &DataFrame,
+    rules: &RuleSet,
+    account: String,
+) -> Result<ImportPreview> {
+    // A new account takes the currency the statement says it is in.
+    let currency = database
+        .get_accounts()?
+        .into_iter()
+        .find(|row| row.name == account)
+        .map(|row| row.currency)
+        .or_else(|| {
+            df.column("currency")
+                .ok()?
+                .str()
+                .ok()?
+                .into_iter()
+                .flatten()
+                .find_map(|currency| Currency::from_str(currency).ok())
+        })
+        .unwrap_or_default();
+
+    let converted = convert_df(df, &account, currency)?;
+    let lines = df.column("line")?.i64()?;
+    let purchases: Vec<Purchase> = database
+        .get_transactions()?
+        .iter()
+        .filter_map(Purchase::from_transaction)
+        .chain(
+            converted
+                .iter()
+                .filter_map(|row| row.as_ref().ok())
+                .filter_map(Purchase::from_new_transaction),
+        )
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    for (index, row) in converted.into_iter().enumerate() {
+        let (status, transaction) = match row {
+            Err(reason) => (RowStatus::Rejected(reason), None),
+            Ok(mut transaction) => {
+                // The fingerprint keeps the kind the statement gave, so re-imports still match.
+                let mut changes = Vec::new();
+                if transaction.kind != Kind::Refund
+                    && refund::is_refund(
+                        &transaction.kind,
+                        transaction.date,
+                        &transaction.title,
+                        transaction.amount,
+                        &transaction.account,
+                        &purchases,
+                    )
+                {
+                    transaction.kind = Kind::Refund;
+                    changes.push("recorded as a refund".to_string());
+                }
+                if let Some(title) = rules
+                    .label(
+                        &transaction.title,
+                        transaction.amount,
+                        &transaction.kind,
+                        &transaction.account,
+                        transaction.date,
+                    )
+                    .title
+                    && title != transaction.title
+                {
+                    changes.push(format!("title shown as {title:?}"));
+                }
+
+                let status = if !seen.insert(transaction.fingerprint.clone())
+                    || database.has_fingerprint(&transaction.fingerprint)?
+                {
+                    RowStatus::Duplicate
+                } else if changes.is_empty() {
+                    RowStatus::New
+                } else {
+                    RowStatus::Transformed(changes.join(", "))
+                };
+                (status, Some(transaction))
+            }
+        };
+        rows.push(PreviewRow {
+            line: lines.get(index).map_or(index + 1, |line| line as usize),
+            status,
+            transaction,
+        });
+    }
+
+    Ok(ImportPreview {
+        path: statement.path.clone(),
+        content_hash: statement.content_hash(),
+        importer,
+        account,
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Account, AccountKind, Rule};
+
+    const STATEMENT: &str = "date,title,amount\n\
+                             2024-01-05,PADARIA,12.50\n\
+                             2024-01-05,PADARIA,12.50\n\
+                             2024-01-06,MERCADO,abc\n\
+                             2024-13-01,FARMACIA,8.00\n\
+                             2024-01-07,PAGAMENTO,-30.00\n";
+
+    // Committing keeps the canonical path of the statement, so it has to exist.
+    fn statement(name: &str, text: &str) -> Statement {
+        let path = std::env::temp_dir().join(format!("easymoney-{}-{name}", std::process::id()));
+        fs::write(&path, text).unwrap();
+        Statement::open(&path).unwrap()
+    }
+
+    fn preview_of(database: &Database, statement: &Statement, rules: &RuleSet) -> ImportPreview {
+        let (importer, df) = read_statement(statement, &ImporterRegistry::new()).unwrap();
+        preview(
+            database,
+            statement,
+            importer,
+            &df,
+            rules,
+            "card".to_string(),
+        )
+        .unwrap()
+    }
+
+    fn statuses(preview: &ImportPreview) -> Vec<(usize, RowStatus)> {
+        preview
+            .rows
+            .iter()
+            .map(|row| (row.line, row.status.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn rows_are_classified_before_anything_is_written() {
+        let database = Database::open(Path::new(":memory:")).unwrap();
+        let preview = preview_of(
+            &database,
+            &statement("classified.csv", STATEMENT),
+            &RuleSet::default(),
+        );
+        assert_eq!(
+            statuses(&preview),
+            vec![
+                (2, RowStatus::New),
+                (3, RowStatus::New),
+                (
+                    4,
+                    RowStatus::Rejected("invalid or missing amount".to_string())
+                ),
+                (
+                    5,
+                    RowStatus::Rejected("invalid date \"2024-13-01\"".to_string())
+                ),
+                (6, RowStatus::New),
+            ]
+        );
+        assert_eq!(preview.transactions().len(), 3);
+        assert!(database.get_transactions().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rows_renamed_by_a_rule_are_transformed() {
+        let database = Database::open(Path::new(":memory:")).unwrap();
+        let rules = RuleSet::new(vec![Rule {
+            rename: Some("Padaria Pão Quente".to_string()),
+            ..Rule::keyword("padaria")
+        }]);
+        let preview = preview_of(&database, &statement("renamed.csv", STATEMENT), &rules);
+        assert_eq!(
+            preview.rows[0].status,
+            RowStatus::Transformed("title shown as \"Padaria Pão Quente\"".to_string())
+        );
+        assert_eq!(preview.count("transformed"), 2);
+        assert_eq!(preview.transactions().len(), 3);
+    }
+
+    #[test]
+    fn committed_rows_are_duplicates_the_next_time() {
+        let database = Database::open(Path::new(":memory:")).unwrap();
+        let first = statement("committed.csv", STATEMENT);
+        let batch = preview_of(&database, &first, &RuleSet::default())
+            .commit(&database)
+            .unwrap();
+        assert_eq!((batch.total_rows, batch.inserted_rows), (5, 3));
+        assert_eq!(database.get_transactions().unwrap().len(), 3);
+
+        let again = preview_of(&database, &first, &RuleSet::default());
+        assert_eq!(again.count("duplicate"), 3);
+        assert_eq!(again.count("rejected"), 2);
+        assert!(again.transactions().is_empty());
+
+        // A statement overlapping the first one only brings what is new.
+        let overlapping = statement(
+            "overlapping.csv",
+            "date,title,amount\n2024-01-05,PADARIA,12.50\n2024-01-08,CINEMA,40.00\n",
+        );
+        let preview = preview_of(&database, &overlapping, &RuleSet::default());
+        assert_eq!(
+            statuses(&preview),
+            vec![(2, RowStatus::Duplicate), (3, RowStatus::New)]
+        );
+        preview.commit(&database).unwrap();
+        assert_eq!(database.get_transactions().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn commit_refuses_an_account_in_another_currency() {
+        let database = Database::open(Path::new(":memory:")).unwrap();
+        let preview = preview_of(
+            &database,
+            &statement("currency.csv", STATEMENT),
+            &RuleSet::default(),
+        );
+
+        // Another statement of the same run created the account in the meantime.
+        let usd: Currency = "USD".parse().unwrap();
+        database
+            .save_account(&Account {
+                currency: usd,
+                opening_balance: Money::zero(usd),
+                ..Account::new("card", AccountKind::CreditCard)
+            })
+            .unwrap();
+
+        let error = preview.commit(&database).unwrap_err().to_string();
+        assert!(
+            error.ends_with(": amounts in BRL, account card is in USD, nothing was written"),
+            "{error}"
+        );
+        assert!(database.get_transactions().unwrap().is_empty());
+        assert!(database.get_imports().unwrap().is_empty());
+    }
+}
@@ -1,59 +1,464 @@
+use chrono::NaiveDate;
+use itertools::Itertools;
+use regex::{Regex, RegexBuilder};
 use std::collections::HashMap;
 
-use crate::models::Category;
+use crate::models::{Category, Kind, Money, Rule, Split, Transaction};
 
-#[derive(Clone)]
-pub struct FieldMap<V> {
-    pub map: HashMap<String, V>,
+// Words in a title that say nothing about where the money went, ignored when titles are
+// compared to link a refund and when they are weighed to suggest a category.
+pub const STOP_WORDS: [&str; 8] = ["de", "do", "da", "of", "the", "compra", "pix", "parcela"];
+
+// Rules created by editing a transaction in the TUI are checked before keyword rules.
+pub const EDIT_PRIORITY: i64 = 10;
+
+// Longer keywords come first, so the more specific one wins when a title contains both.
+const DEFAULT_RULES: [(&str, &str); 11] = [
+    ("supermercado", "Supermarket"),
+    ("restaurante", "Food"),
+    ("culinaria", "Food"),
+    ("esporte", "Personal"),
+    ("spotify", "Personal"),
+    ("airbnb", "Trips"),
+    ("lanche", "Food"),
+    ("viacao", "Trips"),
+    ("ifood", "Food"),
+    ("doces", "Food"),
+    ("uber", "Transportation"),
+];
+
+pub fn default_rules() -> Vec<Rule> {
+    DEFAULT_RULES
+        .iter()
+        .map(|(keyword, category)| Rule {
+            category: Some(Category::from(*category)),
+            ..Rule::keyword(keyword)
+        })
+        .collect()
 }
 
-impl<T: Clone> FieldMap<T> {
-    pub fn get(&self, title: &String) -> Option<T> {
-        for (substring, value) in &self.map {
-            if title.to_ascii_lowercase().contains(substring) {
-                return Some(value.clone());
-            }
+pub fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+// Rules are saved only when they can match something and do something to what they match.
+// Their tags are stored joined by commas, so a tag cannot have one in it.
+pub fn check_rule(rule: &Rule) -> Result<(), String> {
+    if let Some(pattern) = &rule.pattern {
+        compile_pattern(pattern).map_err(|e| format!("Invalid regex: {e}"))?;
+    }
+    if let (Some(min), Some(max)) = (rule.min_amount, rule.max_amount)
+        && min > max
+    {
+        return Err("Minimum amount is above the maximum".to_string());
+    }
+    if let (Some(from), Some(to)) = (rule.from_date, rule.to_date)
+        && from > to
+    {
+        return Err("Start date is after the end date".to_string());
+    }
+    if let Some(tag) = rule.tags.iter().find(|tag| tag.contains(',')) {
+        return Err(format!("Tag {tag:?} has a comma, give each tag separately"));
+    }
+    if rule.actions().is_empty() {
+        return Err("A rule needs a category, a new title or tags".to_string());
+    }
+    Ok(())
+}
+
+fn comes_before(rule: &Rule, other: &Rule) -> bool {
+    (-rule.priority, rule.id) < (-other.priority, other.id)
+}
+
+// Gives the rule at `index` the priority it needs to come before the next one, and the rules
+// above it too as long as they fall out of order.
+fn raise_from(rules: &mut [Rule], index: usize) -> Vec<Rule> {
+    let mut changed = Vec::new();
+    for i in (0..=index).rev() {
+        if comes_before(&rules[i], &rules[i + 1]) {
+            break;
         }
-        None
+        rules[i].priority = match rules[i].id < rules[i + 1].id {
+            true => rules[i + 1].priority,
+            false => rules[i + 1].priority + 1,
+        };
+        changed.push(rules[i].clone());
+    }
+    changed
+}
+
+fn lower_from(rules: &mut [Rule], index: usize) -> Vec<Rule> {
+    let mut changed = Vec::new();
+    for i in index..rules.len() {
+        if comes_before(&rules[i - 1], &rules[i]) {
+            break;
+        }
+        rules[i].priority = match rules[i].id > rules[i - 1].id {
+            true => rules[i - 1].priority,
+            false => rules[i - 1].priority - 1,
+        };
+        changed.push(rules[i].clone());
+    }
+    changed
+}
+
+// Swaps a rule with its neighbour in checking order. Priorities are what decides the order, so
+// either the upper rule of the pair and those above it are raised, or the lower one and those
+// below it are lowered, whichever changes fewer rules. Returns the rules whose priority changed.
+pub fn move_rule(rules: &mut [Rule], index: usize, up: bool) -> Vec<Rule> {
+    let upper = match up {
+        true => index.checked_sub(1),
+        false => Some(index).filter(|index| index + 1 < rules.len()),
+    };
+    let Some(upper) = upper else {
+        return Vec::new();
+    };
+    rules.swap(upper, upper + 1);
+
+    let mut raised = rules.to_vec();
+    let raised_changes = raise_from(&mut raised, upper);
+    let mut lowered = rules.to_vec();
+    let lowered_changes = lower_from(&mut lowered, upper + 1);
+    match raised_changes.len() <= lowered_changes.len() {
+        true => {
+            rules.clone_from_slice(&raised);
+            raised_changes
+        }
+        false => {
+            rules.clone_from_slice(&lowered);
+            lowered_changes
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Labels {
+    pub title: Option<String>,
+    pub category: Option<Category>,
+    pub tags: Vec<String>,
+    pub rules: Vec<u32>,
+}
+
+#[derive(Default, Clone)]
+pub struct RuleSet {
+    rules: Vec<(Rule, Option<Regex>)>,
+}
+
+impl RuleSet {
+    // Rules are checked by descending priority and then in the order they were created, so the
+    // outcome never depends on anything but the rules themselves. A rule whose pattern does not
+    // compile never matches, and disabled rules are left out.
+    pub fn new(mut rules: Vec<Rule>) -> Self {
+        rules.retain(|rule| !rule.disabled);
+        rules.sort_by_key(|rule| (-rule.priority, rule.id));
+        RuleSet {
+            rules: rules
+                .into_iter()
+                .map(|rule| {
+                    let pattern = rule
+                        .pattern
+                        .as_deref()
+                        .and_then(|pattern| compile_pattern(pattern).ok());
+                    (rule, pattern)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter().map(|(rule, _)| rule)
     }
 
-    pub fn insert(&mut self, title: &String, somevalue: &Option<T>) {
-        match somevalue {
-            Some(value) => {
-                self.map
-                    .insert(title.to_string().to_ascii_lowercase(), value.clone());
+    // The first matching rule that renames decides the title. Category and tags are then matched
+    // against both the statement title and the new one, the first rule with a category wins and
+    // tags from every matching rule are kept.
+    pub fn label(
+        &self,
+        title: &str,
+        amount: Money,
+        kind: &Kind,
+        account: &str,
+        date: NaiveDate,
+    ) -> Labels {
+        let mut labels = Labels::default();
+        if let Some((rule, _)) = self.rules.iter().find(|(rule, pattern)| {
+            rule.rename.is_some()
+                && rule.matches(pattern.as_ref(), title, amount, kind, account, date)
+        }) {
+            labels.title = rule.rename.clone();
+            labels.rules.push(rule.id);
+        }
+
+        let renamed = labels.title.clone().unwrap_or(title.to_string());
+        for (rule, _) in self.rules.iter().filter(|(rule, pattern)| {
+            rule.matches(pattern.as_ref(), title, amount, kind, account, date)
+                || rule.matches(pattern.as_ref(), &renamed, amount, kind, account, date)
+        }) {
+            let mut applied = false;
+            if labels.category.is_none()
+                && let Some(category) = &rule.category
+            {
+                labels.category = Some(category.clone());
+                applied = true;
+            }
+            for tag in &rule.tags {
+                if !labels.tags.contains(tag) {
+                    labels.tags.push(tag.clone());
+                    applied = true;
+                }
+            }
+            if applied && !labels.rules.contains(&rule.id) {
+                labels.rules.push(rule.id);
             }
-            None => (),
         }
+        labels
     }
 }
 
-impl FieldMap<Category> {
-    pub fn new() -> Self {
-        let map = FieldMap::create_category_map();
-        FieldMap { map }
+// Labels always start from the title on the statement, so labeling again after the rules change
+// gives the same result as labeling once. A title or category set on the transaction itself
+// takes precedence over the rules, and its own tags come before the ones rules add.
+pub fn label_transactions(transactions: &mut [Transaction], rules: &RuleSet) {
+    for transaction in transactions.iter_mut() {
+        let labels = rules.label(
+            &transaction.statement_title,
+            transaction.amount,
+            &transaction.kind,
+            &transaction.account,
+            transaction.date,
+        );
+        transaction.title = transaction
+            .title_override
+            .clone()
+            .or(labels.title)
+            .unwrap_or(transaction.statement_title.clone());
+        transaction.group = transaction.category_override.clone().or(labels.category);
+        transaction.category_confirmed = transaction.group.is_some();
+        transaction.tags = transaction
+            .manual_tags
+            .iter()
+            .chain(&labels.tags)
+            .unique()
+            .cloned()
+            .collect();
+        transaction.rules = labels.rules;
     }
 
-    fn create_category_map() -> HashMap<String, Category> {
-        let mut map: HashMap<String, Category> = HashMap::new();
-        map.insert("ifood".to_string(), Category::Food);
-        map.insert("uber".to_string(), Category::Transportation);
-        map.insert("supermercado".to_string(), Category::Supermarket);
-        map.insert("restaurante".to_string(), Category::Food);
-        map.insert("lanche".to_string(), Category::Food);
-        map.insert("viacao".to_string(), Category::Trips);
-        map.insert("airbnb".to_string(), Category::Trips);
-        map.insert("spotify".to_string(), Category::Personal);
-        map.insert("doces".to_string(), Category::Food);
-        map.insert("esporte".to_string(), Category::Personal);
-        map.insert("culinaria".to_string(), Category::Food);
-        map
+    // A refund without a rule of its own goes back to the category of the purchase it reverses,
+    // and gives back its splits too since both have the same amount.
+    let purchases: HashMap<u32, (Option<Category>, Vec<Split>)> = transactions
+        .iter()
+        .map(|row| (row.id, (row.group.clone(), row.splits.clone())))
+        .collect();
+    for transaction in transactions.iter_mut() {
+        let Some((group, splits)) = transaction
+            .refund_of
+            .and_then(|purchase| purchases.get(&purchase))
+        else {
+            continue;
+        };
+        if transaction.group.is_none() {
+            transaction.group = group.clone();
+        }
+        if transaction.splits.is_empty() {
+            transaction.splits = splits.clone();
+        }
     }
 }
 
-impl FieldMap<String> {
-    pub fn new() -> Self {
-        let map: HashMap<String, String> = HashMap::new();
-        FieldMap { map }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Currency;
+
+    fn rule(id: u32, priority: i64, contains: &str) -> Rule {
+        Rule {
+            id,
+            priority,
+            ..Rule::keyword(contains)
+        }
+    }
+
+    fn categorize(id: u32, priority: i64, contains: &str, category: &str) -> Rule {
+        Rule {
+            category: Some(Category::from(category)),
+            ..rule(id, priority, contains)
+        }
+    }
+
+    fn label(rules: &RuleSet, title: &str) -> Labels {
+        rules.label(
+            title,
+            Money::new(-2500, Currency::BRL),
+            &Kind::CreditPurchase,
+            "nubank",
+            "2024-03-01".parse().unwrap(),
+        )
+    }
+
+    fn ids(rules: &RuleSet) -> Vec<u32> {
+        rules.rules().map(|rule| rule.id).collect()
+    }
+
+    #[test]
+    fn rules_that_cannot_be_saved_are_refused() {
+        let tagged = |tags: &[&str]| Rule {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Rule::keyword("uber")
+        };
+        assert_eq!(check_rule(&tagged(&["travel", "work"])), Ok(()));
+        assert_eq!(
+            check_rule(&tagged(&["travel,work"])),
+            Err("Tag \"travel,work\" has a comma, give each tag separately".to_string())
+        );
+        assert_eq!(
+            check_rule(&tagged(&[])),
+            Err("A rule needs a category, a new title or tags".to_string())
+        );
+        assert!(
+            check_rule(&Rule {
+                pattern: Some("(".to_string()),
+                ..tagged(&["travel"])
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn rules_are_checked_by_priority_then_id() {
+        let rules = RuleSet::new(vec![
+            categorize(3, 0, "uber", "Transportation"),
+            categorize(1, 0, "uber eats", "Food"),
+            categorize(4, EDIT_PRIORITY, "uber eats", "Delivery"),
+            Rule {
+                disabled: true,
+                ..categorize(2, 20, "uber", "Trips")
+            },
+        ]);
+        assert_eq!(ids(&rules), vec![4, 1, 3]);
+
+        let labels = label(&rules, "UBER EATS SAO PAULO");
+        assert_eq!(labels.category, Some(Category::from("Delivery")));
+        assert_eq!(labels.rules, vec![4]);
+        assert_eq!(
+            label(&rules, "UBER TRIP").category,
+            Some(Category::from("Transportation"))
+        );
+    }
+
+    #[test]
+    fn category_matches_the_new_title_and_tags_add_up() {
+        let rules = RuleSet::new(vec![
+            Rule {
+                rename: Some("iFood".to_string()),
+                ..rule(1, EDIT_PRIORITY, "ifd*")
+            },
+            categorize(2, 0, "ifood", "Food"),
+            Rule {
+                tags: vec!["delivery".to_string()],
+                ..rule(3, 0, "ifood")
+            },
+            Rule {
+                tags: vec!["delivery".to_string(), "card".to_string()],
+                kind: Some(Kind::CreditPurchase),
+                ..rule(4, 0, "")
+            },
+        ]);
+        let labels = label(&rules, "IFD*RESTAURANTE");
+        assert_eq!(labels.title.as_deref(), Some("iFood"));
+        assert_eq!(labels.category, Some(Category::from("Food")));
+        assert_eq!(labels.tags, vec!["delivery", "card"]);
+        assert_eq!(labels.rules, vec![1, 2, 3, 4]);
+    }
+
+    // After any move, the slice is still in the order the rule set checks the rules in.
+    fn assert_in_order(rules: &[Rule]) {
+        let order: Vec<u32> = rules.iter().map(|rule| rule.id).collect();
+        assert_eq!(ids(&RuleSet::new(rules.to_vec())), order);
+    }
+
+    #[test]
+    fn only_categories_from_rules_or_edits_are_confirmed() {
+        let rules = RuleSet::new(vec![categorize(1, 0, "uber", "Transportation")]);
+        let mut transactions = [
+            Transaction::sample(1, "2024-03-01", "UBER TRIP", 2300, Kind::CreditPurchase),
+            Transaction {
+                category_override: Some(Category::from("Books")),
+                ..Transaction::sample(2, "2024-03-02", "LIVRARIA", 4500, Kind::CreditPurchase)
+            },
+            Transaction::sample(3, "2024-03-03", "PADARIA", 1200, Kind::CreditPurchase),
+            Transaction {
+                refund_of: Some(1),
+                ..Transaction::sample(4, "2024-03-04", "ESTORNO", 2300, Kind::Refund)
+            },
+        ];
+        label_transactions(&mut transactions, &rules);
+        let labels: Vec<(Option<String>, bool)> = transactions
+            .iter()
+            .map(|row| {
+                (
+                    row.group.as_ref().map(Category::to_string),
+                    row.category_confirmed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                (Some("Transportation".to_string()), true),
+                (Some("Books".to_string()), true),
+                (None, false),
+                (Some("Transportation".to_string()), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn move_rule_swaps_neighbours_changing_the_fewest_priorities() {
+        let mut rules = vec![
+            rule(1, EDIT_PRIORITY, "a"),
+            rule(2, 0, "b"),
+            rule(3, 0, "c"),
+            rule(4, 0, "d"),
+        ];
+        let changed = move_rule(&mut rules, 2, true);
+        assert_eq!(changed, vec![rule(3, 1, "c")]);
+        assert_eq!(
+            rules.iter().map(|rule| rule.id).collect::<Vec<u32>>(),
+            vec![1, 3, 2, 4]
+        );
+        assert_in_order(&rules);
+
+        let changed = move_rule(&mut rules, 2, false);
+        assert_eq!(changed, vec![rule(4, 1, "d")]);
+        assert_eq!(
+            rules.iter().map(|rule| rule.id).collect::<Vec<u32>>(),
+            vec![1, 3, 4, 2]
+        );
+        assert_in_order(&rules);
+    }
+
+    #[test]
+    fn move_rule_keeps_the_order_consistent() {
+        let mut rules: Vec<Rule> = (1..=6).map(|id| rule(id, 0, "x")).collect();
+        for (index, up) in [
+            (5, true),
+            (4, true),
+            (0, false),
+            (3, true),
+            (1, false),
+            (2, true),
+        ] {
+            move_rule(&mut rules, index, up);
+            assert_in_order(&rules);
+        }
+    }
+
+    #[test]
+    fn move_rule_past_the_ends_does_nothing() {
+        let mut rules = vec![rule(1, 0, "a"), rule(2, 0, "b")];
+        assert!(move_rule(&mut rules, 0, true).is_empty());
+        assert!(move_rule(&mut rules, 1, false).is_empty());
+        assert_eq!(rules, vec![rule(1, 0, "a"), rule(2, 0, "b")]);
     }
 }
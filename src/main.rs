@@ -1,112 +1,769 @@
-use chrono::Datelike;
-use itertools::multizip;
-use polars::prelude::*;
-use rusqlite::Result;
+use chrono::{Datelike, NaiveDate};
+use clap::Parser;
+use color_eyre::eyre::{Result, eyre};
+use itertools::Itertools;
 use std::collections::HashMap;
-use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::str::FromStr;
 
-use crate::models::Category;
+use crate::cli::{
+    AccountArgs, AccountsArgs, AccountsCommand, CategoriesArgs, CategoriesCommand, CategoryArgs,
+    Cli, Command, ImportArgs, MerchantsArgs, RatesArgs, RatesCommand, ReportArgs, RuleArgs,
+    RulesArgs, RulesCommand, RulesExportArgs, RulesImportArgs,
+};
+use crate::config::Config;
+use crate::db::Database;
+use crate::exchange::ExchangeRates;
+use crate::format::{ImporterRegistry, Statement};
+use crate::import::{ImportPreview, RowStatus};
+use crate::models::{CategoryInfo, Money};
+use crate::rulepack::{Merge, RulePack};
 
 pub mod app;
+pub mod classifier;
+pub mod cli;
+pub mod config;
 pub mod db;
 pub mod event;
+pub mod exchange;
 pub mod format;
+pub mod import;
 pub mod labeling;
+pub mod merchants;
 pub mod models;
+pub mod refund;
+pub mod rulepack;
+pub mod settlement;
+pub mod summary;
 pub mod tui;
 
-fn read_csv(path: &fs::DirEntry) -> PolarsResult<DataFrame> {
-    CsvReadOptions::default()
-        .with_has_header(true)
-        .map_parse_options(|parse_options| parse_options.with_try_parse_dates(true))
-        .try_into_reader_with_file_path(Some(path.path()))?
-        .finish()
-}
-
-fn read_folder(path: String) -> PolarsResult<DataFrame> {
-    let paths = fs::read_dir(path)?;
-
-    let mut formatter = format::Formatter::new(DataFrame::default());
-
-    for path in paths {
-        let df = read_csv(&path?)?;
-        formatter.add(df);
-    }
-
-    let full_df = formatter.build()?;
-
-    Ok(full_df)
-}
-
-fn convert_df(df: DataFrame) -> Vec<models::NewTransaction> {
-    let date_series = df
-        .column("date")
-        .expect("CSV Date error")
-        .date()
-        .unwrap()
-        .as_date_iter();
-    let title_series = df
-        .column("title")
-        .expect("CSV Str error")
-        .str()
-        .unwrap()
-        .iter();
-    let amount_series = df
-        .column("amount")
-        .expect("CSV Float error")
-        .f64()
-        .unwrap()
-        .iter();
-    let group_series = df
-        .column("kind")
-        .expect("CSV Str error")
-        .str()
-        .unwrap()
-        .iter();
-
-    let combined = multizip((date_series, title_series, amount_series, group_series));
-    let res: Vec<models::NewTransaction> = combined
-        .map(|(date, title, amount, kind)| models::NewTransaction {
-            date: date.unwrap(),
-            title: title.expect("DateErr").to_string(),
-            amount: amount.unwrap(),
-            kind: models::Kind::from_str(kind.expect("Kind not found")).unwrap(),
+fn load_transactions(database: &Database) -> Result<Vec<models::Transaction>> {
+    let mut transactions = database.get_transactions()?;
+    labeling::label_transactions(
+        &mut transactions,
+        &labeling::RuleSet::new(database.get_rules()?),
+    );
+    Ok(transactions)
+}
+
+fn list_imports(database: &Database) -> Result<()> {
+    println!(
+        "{:>6}  {:<19}  {:<16}  {:>8}  File",
+        "Batch", "Imported at", "Importer", "Rows"
+    );
+    for batch in database.get_imports()? {
+        println!(
+            "{:>6}  {:<19}  {:<16}  {:>8}  {}",
+            batch.id,
+            batch.imported_at.format("%Y-%m-%d %H:%M:%S"),
+            batch.importer,
+            format!("{}/{}", batch.inserted_rows, batch.total_rows),
+            batch.path
+        );
+    }
+    Ok(())
+}
+
+fn undo_import(database: &Database, import_id: u32) -> Result<()> {
+    match database.undo_import(import_id)? {
+        Some(deleted) => println!("Removed batch {import_id} and its {deleted} transactions"),
+        None => return Err(eyre!("Import batch {import_id} not found")),
+    }
+    Ok(())
+}
+
+fn import(database: &Database, config: &Config, args: ImportArgs) -> Result<()> {
+    if let Some(import_id) = args.undo {
+        return undo_import(database, import_id);
+    }
+    if args.list {
+        return list_imports(database);
+    }
+
+    let registry = ImporterRegistry::with_profiles(&config.profiles)?;
+    let rules = labeling::RuleSet::new(database.get_rules()?);
+
+    let mut previews = Vec::new();
+    let mut unreadable = 0;
+    for path in import::read_paths(&args.paths)? {
+        let statement = Statement::open(&path)?;
+        if let Some(batch) = database.find_import(&statement.content_hash())?
+            && !args.force
+        {
+            println!(
+                "{}: already imported as batch {} on {}, skipping (use --force to import again)",
+                path.display(),
+                batch.id,
+                batch.imported_at.format("%Y-%m-%d")
+            );
+            continue;
+        }
+
+        // A statement that cannot be read is reported along with the others instead of stopping
+        // the whole import.
+        let (importer, df) = match import::read_statement(&statement, &registry) {
+            Ok(read) => read,
+            Err(e) => {
+                println!("{e}, skipping");
+                unreadable += 1;
+                continue;
+            }
+        };
+        if args.print {
+            println!("{df}");
+        }
+
+        let account = args.account.clone().unwrap_or(importer.clone());
+        let preview = import::preview(database, &statement, importer, &df, &rules, account)?;
+        print_preview(&preview, args.verbose);
+        previews.push(preview);
+    }
+
+    let new_rows: usize = previews
+        .iter()
+        .map(|preview| preview.transactions().len())
+        .sum();
+    if previews.is_empty() && unreadable > 0 {
+        return Err(eyre!("None of the statements could be read"));
+    }
+    if args.dry_run || previews.is_empty() {
+        return Ok(());
+    }
+    // Without a terminal there is no one to ask, and importing anyway would skip the preview.
+    // Scheduled imports say --yes to commit what they find.
+    if !args.yes && !io::stdin().is_terminal() {
+        return Err(eyre!(
+            "--yes is required to import without a terminal to confirm in, nothing was written"
+        ));
+    }
+    if !args.yes && !confirm(new_rows, previews.len())? {
+        println!("Import aborted, nothing was written");
+        return Ok(());
+    }
+
+    for preview in previews {
+        let batch = preview.commit(database)?;
+        println!(
+            "{}: imported {} new transactions as {} in batch {}",
+            preview.path.display(),
+            batch.inserted_rows,
+            batch.importer,
+            batch.id,
+        );
+    }
+    Ok(())
+}
+
+fn print_preview(preview: &ImportPreview, verbose: bool) {
+    println!(
+        "{} ({}, account {})",
+        preview.path.display(),
+        preview.importer,
+        preview.account
+    );
+    for row in &preview.rows {
+        let detail = match (&row.status, &row.transaction) {
+            (RowStatus::Rejected(reason), _) => reason.clone(),
+            (RowStatus::Transformed(reason), Some(transaction)) => {
+                format!("{} {}, {reason}", transaction.date, transaction.title)
+            }
+            (RowStatus::New | RowStatus::Duplicate, Some(transaction)) if verbose => format!(
+                "{} {} {}",
+                transaction.date, transaction.title, transaction.amount
+            ),
+            _ => continue,
+        };
+        println!(
+            "  line {:>4}  {:<11}  {detail}",
+            row.line,
+            row.status.label()
+        );
+    }
+    println!(
+        "  {} new, {} duplicate, {} transformed, {} rejected",
+        preview.count("new"),
+        preview.count("duplicate"),
+        preview.count("transformed"),
+        preview.count("rejected")
+    );
+}
+
+fn confirm(new_rows: usize, statements: usize) -> Result<bool> {
+    print!("Commit {new_rows} new transactions from {statements} statements? [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+async fn tui(database: Database, config: &Config) -> Result<()> {
+    if database.get_transactions()?.is_empty() {
+        println!("No transactions yet, run `easymoney import <path>` first");
+        return Ok(());
+    }
+    app::init_app(database, config.reporting_currency).await
+}
+
+fn report(database: &Database, config: &Config, args: ReportArgs) -> Result<()> {
+    let currency = config.reporting_currency;
+    let (transactions, missing_rates) = ExchangeRates::new(&database.get_exchange_rates()?)
+        .convert_transactions(&load_transactions(database)?, currency);
+    let categories = database.get_category_list()?;
+    let summaries = summary::get_transactions_by_month(&transactions, &categories);
+
+    let summaries: Vec<&summary::MonthSummary> = match &args.month {
+        Some(month) => {
+            let date = NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d")
+                .map_err(|_| eyre!("Invalid month {month}, expected YYYY-MM"))?;
+            summaries
+                .iter()
+                .filter(|row| (row.year, row.month) == (date.year(), date.month()))
+                .collect()
+        }
+        None => summaries.iter().take(args.last).collect(),
+    };
+
+    println!(
+        "{:<10}{:>14}{:>14}",
+        "Month",
+        format!("Expenses {currency}"),
+        format!("Income {currency}")
+    );
+    for month_summary in &summaries {
+        println!(
+            "{:<10}{:>14}{:>14}",
+            format!("{:02}/{}", month_summary.month, month_summary.year),
+            month_summary.total_expenses,
+            month_summary.total_income
+        );
+        if args.categories {
+            print_category_totals(&month_summary.rolled_up_expenses, &categories);
+        }
+    }
+    if missing_rates > 0 {
+        eprintln!(
+            "{missing_rates} transactions have no exchange rate to {currency} and were left out, \
+            load rates with `easymoney rates import`"
+        );
+    }
+    for month_summary in &summaries {
+        for transaction in &month_summary.left_out {
+            eprintln!("{transaction}: does not add up with the other amounts, left out");
+        }
+    }
+    Ok(())
+}
+
+// Categories are listed as a tree, each with everything spent in its subcategories, followed by
+// names that are not in the category list.
+fn print_category_totals(totals: &HashMap<String, Money>, categories: &models::CategoryList) {
+    let listed: Vec<(String, usize)> = categories
+        .items
+        .iter()
+        .map(|info| (info.category.to_string(), categories.depth(&info.category)))
+        .collect();
+    let unlisted = totals
+        .keys()
+        .filter(|name| !listed.iter().any(|(listed, _)| listed == *name))
+        .sorted()
+        .map(|name| (name.clone(), 1));
+
+    for (name, depth) in listed.iter().cloned().chain(unlisted) {
+        let Some(total) = totals.get(&name).filter(|total| !total.is_zero()) else {
+            continue;
+        };
+        let label = format!("{}{name}", "  ".repeat(depth - 1));
+        println!("  {label:<22}{total:>12}");
+    }
+}
+
+fn rules(database: &Database, args: RulesArgs) -> Result<()> {
+    match args.command {
+        Some(RulesCommand::Add(args)) => return add_rule(database, *args),
+        Some(RulesCommand::Export(args)) => return export_rules(database, args),
+        Some(RulesCommand::Import(args)) => return import_rules(database, args),
+        Some(RulesCommand::Remove(args)) => {
+            return match database.delete_rule(args.id)? {
+                true => {
+                    println!("Removed rule {}", args.id);
+                    Ok(())
+                }
+                false => Err(eyre!("Rule {} not found", args.id)),
+            };
+        }
+        None => (),
+    }
+
+    let filter = args.filter.map(|filter| filter.to_lowercase());
+    let matches = |text: &Option<String>| match (&filter, text) {
+        (Some(filter), Some(text)) => text.to_lowercase().contains(filter),
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    let transactions = load_transactions(database)?;
+
+    println!("{:>6}  {:>8}  {:>7}  Rule", "ID", "Priority", "Matches");
+    for rule in database
+        .get_rules()?
+        .iter()
+        .filter(|rule| matches(&rule.contains) || matches(&rule.pattern))
+        .filter(|rule| !args.categories || rule.category.is_some())
+        .filter(|rule| !args.titles || rule.rename.is_some())
+    {
+        let count = transactions
+            .iter()
+            .filter(|row| row.rules.contains(&rule.id))
+            .count();
+        println!(
+            "{:>6}  {:>8}  {:>7}  {} -> {}{}",
+            rule.id,
+            rule.priority,
+            count,
+            match rule.conditions().is_empty() {
+                true => "always".to_string(),
+                false => rule.conditions().join(", "),
+            },
+            rule.actions().join(", "),
+            match rule.disabled {
+                true => " (disabled)",
+                false => "",
+            }
+        );
+    }
+    Ok(())
+}
+
+fn parse_rule_amount(value: Option<String>) -> Result<Option<i64>> {
+    value
+        .map(|value| {
+            Money::parse(&value, models::Currency::default())
+                .map(|amount| amount.abs().cents)
+                .ok_or_else(|| eyre!("invalid amount {value}"))
         })
+        .transpose()
+}
+
+fn parse_rule_date(value: Option<String>) -> Result<Option<NaiveDate>> {
+    value
+        .map(|value| import::parse_date(&value).ok_or_else(|| eyre!("invalid date {value}")))
+        .transpose()
+}
+
+fn add_rule(database: &Database, args: RuleArgs) -> Result<()> {
+    let rule = models::Rule {
+        id: 0,
+        priority: args.priority,
+        contains: args.contains.map(|contains| contains.to_lowercase()),
+        pattern: args.regex,
+        min_amount: parse_rule_amount(args.min_amount)?,
+        max_amount: parse_rule_amount(args.max_amount)?,
+        kind: args.kind,
+        account: args.account,
+        from_date: parse_rule_date(args.from)?,
+        to_date: parse_rule_date(args.to)?,
+        category: args.category,
+        rename: args.rename,
+        tags: args.tags,
+        disabled: false,
+    };
+    labeling::check_rule(&rule).map_err(|e| eyre!(e))?;
+
+    let id = database.insert_rule(&rule)?;
+    println!("Added rule {id}");
+    Ok(())
+}
+
+fn export_rules(database: &Database, args: RulesExportArgs) -> Result<()> {
+    let pack = RulePack::new(&database.get_rules()?, &database.get_category_list()?);
+    pack.write(&args.path)?;
+    println!(
+        "Exported {} rules and {} categories to {}",
+        pack.rules.len(),
+        pack.categories.len(),
+        args.path.display()
+    );
+    Ok(())
+}
+
+fn describe_category(info: &CategoryInfo) -> String {
+    format!(
+        "parent {}, budget {}, color {}{}",
+        info.parent
+            .as_ref()
+            .map_or("none".to_string(), |parent| parent.to_string()),
+        info.budget,
+        info.color.as_deref().unwrap_or("none"),
+        match info.archived {
+            true => ", archived",
+            false => "",
+        }
+    )
+}
+
+fn describe_rule(rule: &models::Rule) -> String {
+    format!(
+        "{}, priority {}{}",
+        rule.actions().join(", "),
+        rule.priority,
+        match rule.disabled {
+            true => ", disabled",
+            false => "",
+        }
+    )
+}
+
+fn count_merges<T>(merges: &[Merge<T>]) -> String {
+    let count = |added: bool, unchanged: bool| {
+        merges
+            .iter()
+            .filter(|merge| match merge {
+                Merge::Added(_) => added,
+                Merge::Unchanged(_) => unchanged,
+                Merge::Conflict(..) | Merge::Repeated(..) => !added && !unchanged,
+            })
+            .count()
+    };
+    format!(
+        "{} new, {} already there, {} conflicting",
+        count(true, false),
+        count(false, true),
+        count(false, false)
+    )
+}
+
+// Rules and categories are matched with the database's own: new ones are added, identical ones
+// are left alone and the ones that differ are conflicts, kept as they are unless `--replace`
+// says to take the file's version. Every conflict is listed either way.
+fn import_rules(database: &Database, args: RulesImportArgs) -> Result<()> {
+    let (categories, rules) = RulePack::read(&args.path)?.parse()?;
+    let categories = rulepack::merge_categories(&database.get_category_list()?, categories);
+    let rules = rulepack::merge_rules(&database.get_rules()?, rules);
+
+    for merge in &categories {
+        if let Merge::Conflict(current, incoming) = merge {
+            println!("Conflict: category {}", current.category);
+            println!("    database  {}", describe_category(current));
+            println!("    file      {}", describe_category(incoming));
+        }
+    }
+    let conditions = |rule: &models::Rule| match rule.conditions().is_empty() {
+        true => "always".to_string(),
+        false => rule.conditions().join(", "),
+    };
+    for merge in &rules {
+        match merge {
+            Merge::Conflict(current, incoming) => {
+                println!("Conflict: rule {} {}", current.id, conditions(current));
+                println!("    database  {}", describe_rule(current));
+                println!("    file      {}", describe_rule(incoming));
+            }
+            Merge::Repeated(earlier, again) => {
+                println!("Conflict: rule {} twice in the file", conditions(earlier));
+                println!("    first     {}", describe_rule(earlier));
+                println!("    again     {}", describe_rule(again));
+            }
+            _ => (),
+        }
+    }
+    println!("Categories: {}", count_merges(&categories));
+    println!("Rules: {}", count_merges(&rules));
+
+    let conflicts =
+        categories.iter().any(Merge::is_conflict) || rules.iter().any(Merge::is_conflict);
+    let repeated = rules
+        .iter()
+        .any(|merge| matches!(merge, Merge::Repeated(..)));
+    if args.dry_run {
+        return Ok(());
+    }
+    let categories: Vec<CategoryInfo> = categories
+        .into_iter()
+        .filter_map(|merge| merge.into_saved(args.replace))
         .collect();
-    res
+    let rules: Vec<models::Rule> = rules
+        .into_iter()
+        .filter_map(|merge| merge.into_saved(args.replace))
+        .collect();
+    database.save_rule_pack(&categories, &rules)?;
+    println!(
+        "Saved {} categories and {} rules",
+        categories.len(),
+        rules.len()
+    );
+    if conflicts && !args.replace {
+        println!(
+            "Conflicts kept the database's version, use `rules import --replace` to take the file's"
+        );
+    }
+    if repeated {
+        println!("Rules repeated in the file kept their first entry");
+    }
+    Ok(())
 }
 
-fn _get_transactions_by_month(transactions: &Vec<models::Transaction>) -> HashMap<(i32, u32), f64> {
-    let mut count: HashMap<(i32, u32), f64> = HashMap::new();
-    let monthly_totals = transactions.iter().fold(&mut count, |acc, transaction| {
-        *acc.entry((transaction.date.year(), transaction.date.month()))
-            .or_insert(0.0) += transaction.amount;
-        acc // Return the updated accumulator for the next iteration
-    });
-    monthly_totals.clone()
+// Suggestions are numbered in the order they are listed, which only changes when rules or
+// transactions do.
+fn merchants(database: &Database, args: MerchantsArgs) -> Result<()> {
+    let transactions = load_transactions(database)?;
+    let suggestions = merchants::suggest_merchants(&transactions);
+    if let Some(number) = args
+        .accept
+        .iter()
+        .find(|number| **number == 0 || **number > suggestions.len())
+    {
+        return Err(eyre!("Suggestion {number} not found"));
+    }
+
+    let accepted: Vec<&merchants::MerchantSuggestion> = suggestions
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| args.all || args.accept.contains(&(index + 1)))
+        .map(|(_, suggestion)| suggestion)
+        .collect();
+    if accepted.is_empty() {
+        if suggestions.is_empty() {
+            println!("No titles to merge");
+            return Ok(());
+        }
+        for (index, suggestion) in suggestions.iter().enumerate() {
+            println!(
+                "{:>4}  {} ({} transactions)",
+                index + 1,
+                suggestion.name,
+                suggestion.transactions()
+            );
+            for (title, count) in &suggestion.titles {
+                println!("        {title:<40}  {count:>5}");
+            }
+        }
+        println!("Accept suggestions with `merchants --accept <NUMBER>` or `merchants --all`");
+        return Ok(());
+    }
+
+    let rules = database.get_rules()?;
+    let mut renames = Vec::new();
+    for suggestion in &accepted {
+        renames.extend(merchants::rename_rules(suggestion, &rules));
+        println!(
+            "{}: {}",
+            suggestion.name,
+            suggestion.titles.iter().map(|(title, _)| title).join(", ")
+        );
+    }
+    // A rename rule updated for a group may be a keyword one that other titles contain too.
+    let outside = merchants::renamed_outside(&accepted, &transactions, &rules, &renames);
+    if !outside.is_empty() {
+        println!("Also renamed, outside the accepted groups:");
+        for (before, after) in &outside {
+            println!(
+                "    {:<40}  {} -> {}",
+                before.statement_title, before.title, after.title
+            );
+        }
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    database.save_rules(&renames)?;
+    println!("Saved {} rename rules", renames.len());
+    Ok(())
 }
 
-fn _get_transactions_by_category(transactions: &Vec<models::Transaction>) -> HashMap<String, f64> {
-    let mut count: HashMap<String, f64> = HashMap::new();
-    let category_totals = transactions.iter().fold(&mut count, |acc, transaction| {
-        match &transaction.group {
-            Some(category) => *acc.entry(category.to_string()).or_insert(0.0) += transaction.amount,
-            None => (),
+fn accounts(database: &Database, args: AccountsArgs) -> Result<()> {
+    if let Some(AccountsCommand::Set(args)) = args.command {
+        return set_account(database, args);
+    }
+
+    let balances = summary::get_account_balances(
+        &database.get_accounts()?,
+        &database.get_transactions()?,
+        &ExchangeRates::new(&database.get_exchange_rates()?),
+    );
+    println!(
+        "{:<20}  {:<16}  {:<12}  {:<8}  {:>12}  {:>12}  {:>6}",
+        "Account", "Institution", "Type", "Currency", "Opening", "Balance", "Rows"
+    );
+    for balance in balances {
+        let account = balance.account;
+        println!(
+            "{:<20}  {:<16}  {:<12}  {:<8}  {:>12}  {:>12}  {:>6}",
+            account.name,
+            account.institution.unwrap_or_default(),
+            account.kind.to_string(),
+            account.currency,
+            account.opening_balance,
+            balance.balance,
+            balance.transactions
+        );
+        if balance.missing_rates > 0 {
+            eprintln!(
+                "{}: {} transactions have no exchange rate to {} and were left out, \
+                load rates with `easymoney rates import`",
+                account.name, balance.missing_rates, account.currency
+            );
         }
-        acc // Return the updated accumulator for the next iteration
-    });
-    category_totals.clone()
+        for transaction in &balance.left_out {
+            eprintln!(
+                "{}: {transaction}: does not add up with the balance, left out",
+                account.name
+            );
+        }
+    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let df = read_folder("../data".into()).expect("PathError");
-    println!("{df}");
+fn set_account(database: &Database, args: AccountArgs) -> Result<()> {
+    let mut account = database
+        .get_accounts()?
+        .into_iter()
+        .find(|account| account.name == args.name)
+        .unwrap_or_else(|| models::Account::new(&args.name, models::AccountKind::Checking));
+
+    if let Some(institution) = args.institution {
+        account.institution = Some(institution);
+    }
+    if let Some(kind) = args.kind {
+        account.kind = kind;
+    }
+    // Stored amounts keep the currency they were imported in, so an account holding any can no
+    // longer switch to another.
+    if let Some(currency) = args.currency {
+        let currency: models::Currency = currency.parse()?;
+        let rows = database
+            .get_transactions()?
+            .iter()
+            .filter(|row| row.account == account.name)
+            .count();
+        if currency != account.currency && rows > 0 {
+            return Err(eyre!(
+                "{} already has {rows} transactions in {}, its currency cannot change",
+                account.name,
+                account.currency
+            ));
+        }
+        account.currency = currency;
+    }
+    account.opening_balance = match args.opening_balance {
+        Some(opening_balance) => Money::parse(&opening_balance, account.currency)
+            .ok_or_else(|| eyre!("invalid opening balance {opening_balance}"))?,
+        None => Money::new(account.opening_balance.cents, account.currency),
+    };
+
+    database.save_account(&account)?;
+    println!("Saved account {}", account.name);
+    Ok(())
+}
+
+fn categories(database: &Database, args: CategoriesArgs) -> Result<()> {
+    if let Some(CategoriesCommand::Set(args)) = args.command {
+        return set_category(database, args);
+    }
+
+    println!(
+        "{:<20}  {:<8}  {:<12}  Status",
+        "Category", "Budget", "Color"
+    );
+    let categories = database.get_category_list()?;
+    for info in &categories.items {
+        let depth = categories.depth(&info.category);
+        println!(
+            "{:<20}  {:<8}  {:<12}  {}",
+            format!("{}{}", "  ".repeat(depth - 1), info.category),
+            categories.budget(&info.category),
+            info.color.clone().unwrap_or_default(),
+            match info.archived {
+                true => "archived",
+                false => "active",
+            }
+        );
+    }
+    Ok(())
+}
+
+fn set_category(database: &Database, args: CategoryArgs) -> Result<()> {
+    let category: models::Category = args.name.parse()?;
+    let categories = database.get_category_list()?;
+    let mut info = categories
+        .get(&category)
+        .cloned()
+        .unwrap_or_else(|| CategoryInfo::new(category));
+
+    if let Some(parent) = args.parent {
+        let parent: models::Category = parent.parse()?;
+        if categories.get(&parent).is_none() {
+            return Err(eyre!("unknown category {parent}"));
+        }
+        if categories.contains(&info.category, &parent) {
+            return Err(eyre!(
+                "{parent} cannot hold {}, it is the same category or one of its subcategories",
+                info.category
+            ));
+        }
+        info.parent = Some(parent);
+    }
+    if args.top_level {
+        info.parent = None;
+    }
 
-    let transactions = convert_df(df);
+    if let Some(color) = args.color {
+        ratatui::style::Color::from_str(&color).map_err(|_| eyre!("invalid color {color}"))?;
+        info.color = Some(color);
+    }
+    if let Some(budget) = args.budget {
+        info.budget = budget;
+    }
+    if args.archive || args.unarchive {
+        info.archived = args.archive;
+    }
+
+    database.save_category_info(&info)?;
+    println!("Saved category {}", info.category);
+    Ok(())
+}
 
-    let _ = app::init_app(transactions).await;
+fn rates(database: &Database, args: RatesArgs) -> Result<()> {
+    if let Some(RatesCommand::Import(args)) = args.command {
+        let rates = exchange::read_rates(&args.path)?;
+        let loaded = database.insert_exchange_rates(&rates)?;
+        println!(
+            "Loaded {loaded} exchange rates from {}",
+            args.path.display()
+        );
+        return Ok(());
+    }
 
+    println!("{:<10}  {:<4}  {:<4}  {:>12}", "Date", "From", "To", "Rate");
+    for rate in database.get_exchange_rates()? {
+        println!(
+            "{:<10}  {:<4}  {:<4}  {:>12}",
+            rate.date.to_string(),
+            rate.from,
+            rate.to,
+            rate.rate
+        );
+    }
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref())?;
+
+    let database = match &cli.database {
+        Some(path) => Database::open(path)?,
+        None => Database::new()?,
+    };
+
+    match cli.command.unwrap_or(Command::Tui) {
+        Command::Import(args) => import(&database, &config, args),
+        Command::Tui => tui(database, &config).await,
+        Command::Report(args) => report(&database, &config, args),
+        Command::Rules(args) => rules(&database, args),
+        Command::Merchants(args) => merchants(&database, args),
+        Command::Accounts(args) => accounts(&database, args),
+        Command::Categories(args) => categories(&database, args),
+        Command::Rates(args) => rates(&database, args),
+    }
+}
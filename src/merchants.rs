@@ -0,0 +1,471 @@
+use itertools::Itertools;
+
+use crate::labeling::{EDIT_PRIORITY, RuleSet, label_transactions};
+use crate::models::{Rule, Transaction};
+
+// Card processors and payment apps put their name before the merchant's, as in PAG*PADARIA.
+const PROCESSORS: [&str; 16] = [
+    "pag",
+    "pagseguro",
+    "pg",
+    "mp",
+    "mercadopago",
+    "mercpago",
+    "sumup",
+    "stone",
+    "ec",
+    "paypal",
+    "pp",
+    "sq",
+    "iz",
+    "picpay",
+    "cielo",
+    "getnet",
+];
+// Words statements add around the merchant's name.
+const NOISE_WORDS: [&str; 3] = ["compra", "parcela", "parc"];
+// An abbreviation keeps at least this share of the letters of the name it shortens.
+const ABBREVIATION_RATIO: f64 = 0.6;
+
+fn fold_accent(c: char) -> char {
+    match c {
+        'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+        'é' | 'è' | 'ê' | 'ë' => 'e',
+        'í' | 'ì' | 'î' | 'ï' => 'i',
+        'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+        'ú' | 'ù' | 'û' | 'ü' => 'u',
+        'ç' => 'c',
+        c => c,
+    }
+}
+
+// The words of a title that name the merchant, in lowercase. A processor prefix is dropped, and
+// so are store numbers, installment counts and anything else with a digit in it.
+fn words(title: &str) -> Vec<String> {
+    let title = title.to_lowercase();
+    let title = match title.split_once('*') {
+        Some((prefix, rest)) if PROCESSORS.contains(&prefix.trim()) => rest.to_string(),
+        _ => title,
+    };
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .filter(|word| !word.chars().any(|c| c.is_ascii_digit()))
+        .filter(|word| !NOISE_WORDS.contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+fn fold(words: &[String]) -> Vec<String> {
+    words
+        .iter()
+        .map(|word| word.chars().map(fold_accent).collect())
+        .collect()
+}
+
+// Whether `short` can be read as `long` with letters left out, as IFD is for IFOOD.
+fn is_abbreviation(short: &str, long: &str) -> bool {
+    let mut letters = long.chars();
+    short.chars().count() as f64 >= long.chars().count() as f64 * ABBREVIATION_RATIO
+        && short.chars().take(2).eq(long.chars().take(2))
+        && short.chars().all(|c| letters.any(|l| l == c))
+}
+
+// Titles are similar when they have the same words, when each word of one starts the matching
+// word of the other, as REST does RESTAURANTE, or when one abbreviates the other as a whole.
+fn similar(a: &[String], b: &[String]) -> bool {
+    let (compact_a, compact_b) = (a.concat(), b.concat());
+    let (short, long) = match compact_a.len() <= compact_b.len() {
+        true => (&compact_a, &compact_b),
+        false => (&compact_b, &compact_a),
+    };
+    compact_a == compact_b
+        || (a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(a, b)| a.starts_with(b.as_str()) || b.starts_with(a.as_str())))
+        || (short.chars().count() >= 4 && is_abbreviation(short, long))
+}
+
+fn title_case(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerchantSuggestion {
+    pub name: String,
+    // Statement titles in the group, with how many transactions have each.
+    pub titles: Vec<(String, usize)>,
+}
+
+impl MerchantSuggestion {
+    pub fn transactions(&self) -> usize {
+        self.titles.iter().map(|(_, count)| count).sum()
+    }
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    match parents[index] == index {
+        true => index,
+        false => {
+            let root = find(parents, parents[index]);
+            parents[index] = root;
+            root
+        }
+    }
+}
+
+// Groups statement titles that look like the same merchant and suggests one name for each group
+// still shown under more than one title. A name already given to some of them by a rule or by
+// hand is kept, otherwise the most complete title is cleaned up.
+pub fn suggest_merchants(transactions: &[Transaction]) -> Vec<MerchantSuggestion> {
+    let counts = transactions
+        .iter()
+        .map(|row| row.statement_title.as_str())
+        .counts();
+    let titles: Vec<(&str, Vec<String>, Vec<String>)> = counts
+        .keys()
+        .sorted()
+        .map(|title| {
+            let words = words(title);
+            let folded = fold(&words);
+            (*title, words, folded)
+        })
+        .filter(|(_, words, _)| !words.is_empty())
+        .collect();
+
+    // Only titles starting with the same letter are compared, which keeps this far from
+    // comparing every pair.
+    let mut parents: Vec<usize> = (0..titles.len()).collect();
+    let buckets = (0..titles.len()).into_group_map_by(|&i| titles[i].2.concat().chars().next());
+    for indexes in buckets.values() {
+        for (&i, &j) in indexes.iter().tuple_combinations() {
+            if similar(&titles[i].2, &titles[j].2) {
+                let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+                parents[root_i.max(root_j)] = root_i.min(root_j);
+            }
+        }
+    }
+
+    let groups = (0..titles.len()).into_group_map_by(|&i| find(&mut parents, i));
+    groups
+        .values()
+        .filter(|group| group.len() > 1)
+        .filter_map(|group| {
+            let members: Vec<&str> = group.iter().map(|&i| titles[i].0).collect();
+            let rows: Vec<&Transaction> = transactions
+                .iter()
+                .filter(|row| members.contains(&row.statement_title.as_str()))
+                .collect();
+            if rows.iter().map(|row| &row.title).all_equal() {
+                return None;
+            }
+
+            let renamed = rows
+                .iter()
+                .filter(|row| row.title != row.statement_title)
+                .map(|row| &row.title)
+                .counts()
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+                .map(|(title, _)| title.clone());
+            let cleaned = group
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let length = |i: usize| titles[i].2.concat().len();
+                    length(a)
+                        .cmp(&length(b))
+                        .then(counts[titles[a].0].cmp(&counts[titles[b].0]))
+                        .then(titles[b].0.cmp(titles[a].0))
+                })
+                .map(|&i| title_case(&titles[i].1))?;
+            Some(MerchantSuggestion {
+                name: renamed.unwrap_or(cleaned),
+                titles: members
+                    .iter()
+                    .map(|title| (title.to_string(), counts[title]))
+                    .sorted_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)))
+                    .collect(),
+            })
+        })
+        .sorted_by(|a, b| {
+            b.transactions()
+                .cmp(&a.transactions())
+                .then(a.name.cmp(&b.name))
+        })
+        .collect()
+}
+
+// A rule matching the whole statement title and nothing longer, ignoring case.
+fn whole_title(title: &str) -> Rule {
+    Rule {
+        pattern: Some(format!("^{}$", regex::escape(title))),
+        ..Rule::default()
+    }
+}
+
+// An accepted suggestion renames every statement title in it and no other, so each gets a rule
+// matching that whole title rather than a keyword that longer titles contain too. Titles that
+// differ only in case share one rule, and a rename rule already keyed on the title, by keyword
+// as the edit form saves them or whole, is updated rather than repeated.
+pub fn rename_rules(suggestion: &MerchantSuggestion, rules: &[Rule]) -> Vec<Rule> {
+    suggestion
+        .titles
+        .iter()
+        .filter(|(title, _)| *title != suggestion.name)
+        .unique_by(|(title, _)| title.to_lowercase())
+        .map(|(title, _)| {
+            let keys = [Rule::keyword(title), whole_title(title)].map(|rule| rule.conditions());
+            let existing = rules.iter().find(|rule| {
+                keys.contains(&rule.conditions())
+                    && rule.rename.is_some()
+                    && rule.category.is_none()
+                    && rule.tags.is_empty()
+            });
+            Rule {
+                rename: Some(suggestion.name.clone()),
+                disabled: false,
+                ..existing.cloned().unwrap_or(Rule {
+                    priority: EDIT_PRIORITY,
+                    ..whole_title(title)
+                })
+            }
+        })
+        .collect()
+}
+
+// Transactions the rename rules would give a new title that are in none of the accepted groups,
+// as they are now and as they would be. Only an existing keyword rule being updated reaches them.
+// Titles are compared ignoring case, as rules match them.
+pub fn renamed_outside(
+    accepted: &[&MerchantSuggestion],
+    transactions: &[Transaction],
+    rules: &[Rule],
+    renames: &[Rule],
+) -> Vec<(Transaction, Transaction)> {
+    let mut rules = rules.to_vec();
+    for rename in renames {
+        match rules
+            .iter_mut()
+            .find(|rule| rename.id != 0 && rule.id == rename.id)
+        {
+            Some(rule) => *rule = rename.clone(),
+            None => rules.push(rename.clone()),
+        }
+    }
+    let mut relabeled = transactions.to_vec();
+    label_transactions(&mut relabeled, &RuleSet::new(rules));
+    transactions
+        .iter()
+        .zip(relabeled)
+        .filter(|(before, after)| before.title != after.title)
+        .filter(|(before, _)| {
+            !accepted.iter().any(|suggestion| {
+                suggestion
+                    .titles
+                    .iter()
+                    .any(|(title, _)| title.to_lowercase() == before.statement_title.to_lowercase())
+            })
+        })
+        .map(|(before, after)| (before.clone(), after))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Kind;
+
+    fn purchases(titles: &[&str]) -> Vec<Transaction> {
+        titles
+            .iter()
+            .enumerate()
+            .map(|(id, title)| {
+                Transaction::sample(id as u32, "2024-03-01", title, 1000, Kind::CreditPurchase)
+            })
+            .collect()
+    }
+
+    fn groups(suggestions: &[MerchantSuggestion]) -> Vec<(String, Vec<String>)> {
+        suggestions
+            .iter()
+            .map(|suggestion| {
+                (
+                    suggestion.name.clone(),
+                    suggestion
+                        .titles
+                        .iter()
+                        .map(|(title, _)| title.clone())
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn words_drop_processors_numbers_and_noise() {
+        assert_eq!(words("PAG*Padaria Pão 123"), vec!["padaria", "pão"]);
+        assert_eq!(words("COMPRA IFOOD PARC 02/03"), vec!["ifood"]);
+        assert_eq!(words("LOJA*CENTRO"), vec!["loja", "centro"]);
+    }
+
+    #[test]
+    fn similar_titles_are_grouped() {
+        let transactions = purchases(&[
+            "PAG*PADARIA PAO QUENTE",
+            "PADARIA PÃO QUENTE 02",
+            "PADARIA PAO QUENTE",
+            "PADARIA PAO QUENTE",
+            "MERCADOLIVRE",
+            "MERCADO LIVRE",
+            "REST SABOR",
+            "RESTAURANTE SABOR",
+            "LIVRARIA",
+            "CINEMARK",
+        ]);
+        assert_eq!(
+            groups(&suggest_merchants(&transactions)),
+            vec![
+                (
+                    "Padaria Pao Quente".to_string(),
+                    vec![
+                        "PADARIA PAO QUENTE".to_string(),
+                        "PADARIA PÃO QUENTE 02".to_string(),
+                        "PAG*PADARIA PAO QUENTE".to_string(),
+                    ]
+                ),
+                (
+                    "Mercado Livre".to_string(),
+                    vec!["MERCADO LIVRE".to_string(), "MERCADOLIVRE".to_string()]
+                ),
+                (
+                    "Restaurante Sabor".to_string(),
+                    vec!["REST SABOR".to_string(), "RESTAURANTE SABOR".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn abbreviations_need_most_of_the_letters() {
+        assert!(is_abbreviation("ifd", "ifood"));
+        assert!(is_abbreviation("drogasil", "drogasil"));
+        assert!(!is_abbreviation("if", "ifood"));
+        assert!(!is_abbreviation("fido", "ifood"));
+    }
+
+    #[test]
+    fn a_name_given_by_hand_is_kept() {
+        let mut transactions = purchases(&["UBER *TRIP", "UBER TRIP 0312", "UBER TRIP"]);
+        transactions[0].title = "Uber".to_string();
+        let suggestions = suggest_merchants(&transactions);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].name, "Uber");
+        assert_eq!(suggestions[0].transactions(), 3);
+    }
+
+    #[test]
+    fn groups_already_under_one_name_are_left_out() {
+        let mut transactions = purchases(&["IFD*IFOOD", "IFOOD"]);
+        for transaction in transactions.iter_mut() {
+            transaction.title = "iFood".to_string();
+        }
+        assert!(suggest_merchants(&transactions).is_empty());
+    }
+
+    #[test]
+    fn rename_rules_cover_each_title_once() {
+        let suggestion = MerchantSuggestion {
+            name: "iFood".to_string(),
+            titles: vec![
+                ("IFD*IFOOD".to_string(), 3),
+                ("ifd*ifood".to_string(), 1),
+                ("IFOOD".to_string(), 2),
+                ("iFood".to_string(), 1),
+            ],
+        };
+        let existing = Rule {
+            id: 7,
+            priority: 3,
+            rename: Some("Ifood".to_string()),
+            disabled: true,
+            ..Rule::keyword("IFOOD")
+        };
+        let rules = rename_rules(&suggestion, std::slice::from_ref(&existing));
+        assert_eq!(
+            rules,
+            vec![
+                Rule {
+                    priority: EDIT_PRIORITY,
+                    rename: Some("iFood".to_string()),
+                    ..whole_title("IFD*IFOOD")
+                },
+                Rule {
+                    rename: Some("iFood".to_string()),
+                    disabled: false,
+                    ..existing
+                },
+            ]
+        );
+    }
+
+    fn uber() -> MerchantSuggestion {
+        MerchantSuggestion {
+            name: "Uber".to_string(),
+            titles: vec![("UBER TRIP".to_string(), 2), ("UBER *TRIP".to_string(), 1)],
+        }
+    }
+
+    fn titles(transactions: &[Transaction], rules: Vec<Rule>) -> Vec<String> {
+        let mut transactions = transactions.to_vec();
+        label_transactions(&mut transactions, &RuleSet::new(rules));
+        transactions.into_iter().map(|row| row.title).collect()
+    }
+
+    #[test]
+    fn rename_rules_leave_longer_titles_alone() {
+        let transactions = purchases(&["UBER TRIP", "uber trip", "UBER *TRIP", "UBER TRIP HELP"]);
+        let renames = rename_rules(&uber(), &[]);
+        assert_eq!(
+            titles(&transactions, renames.clone()),
+            vec!["Uber", "Uber", "Uber", "UBER TRIP HELP"]
+        );
+        assert!(renamed_outside(&[&uber()], &transactions, &[], &renames).is_empty());
+    }
+
+    #[test]
+    fn titles_outside_the_group_renamed_by_an_updated_keyword_are_listed() {
+        let transactions = purchases(&["UBER TRIP", "UBER *TRIP", "UBER TRIP HELP"]);
+        let existing = Rule {
+            id: 5,
+            priority: EDIT_PRIORITY,
+            rename: Some("Uber Trip".to_string()),
+            ..Rule::keyword("UBER TRIP")
+        };
+        let mut labeled = transactions.clone();
+        label_transactions(&mut labeled, &RuleSet::new(vec![existing.clone()]));
+
+        let renames = rename_rules(&uber(), std::slice::from_ref(&existing));
+        let outside = renamed_outside(&[&uber()], &labeled, &[existing], &renames);
+        let outside: Vec<(&str, &str, &str)> = outside
+            .iter()
+            .map(|(before, after)| {
+                (
+                    before.statement_title.as_str(),
+                    before.title.as_str(),
+                    after.title.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(outside, vec![("UBER TRIP HELP", "Uber Trip", "Uber")]);
+    }
+}
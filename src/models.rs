@@ -1,14 +1,32 @@
-mod csvtype;
-pub use csvtype::CsvType;
+mod account;
+pub use account::{Account, AccountKind};
 
 mod category;
-pub use category::Category;
+pub use category::{BudgetGroup, Category, CategoryInfo, CategoryList};
 
 mod kind;
 pub use kind::Kind;
 
+mod money;
+pub use money::{Currency, Money};
+
 mod newtransaction;
-pub use newtransaction::NewTransaction;
+pub use newtransaction::{NewTransaction, assign_fingerprints};
 
 mod transaction;
 pub use transaction::Transaction;
+
+mod installment;
+pub use installment::Installment;
+
+mod importbatch;
+pub use importbatch::ImportBatch;
+
+mod exchangerate;
+pub use exchangerate::ExchangeRate;
+
+mod split;
+pub use split::Split;
+
+mod rule;
+pub use rule::Rule;
@@ -0,0 +1,94 @@
+use rusqlite::{
+    Result, ToSql,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+};
+use std::{fmt, str::FromStr};
+
+use crate::models::{Currency, Kind, Money};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountKind {
+    Checking,
+    Savings,
+    CreditCard,
+    Cash,
+    Investment,
+}
+
+impl AccountKind {
+    pub fn guess<'a>(kinds: impl IntoIterator<Item = &'a Kind>) -> Self {
+        let credit = kinds
+            .into_iter()
+            .any(|kind| matches!(kind, Kind::CreditPurchase | Kind::CreditBillPayment));
+        match credit {
+            true => AccountKind::CreditCard,
+            false => AccountKind::Checking,
+        }
+    }
+}
+
+impl fmt::Display for AccountKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccountKind::Checking => write!(f, "checking"),
+            AccountKind::Savings => write!(f, "savings"),
+            AccountKind::CreditCard => write!(f, "credit-card"),
+            AccountKind::Cash => write!(f, "cash"),
+            AccountKind::Investment => write!(f, "investment"),
+        }
+    }
+}
+
+impl ToSql for AccountKind {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(self.to_string().into())
+    }
+}
+
+impl FromStr for AccountKind {
+    type Err = FromSqlError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checking" => Ok(AccountKind::Checking),
+            "savings" => Ok(AccountKind::Savings),
+            "credit-card" => Ok(AccountKind::CreditCard),
+            "cash" => Ok(AccountKind::Cash),
+            "investment" => Ok(AccountKind::Investment),
+            _ => Err(FromSqlError::Other(
+                format!("unknown account type {s}").into(),
+            )),
+        }
+    }
+}
+
+impl FromSql for AccountKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub id: u32,
+    pub name: String,
+    pub institution: Option<String>,
+    pub kind: AccountKind,
+    pub currency: Currency,
+    pub opening_balance: Money,
+}
+
+impl Account {
+    pub fn new(name: &str, kind: AccountKind) -> Self {
+        Account {
+            id: 0,
+            name: name.to_string(),
+            institution: None,
+            kind,
+            currency: Currency::default(),
+            opening_balance: Money::zero(Currency::default()),
+        }
+    }
+}
@@ -1,100 +1,277 @@
+use itertools::Itertools;
 use rusqlite::{
     Result, ToSql,
     types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
 };
 use std::fmt;
 use std::str::FromStr;
-use strum::EnumIter;
 
-#[derive(Default, PartialEq, Eq, Debug, Clone, EnumIter)]
-pub enum Category {
-    #[default]
-    Housing,
-    Transportation,
-    Food,
-    Supermarket,
-    Savings,
-    Health,
-    Personal,
-    Trips,
-    Other,
-}
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone)]
+pub struct Category(String);
 
 impl Category {
-    pub fn next(&self) -> Self {
-        match self {
-            Self::Housing => Self::Transportation,
-            Self::Transportation => Self::Food,
-            Self::Food => Self::Supermarket,
-            Self::Supermarket => Self::Savings,
-            Self::Savings => Self::Health,
-            Self::Health => Self::Personal,
-            Self::Personal => Self::Trips,
-            Self::Trips => Self::Other,
-            Self::Other => Self::Housing,
-        }
+    pub fn name(&self) -> &str {
+        &self.0
     }
+}
 
-    pub fn previous(&self) -> Self {
-        match self {
-            Self::Housing => Self::Other,
-            Self::Transportation => Self::Housing,
-            Self::Food => Self::Transportation,
-            Self::Supermarket => Self::Food,
-            Self::Savings => Self::Supermarket,
-            Self::Health => Self::Savings,
-            Self::Personal => Self::Health,
-            Self::Trips => Self::Personal,
-            Self::Other => Self::Trips,
-        }
+impl From<&str> for Category {
+    fn from(name: &str) -> Self {
+        Category(name.to_string())
     }
 }
 
 impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.0)
+    }
+}
+
+impl ToSql for Category {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(self.0.as_str().into())
+    }
+}
+
+impl FromStr for Category {
+    type Err = FromSqlError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err(FromSqlError::Other("empty category name".into())),
+            name => Ok(Category::from(name)),
+        }
+    }
+}
+
+impl FromSql for Category {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse()
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetGroup {
+    Needs,
+    Wants,
+    Savings,
+    #[default]
+    Unbudgeted,
+}
+
+impl fmt::Display for BudgetGroup {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Category::Housing => write!(f, "Housing"),
-            Category::Supermarket => write!(f, "Supermarket"),
-            Category::Transportation => write!(f, "Transportation"),
-            Category::Food => write!(f, "Food"),
-            Category::Savings => write!(f, "Savings"),
-            Category::Health => write!(f, "Health"),
-            Category::Personal => write!(f, "Personal"),
-            Category::Trips => write!(f, "Trips"),
-            Category::Other => write!(f, "Other"),
+            BudgetGroup::Needs => f.pad("needs"),
+            BudgetGroup::Wants => f.pad("wants"),
+            BudgetGroup::Savings => f.pad("savings"),
+            BudgetGroup::Unbudgeted => f.pad("none"),
         }
     }
 }
 
-impl ToSql for Category {
+impl ToSql for BudgetGroup {
     fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
         Ok(self.to_string().into())
     }
 }
 
-impl FromStr for Category {
+impl FromStr for BudgetGroup {
     type Err = FromSqlError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "Housing" => Ok(Category::Housing),
-            "Transportation" => Ok(Category::Transportation),
-            "Food" => Ok(Category::Food),
-            "Savings" => Ok(Category::Savings),
-            "Health" => Ok(Category::Health),
-            "Personal" => Ok(Category::Personal),
-            "Other" => Ok(Category::Other),
-            "Supermarket" => Ok(Category::Supermarket),
-            "Trips" => Ok(Category::Trips),
-            _ => Err(FromSqlError::Other(format!("UnknownEnum {}", s).into())),
+            "needs" => Ok(BudgetGroup::Needs),
+            "wants" => Ok(BudgetGroup::Wants),
+            "savings" => Ok(BudgetGroup::Savings),
+            "none" => Ok(BudgetGroup::Unbudgeted),
+            _ => Err(FromSqlError::Other(
+                format!("unknown budget group {s}").into(),
+            )),
         }
     }
 }
 
-impl FromSql for Category {
+impl FromSql for BudgetGroup {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
-        value
-            .as_str()?
-            .parse()
-            .map_err(|e| FromSqlError::Other(Box::new(e)))
+        value.as_str()?.parse()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryInfo {
+    pub id: u32,
+    pub category: Category,
+    pub parent: Option<Category>,
+    pub color: Option<String>,
+    pub budget: BudgetGroup,
+    pub archived: bool,
+}
+
+impl CategoryInfo {
+    pub fn new(category: Category) -> Self {
+        CategoryInfo {
+            id: 0,
+            category,
+            parent: None,
+            color: None,
+            budget: BudgetGroup::default(),
+            archived: false,
+        }
+    }
+}
+
+// The categories managed in the database, kept in tree order: every category is followed by its
+// subcategories, which is also the order they are offered in when labeling.
+#[derive(Default, Debug, Clone)]
+pub struct CategoryList {
+    pub items: Vec<CategoryInfo>,
+}
+
+impl CategoryList {
+    pub fn new(items: Vec<CategoryInfo>) -> Self {
+        let list = CategoryList { items };
+        let mut ordered: Vec<CategoryInfo> = Vec::with_capacity(list.items.len());
+        for root in list.items.iter().filter(|info| {
+            info.parent
+                .as_ref()
+                .is_none_or(|parent| list.get(parent).is_none())
+        }) {
+            list.push_subtree(root, &mut ordered);
+        }
+        // Categories caught in a parent loop are not reachable from any root, keep them anyway.
+        for info in &list.items {
+            if !ordered.iter().any(|row| row.category == info.category) {
+                ordered.push(info.clone());
+            }
+        }
+        CategoryList { items: ordered }
+    }
+
+    fn push_subtree(&self, info: &CategoryInfo, ordered: &mut Vec<CategoryInfo>) {
+        if ordered.iter().any(|row| row.category == info.category) {
+            return;
+        }
+        ordered.push(info.clone());
+        for child in self.children(&info.category) {
+            self.push_subtree(child, ordered);
+        }
+    }
+
+    pub fn get(&self, category: &Category) -> Option<&CategoryInfo> {
+        self.items.iter().find(|info| info.category == *category)
+    }
+
+    fn children(&self, category: &Category) -> impl Iterator<Item = &CategoryInfo> {
+        self.items
+            .iter()
+            .filter(move |info| info.parent.as_ref() == Some(category))
+    }
+
+    // The category and its ancestors, starting from the top level one.
+    pub fn path(&self, category: &Category) -> Vec<Category> {
+        let mut path = vec![category.clone()];
+        while let Some(parent) = path
+            .last()
+            .and_then(|category| self.get(category))
+            .and_then(|info| info.parent.clone())
+            .filter(|parent| self.get(parent).is_some() && !path.contains(parent))
+        {
+            path.push(parent);
+        }
+        path.reverse();
+        path
+    }
+
+    pub fn depth(&self, category: &Category) -> usize {
+        self.path(category).len()
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.items
+            .iter()
+            .map(|info| self.depth(&info.category))
+            .max()
+            .unwrap_or(1)
+    }
+
+    pub fn contains(&self, ancestor: &Category, category: &Category) -> bool {
+        self.path(category).contains(ancestor)
+    }
+
+    // The ancestor shown in place of the category when levels below `depth` are collapsed.
+    pub fn at_depth(&self, category: &Category, depth: usize) -> Category {
+        let path = self.path(category);
+        path[depth.clamp(1, path.len()) - 1].clone()
+    }
+
+    pub fn label(&self, category: &Category) -> String {
+        self.path(category).iter().join(" > ")
+    }
+
+    // Archived categories keep their history but are no longer offered for new labels.
+    pub fn active(&self) -> Vec<Category> {
+        self.items
+            .iter()
+            .filter(|info| !info.archived)
+            .map(|info| info.category.clone())
+            .collect()
+    }
+
+    // Subcategories count towards the budget group of their parent unless they have their own.
+    pub fn budget(&self, category: &Category) -> BudgetGroup {
+        self.path(category)
+            .iter()
+            .rev()
+            .filter_map(|category| self.get(category))
+            .map(|info| info.budget)
+            .find(|budget| *budget != BudgetGroup::Unbudgeted)
+            .unwrap_or_default()
+    }
+
+    pub fn color(&self, category: &Category) -> Option<&str> {
+        self.path(category)
+            .iter()
+            .rev()
+            .filter_map(|category| self.get(category))
+            .find_map(|info| info.color.as_deref())
+    }
+
+    pub fn default_category(&self) -> Option<Category> {
+        let active = self.active();
+        active
+            .iter()
+            .find(|category| category.name() == "Other")
+            .or(active.first())
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name: &str, parent: Option<&str>, budget: BudgetGroup) -> CategoryInfo {
+        CategoryInfo {
+            parent: parent.map(Category::from),
+            budget,
+            ..CategoryInfo::new(Category::from(name))
+        }
+    }
+
+    #[test]
+    fn budget_comes_from_the_closest_budgeted_ancestor() {
+        let categories = CategoryList::new(vec![
+            info("Food", None, BudgetGroup::Needs),
+            info("Groceries", Some("Food"), BudgetGroup::Unbudgeted),
+            info("Restaurants", Some("Food"), BudgetGroup::Wants),
+            info("Delivery", Some("Restaurants"), BudgetGroup::Unbudgeted),
+            info("Gifts", None, BudgetGroup::Unbudgeted),
+        ]);
+        let budget = |name| categories.budget(&Category::from(name));
+        assert_eq!(budget("Food"), BudgetGroup::Needs);
+        assert_eq!(budget("Groceries"), BudgetGroup::Needs);
+        assert_eq!(budget("Restaurants"), BudgetGroup::Wants);
+        assert_eq!(budget("Delivery"), BudgetGroup::Wants);
+        assert_eq!(budget("Gifts"), BudgetGroup::Unbudgeted);
+        assert_eq!(budget("Unlisted"), BudgetGroup::Unbudgeted);
     }
 }
@@ -1,4 +0,0 @@
-pub enum CsvType {
-    Credit,
-    Debit,
-}
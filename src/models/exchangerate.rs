@@ -0,0 +1,11 @@
+use crate::models::Currency;
+use chrono::NaiveDate;
+
+// One unit of `from` is worth `rate` units of `to` on `date`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeRate {
+    pub date: NaiveDate,
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: f64,
+}
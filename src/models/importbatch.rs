@@ -0,0 +1,23 @@
+use chrono::NaiveDateTime;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct ImportBatch {
+    pub id: u32,
+    pub path: String,
+    pub content_hash: String,
+    pub importer: String,
+    pub account: String,
+    pub total_rows: usize,
+    pub inserted_rows: usize,
+    pub imported_at: NaiveDateTime,
+}
+
+impl ImportBatch {
+    pub fn file_name(&self) -> String {
+        match Path::new(&self.path).file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => self.path.clone(),
+        }
+    }
+}
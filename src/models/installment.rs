@@ -0,0 +1,86 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Matches "Loja X - Parcela 3/10", "LOJA X PARC 03/10" and "Loja X parcela 3 de 10".
+static INSTALLMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(.*?)[\s\-]*\bparc(?:ela)?\.?\s*(\d{1,3})\s*(?:/|de)\s*(\d{1,3})\s*$")
+        .unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Installment {
+    pub purchase_title: String,
+    pub number: u32,
+    pub total: u32,
+}
+
+impl Installment {
+    pub fn parse(title: &str) -> Option<Installment> {
+        let captures = INSTALLMENT.captures(title.trim())?;
+        let purchase_title = captures[1].trim().to_string();
+        let number: u32 = captures[2].parse().ok()?;
+        let total: u32 = captures[3].parse().ok()?;
+
+        if purchase_title.is_empty() || number == 0 || total < 2 || number > total {
+            return None;
+        }
+        Some(Installment {
+            purchase_title,
+            number,
+            total,
+        })
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.total - self.number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installment(purchase_title: &str, number: u32, total: u32) -> Option<Installment> {
+        Some(Installment {
+            purchase_title: purchase_title.to_string(),
+            number,
+            total,
+        })
+    }
+
+    #[test]
+    fn parse_reads_the_usual_notations() {
+        assert_eq!(
+            Installment::parse("Loja X - Parcela 3/10"),
+            installment("Loja X", 3, 10)
+        );
+        assert_eq!(
+            Installment::parse("LOJA X PARC 03/10"),
+            installment("LOJA X", 3, 10)
+        );
+        assert_eq!(
+            Installment::parse("Loja X parcela 3 de 10"),
+            installment("Loja X", 3, 10)
+        );
+        assert_eq!(
+            Installment::parse("  MAGALU PARC.1/2 "),
+            installment("MAGALU", 1, 2)
+        );
+    }
+
+    #[test]
+    fn parse_refuses_impossible_counts() {
+        assert_eq!(Installment::parse("Loja X - Parcela 0/10"), None);
+        assert_eq!(Installment::parse("Loja X - Parcela 11/10"), None);
+        assert_eq!(Installment::parse("Loja X - Parcela 1/1"), None);
+        assert_eq!(Installment::parse("Parcela 1/3"), None);
+        assert_eq!(Installment::parse("Loja X 3/10"), None);
+        assert_eq!(Installment::parse("Parcelamento 3/10"), None);
+    }
+
+    #[test]
+    fn remaining_counts_the_installments_to_come() {
+        assert_eq!(installment("Loja X", 3, 10).unwrap().remaining(), 7);
+        assert_eq!(installment("Loja X", 10, 10).unwrap().remaining(), 0);
+    }
+}
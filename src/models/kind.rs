@@ -10,6 +10,16 @@ pub enum Kind {
     DebitPurchase,
     Income,
     CreditBillPayment,
+    Refund,
+}
+
+impl Kind {
+    pub fn sign(&self) -> i64 {
+        match self {
+            Kind::Income | Kind::CreditBillPayment | Kind::Refund => 1,
+            Kind::CreditPurchase | Kind::DebitPurchase => -1,
+        }
+    }
 }
 
 impl fmt::Display for Kind {
@@ -19,6 +29,7 @@ impl fmt::Display for Kind {
             Kind::DebitPurchase => write!(f, "DebitPurchase"),
             Kind::Income => write!(f, "Income"),
             Kind::CreditBillPayment => write!(f, "CreditBillPayment"),
+            Kind::Refund => write!(f, "Refund"),
         }
     }
 }
@@ -37,6 +48,7 @@ impl FromStr for Kind {
             "DebitPurchase" => Ok(Kind::DebitPurchase),
             "Income" => Ok(Kind::Income),
             "CreditBillPayment" => Ok(Kind::CreditBillPayment),
+            "Refund" => Ok(Kind::Refund),
             _ => Err(FromSqlError::Other(format!("UnknownEnum {}", s).into())),
         }
     }
@@ -0,0 +1,245 @@
+use rusqlite::{
+    Result, ToSql,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
+};
+use serde::Deserialize;
+use std::ops::{Mul, Neg};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const BRL: Currency = Currency(*b"BRL");
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::BRL
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = FromSqlError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim().to_ascii_uppercase();
+        match <[u8; 3]>::try_from(code.as_bytes()) {
+            Ok(bytes) if bytes.iter().all(u8::is_ascii_uppercase) => Ok(Currency(bytes)),
+            _ => Err(FromSqlError::Other(
+                format!("invalid currency code {s}").into(),
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = FromSqlError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl ToSql for Currency {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(self.code().to_string().into())
+    }
+}
+
+impl FromSql for Currency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+// Amounts are whole cents, so sums never drift. Two amounts only add up when they share a
+// currency, so there is no `+`: sums go through `checked_add`, and amounts in other currencies
+// have to be converted first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Money {
+    pub cents: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(cents: i64, currency: Currency) -> Self {
+        Money { cents, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(0, currency)
+    }
+
+    pub fn from_f64(value: f64, currency: Currency) -> Option<Self> {
+        let cents = (value * 100.0).round();
+        match cents.is_finite() && cents.abs() < i64::MAX as f64 {
+            true => Some(Money::new(cents as i64, currency)),
+            false => None,
+        }
+    }
+
+    // Parses a plain decimal such as "-1234.5" without going through floating point.
+    pub fn parse(value: &str, currency: Currency) -> Option<Self> {
+        let value = value.trim();
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let (units, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if units.is_empty() && fraction.is_empty()
+            || !units.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let (fraction, rest) = fraction.split_at(fraction.len().min(2));
+        if rest.chars().any(|c| c != '0') {
+            return None;
+        }
+        let units: i64 = match units.is_empty() {
+            true => 0,
+            false => units.parse().ok()?,
+        };
+        let fraction: i64 = format!("{fraction:0<2}").parse().ok()?;
+
+        let cents = units.checked_mul(100)?.checked_add(fraction)?;
+        Some(Money::new(
+            match negative {
+                true => -cents,
+                false => cents,
+            },
+            currency,
+        ))
+    }
+
+    // None when the currencies differ or the sum does not fit.
+    pub fn checked_add(self, other: Money) -> Option<Self> {
+        match self.currency == other.currency {
+            true => Some(Money::new(
+                self.cents.checked_add(other.cents)?,
+                self.currency,
+            )),
+            false => None,
+        }
+    }
+
+    pub fn checked_sub(self, other: Money) -> Option<Self> {
+        self.checked_add(-other)
+    }
+
+    pub fn convert(self, rate: f64, currency: Currency) -> Option<Self> {
+        Money::from_f64(self.as_f64() * rate, currency)
+    }
+
+    pub fn abs(self) -> Self {
+        Money::new(self.cents.abs(), self.currency)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.cents < 0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.cents == 0
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.cents as f64 / 100.0
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = match self.is_negative() {
+            true => "-",
+            false => "",
+        };
+        let cents = self.cents.unsigned_abs();
+        f.pad(&format!("{sign}{}.{:02}", cents / 100, cents % 100))
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money::new(-self.cents, self.currency)
+    }
+}
+
+impl Mul<i64> for Money {
+    type Output = Money;
+    fn mul(self, factor: i64) -> Money {
+        Money::new(self.cents * factor, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brl(cents: i64) -> Option<Money> {
+        Some(Money::new(cents, Currency::BRL))
+    }
+
+    #[test]
+    fn parse_reads_exact_cents() {
+        assert_eq!(Money::parse("1234.56", Currency::BRL), brl(123456));
+        assert_eq!(Money::parse("-0.29", Currency::BRL), brl(-29));
+        assert_eq!(Money::parse("+10.1", Currency::BRL), brl(1010));
+        assert_eq!(Money::parse(" 7 ", Currency::BRL), brl(700));
+        assert_eq!(Money::parse(".5", Currency::BRL), brl(50));
+        assert_eq!(Money::parse("3.", Currency::BRL), brl(300));
+        assert_eq!(Money::parse("2.500", Currency::BRL), brl(250));
+    }
+
+    #[test]
+    fn parse_refuses_what_is_not_a_plain_decimal() {
+        for value in [
+            "", ".", "-", "1,50", "1.005", "1e3", "R$ 10", "1.2.3", "--1",
+        ] {
+            assert_eq!(Money::parse(value, Currency::BRL), None, "{value}");
+        }
+        assert_eq!(Money::parse("99999999999999999999", Currency::BRL), None);
+    }
+
+    #[test]
+    fn from_f64_rounds_to_the_nearest_cent() {
+        assert_eq!(Money::from_f64(0.29, Currency::BRL), brl(29));
+        assert_eq!(Money::from_f64(1.005, Currency::BRL), brl(100));
+        assert_eq!(Money::from_f64(-19.999, Currency::BRL), brl(-2000));
+        assert_eq!(Money::from_f64(0.125, Currency::BRL), brl(13));
+        assert_eq!(Money::from_f64(f64::NAN, Currency::BRL), None);
+        assert_eq!(Money::from_f64(f64::INFINITY, Currency::BRL), None);
+        assert_eq!(Money::from_f64(1e20, Currency::BRL), None);
+    }
+
+    #[test]
+    fn display_keeps_two_decimals() {
+        assert_eq!(Money::new(-5, Currency::BRL).to_string(), "-0.05");
+        assert_eq!(Money::new(123400, Currency::BRL).to_string(), "1234.00");
+    }
+
+    #[test]
+    fn checked_add_needs_one_currency() {
+        let usd: Currency = "usd".parse().unwrap();
+        let a = Money::new(150, Currency::BRL);
+        assert_eq!(a.checked_add(Money::new(-50, Currency::BRL)), brl(100));
+        assert_eq!(a.checked_sub(Money::new(50, Currency::BRL)), brl(100));
+        assert_eq!(a.checked_add(Money::new(50, usd)), None);
+        assert_eq!(Money::new(i64::MAX, Currency::BRL).checked_add(a), None);
+    }
+}
@@ -1,10 +1,113 @@
-use crate::models::Kind;
+use crate::models::{Installment, Kind, Money};
 use chrono::NaiveDate;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 pub struct NewTransaction {
     pub date: NaiveDate,
     pub title: String,
-    pub amount: f64,
+    pub amount: Money,
     pub kind: Kind,
+    pub fitid: Option<String>,
+    pub account: String,
+    pub fingerprint: String,
+    pub installment: Option<Installment>,
+    pub original: Option<Money>,
+}
+
+impl NewTransaction {
+    fn identity(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}",
+            self.account, self.date, self.amount, self.kind, self.title
+        )
+    }
+
+    pub fn fingerprint(&self, occurrence: usize) -> String {
+        let key = match &self.fitid {
+            Some(fitid) => format!("fitid|{}|{}", self.account, fitid),
+            None => format!("{}|{}", self.identity(), occurrence),
+        };
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+}
+
+// Identical lines within one statement are real repeats, so each one is told apart by how
+// many identical lines came before it. Re-importing the same statement yields the same keys.
+pub fn assign_fingerprints<'a>(transactions: impl Iterator<Item = &'a mut NewTransaction>) {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for transaction in transactions {
+        let occurrence = occurrences.entry(transaction.identity()).or_default();
+        transaction.fingerprint = transaction.fingerprint(*occurrence);
+        *occurrence += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Currency;
+    use itertools::Itertools;
+
+    fn purchase(title: &str, cents: i64, fitid: Option<&str>) -> NewTransaction {
+        NewTransaction {
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            title: title.to_string(),
+            amount: Money::new(cents, Currency::BRL),
+            kind: Kind::DebitPurchase,
+            fitid: fitid.map(str::to_string),
+            account: "nubank".to_string(),
+            fingerprint: String::new(),
+            installment: None,
+            original: None,
+        }
+    }
+
+    fn fingerprints(mut transactions: Vec<NewTransaction>) -> Vec<String> {
+        assign_fingerprints(transactions.iter_mut());
+        transactions
+            .into_iter()
+            .map(|row| row.fingerprint)
+            .collect()
+    }
+
+    #[test]
+    fn repeated_lines_are_told_apart_by_occurrence() {
+        let coffee = purchase("CAFE", 500, None);
+        let fingerprints = fingerprints(vec![
+            coffee.clone(),
+            purchase("PAO", 700, None),
+            coffee.clone(),
+            coffee.clone(),
+        ]);
+        assert_eq!(fingerprints[0], coffee.fingerprint(0));
+        assert_eq!(fingerprints[2], coffee.fingerprint(1));
+        assert_eq!(fingerprints[3], coffee.fingerprint(2));
+        assert_eq!(fingerprints[1], purchase("PAO", 700, None).fingerprint(0));
+        assert_eq!(fingerprints.iter().unique().count(), 4);
+    }
+
+    #[test]
+    fn reimporting_yields_the_same_fingerprints() {
+        let statement = || {
+            vec![
+                purchase("CAFE", 500, None),
+                purchase("CAFE", 500, None),
+                purchase("CAFE", 650, None),
+            ]
+        };
+        assert_eq!(fingerprints(statement()), fingerprints(statement()));
+    }
+
+    #[test]
+    fn fitid_ignores_the_occurrence() {
+        let first = purchase("CAFE", 500, Some("X1"));
+        let renamed = purchase("CAFE CENTRAL", 500, Some("X1"));
+        assert_eq!(first.fingerprint(0), renamed.fingerprint(3));
+        assert_ne!(
+            first.fingerprint(0),
+            purchase("CAFE", 500, Some("X2")).fingerprint(0)
+        );
+    }
 }
@@ -0,0 +1,112 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::models::{Category, Currency, Kind, Money};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rule {
+    pub id: u32,
+    pub priority: i64,
+    pub contains: Option<String>,
+    pub pattern: Option<String>,
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    pub kind: Option<Kind>,
+    pub account: Option<String>,
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub category: Option<Category>,
+    pub rename: Option<String>,
+    pub tags: Vec<String>,
+    pub disabled: bool,
+}
+
+// Amount bounds are whole cents in the transaction's own currency, so they print without one.
+fn cents(cents: i64) -> String {
+    Money::new(cents, Currency::default()).to_string()
+}
+
+impl Rule {
+    pub fn keyword(contains: &str) -> Self {
+        Rule {
+            contains: Some(contains.to_lowercase()),
+            ..Rule::default()
+        }
+    }
+
+    // Titles match case-insensitively, by substring or by regular expression. Amount bounds are
+    // inclusive and apply to the amount without its sign.
+    pub fn matches(
+        &self,
+        pattern: Option<&Regex>,
+        title: &str,
+        amount: Money,
+        kind: &Kind,
+        account: &str,
+        date: NaiveDate,
+    ) -> bool {
+        let cents = amount.abs().cents;
+        self.contains
+            .as_ref()
+            .is_none_or(|contains| title.to_lowercase().contains(&contains.to_lowercase()))
+            && match (&self.pattern, pattern) {
+                (None, _) => true,
+                (Some(_), Some(pattern)) => pattern.is_match(title),
+                (Some(_), None) => false,
+            }
+            && self.min_amount.is_none_or(|min| cents >= min)
+            && self.max_amount.is_none_or(|max| cents <= max)
+            && self.kind.as_ref().is_none_or(|rule_kind| rule_kind == kind)
+            && self
+                .account
+                .as_ref()
+                .is_none_or(|rule_account| rule_account == account)
+            && self.from_date.is_none_or(|from| date >= from)
+            && self.to_date.is_none_or(|to| date <= to)
+    }
+
+    pub fn conditions(&self) -> Vec<String> {
+        let mut conditions = Vec::new();
+        if let Some(contains) = &self.contains {
+            conditions.push(format!("title contains {contains:?}"));
+        }
+        if let Some(pattern) = &self.pattern {
+            conditions.push(format!("title matches /{pattern}/"));
+        }
+        match (self.min_amount, self.max_amount) {
+            (Some(min), Some(max)) => {
+                conditions.push(format!("amount {} to {}", cents(min), cents(max)))
+            }
+            (Some(min), None) => conditions.push(format!("amount >= {}", cents(min))),
+            (None, Some(max)) => conditions.push(format!("amount <= {}", cents(max))),
+            (None, None) => (),
+        }
+        if let Some(kind) = &self.kind {
+            conditions.push(format!("kind {kind}"));
+        }
+        if let Some(account) = &self.account {
+            conditions.push(format!("account {account}"));
+        }
+        match (self.from_date, self.to_date) {
+            (Some(from), Some(to)) => conditions.push(format!("from {from} to {to}")),
+            (Some(from), None) => conditions.push(format!("from {from}")),
+            (None, Some(to)) => conditions.push(format!("until {to}")),
+            (None, None) => (),
+        }
+        conditions
+    }
+
+    pub fn actions(&self) -> Vec<String> {
+        let mut actions = Vec::new();
+        if let Some(rename) = &self.rename {
+            actions.push(format!("rename to {rename:?}"));
+        }
+        if let Some(category) = &self.category {
+            actions.push(format!("category {category}"));
+        }
+        if !self.tags.is_empty() {
+            actions.push(format!("tags {}", self.tags.join(", ")));
+        }
+        actions
+    }
+}
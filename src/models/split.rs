@@ -0,0 +1,8 @@
+use crate::models::{Category, Money};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub amount: Money,
+    pub category: Category,
+    pub note: String,
+}
@@ -1,4 +1,4 @@
-use crate::models::{Category, Kind};
+use crate::models::{Category, Installment, Kind, Money, Split};
 use chrono::NaiveDate;
 use std::fmt;
 
@@ -7,17 +7,236 @@ pub struct Transaction {
     pub id: u32,
     pub date: NaiveDate,
     pub title: String,
-    pub amount: f64,
+    pub statement_title: String,
+    pub amount: Money,
     pub kind: Kind,
     pub group: Option<Category>,
+    // Whether the category was given by a rule or by hand, rather than carried over from another
+    // transaction. Only these are learned from.
+    pub category_confirmed: bool,
+    pub installment: Option<Installment>,
+    pub account: String,
+    pub settled_by: Option<u32>,
+    pub transfer_of: Option<u32>,
+    pub refund_of: Option<u32>,
+    pub original: Option<Money>,
+    pub splits: Vec<Split>,
+    pub tags: Vec<String>,
+    pub manual_tags: Vec<String>,
+    pub rules: Vec<u32>,
+    pub title_override: Option<String>,
+    pub category_override: Option<Category>,
+    pub notes: String,
+    pub hidden: bool,
+}
+
+impl Transaction {
+    pub fn is_transfer(&self) -> bool {
+        self.transfer_of.is_some()
+    }
+
+    pub fn is_edited(&self) -> bool {
+        self.title_override.is_some() || self.category_override.is_some()
+    }
+
+    // What the splits leave of the amount, None when they cannot be taken out of it.
+    pub fn remainder(&self) -> Option<Money> {
+        self.splits
+            .iter()
+            .try_fold(self.amount, |remainder, split| {
+                remainder.checked_sub(split.amount)
+            })
+    }
+
+    // The categories the amount is divided into. Whatever the splits leave goes to the
+    // transaction's own category, and splits in another currency than the amount are ignored.
+    pub fn parts(&self) -> Vec<(Option<Category>, Money)> {
+        let Some(remainder) = self.remainder() else {
+            return vec![(self.group.clone(), self.amount)];
+        };
+        let mut parts: Vec<(Option<Category>, Money)> = self
+            .splits
+            .iter()
+            .map(|split| (Some(split.category.clone()), split.amount))
+            .collect();
+        if parts.is_empty() || !remainder.is_zero() {
+            parts.push((self.group.clone(), remainder));
+        }
+        parts
+    }
+
+    pub fn negated(&self) -> Self {
+        Transaction {
+            amount: -self.amount,
+            splits: self
+                .splits
+                .iter()
+                .map(|split| Split {
+                    amount: -split.amount,
+                    ..split.clone()
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
 }
 
 impl fmt::Display for Transaction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{}: {} - {}, R${}",
-            self.id, self.date, self.title, self.amount
+            "{}: {} - {}, {} {}",
+            self.id, self.date, self.title, self.amount, self.amount.currency
         )
     }
 }
+
+// A transaction as the database would return it before any labels, for tests to adjust.
+#[cfg(test)]
+impl Transaction {
+    pub fn sample(id: u32, date: &str, title: &str, cents: i64, kind: Kind) -> Self {
+        Transaction {
+            id,
+            date: date.parse().unwrap(),
+            title: title.to_string(),
+            statement_title: title.to_string(),
+            amount: Money::new(cents, crate::models::Currency::BRL),
+            kind,
+            group: None,
+            category_confirmed: false,
+            installment: Installment::parse(title),
+            account: "nubank".to_string(),
+            settled_by: None,
+            transfer_of: None,
+            refund_of: None,
+            original: None,
+            splits: Vec::new(),
+            tags: Vec::new(),
+            manual_tags: Vec::new(),
+            rules: Vec::new(),
+            title_override: None,
+            category_override: None,
+            notes: String::new(),
+            hidden: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Currency;
+
+    fn brl(cents: i64) -> Money {
+        Money::new(cents, Currency::BRL)
+    }
+
+    fn split(cents: i64, category: &str) -> Split {
+        Split {
+            amount: brl(cents),
+            category: Category::from(category),
+            note: String::new(),
+        }
+    }
+
+    fn split_purchase(splits: Vec<Split>) -> Transaction {
+        Transaction {
+            group: Some(Category::from("Shopping")),
+            splits,
+            ..Transaction::sample(1, "2024-01-10", "MERCADO", 10000, Kind::CreditPurchase)
+        }
+    }
+
+    fn parts(transaction: &Transaction) -> Vec<(String, i64)> {
+        transaction
+            .parts()
+            .into_iter()
+            .map(|(category, amount)| (category.unwrap().to_string(), amount.cents))
+            .collect()
+    }
+
+    fn sum(transaction: &Transaction) -> Option<Money> {
+        transaction.parts().into_iter().try_fold(
+            Money::zero(transaction.amount.currency),
+            |sum, (_, amount)| sum.checked_add(amount),
+        )
+    }
+
+    #[test]
+    fn without_splits_the_amount_is_one_part() {
+        let transaction = split_purchase(Vec::new());
+        assert_eq!(transaction.remainder(), Some(brl(10000)));
+        assert_eq!(parts(&transaction), vec![("Shopping".to_string(), 10000)]);
+    }
+
+    #[test]
+    fn remainder_goes_to_the_transaction_category() {
+        let transaction = split_purchase(vec![split(3000, "Food"), split(2000, "Home")]);
+        assert_eq!(transaction.remainder(), Some(brl(5000)));
+        assert_eq!(
+            parts(&transaction),
+            vec![
+                ("Food".to_string(), 3000),
+                ("Home".to_string(), 2000),
+                ("Shopping".to_string(), 5000),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_taking_the_whole_amount_leave_no_part() {
+        let transaction = split_purchase(vec![split(4000, "Food"), split(6000, "Home")]);
+        assert_eq!(transaction.remainder(), Some(brl(0)));
+        assert_eq!(
+            parts(&transaction),
+            vec![("Food".to_string(), 4000), ("Home".to_string(), 6000)]
+        );
+    }
+
+    #[test]
+    fn splits_over_the_amount_leave_a_negative_remainder() {
+        // The popup refuses these, but the parts still add up to the amount if one gets stored.
+        let transaction = split_purchase(vec![split(7000, "Food"), split(5000, "Home")]);
+        assert_eq!(transaction.remainder(), Some(brl(-2000)));
+        assert_eq!(
+            parts(&transaction),
+            vec![
+                ("Food".to_string(), 7000),
+                ("Home".to_string(), 5000),
+                ("Shopping".to_string(), -2000),
+            ]
+        );
+        assert_eq!(sum(&transaction), Some(brl(10000)));
+    }
+
+    #[test]
+    fn split_in_another_currency_is_ignored() {
+        let usd: Currency = "USD".parse().unwrap();
+        let transaction = split_purchase(vec![
+            split(3000, "Food"),
+            Split {
+                amount: Money::new(1000, usd),
+                ..split(0, "Travel")
+            },
+        ]);
+        assert_eq!(transaction.remainder(), None);
+        assert_eq!(parts(&transaction), vec![("Shopping".to_string(), 10000)]);
+    }
+
+    #[test]
+    fn negated_parts_mirror_the_original() {
+        let transaction = split_purchase(vec![split(3000, "Food"), split(2000, "Home")]);
+        let refund = transaction.negated();
+        assert_eq!(refund.amount, brl(-10000));
+        assert_eq!(refund.remainder(), Some(brl(-5000)));
+        assert_eq!(
+            parts(&refund),
+            vec![
+                ("Food".to_string(), -3000),
+                ("Home".to_string(), -2000),
+                ("Shopping".to_string(), -5000),
+            ]
+        );
+        assert_eq!(sum(&refund), Some(brl(-10000)));
+    }
+}
@@ -0,0 +1,293 @@
+use chrono::NaiveDate;
+use std::collections::HashSet;
+
+use crate::labeling::STOP_WORDS;
+use crate::models::{Kind, Money, NewTransaction, Transaction};
+
+// A refund is matched against purchases made up to this long before it.
+const REFUND_WINDOW_DAYS: i64 = 180;
+const REFUND_WORDS: [&str; 5] = ["estorn", "reembols", "refund", "chargeback", "devolu"];
+const PAYMENT_WORDS: [&str; 4] = ["pagamento", "pagto", "pgto", "payment"];
+
+#[derive(Clone)]
+pub struct Purchase {
+    pub id: Option<u32>,
+    pub date: NaiveDate,
+    pub title: String,
+    pub amount: Money,
+    pub account: String,
+}
+
+impl Purchase {
+    pub fn from_transaction(transaction: &Transaction) -> Option<Self> {
+        is_purchase(&transaction.kind).then(|| Purchase {
+            id: Some(transaction.id),
+            date: transaction.date,
+            title: transaction.title.clone(),
+            amount: transaction.amount,
+            account: transaction.account.clone(),
+        })
+    }
+
+    pub fn from_new_transaction(transaction: &NewTransaction) -> Option<Self> {
+        is_purchase(&transaction.kind).then(|| Purchase {
+            id: None,
+            date: transaction.date,
+            title: transaction.title.clone(),
+            amount: transaction.amount,
+            account: transaction.account.clone(),
+        })
+    }
+}
+
+fn is_purchase(kind: &Kind) -> bool {
+    matches!(kind, Kind::CreditPurchase | Kind::DebitPurchase)
+}
+
+fn words(title: &str) -> Vec<String> {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn has_word(title: &str, prefixes: &[&str]) -> bool {
+    words(title)
+        .iter()
+        .any(|word| prefixes.iter().any(|prefix| word.starts_with(prefix)))
+}
+
+// The merchant part of a description, without the words banks add to describe a refund.
+fn merchant(title: &str) -> Vec<String> {
+    words(title)
+        .into_iter()
+        .filter(|word| !REFUND_WORDS.iter().any(|prefix| word.starts_with(prefix)))
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+fn same_merchant(refund: &str, purchase: &str) -> bool {
+    let refund = merchant(refund);
+    let purchase = merchant(purchase);
+    !refund.is_empty()
+        && !purchase.is_empty()
+        && (refund.iter().all(|word| purchase.contains(word))
+            || purchase.iter().all(|word| refund.contains(word)))
+}
+
+// The latest earlier purchase on the same account with the same amount and merchant.
+pub fn find_purchase<'a>(
+    date: NaiveDate,
+    title: &str,
+    amount: Money,
+    account: &str,
+    purchases: impl IntoIterator<Item = &'a Purchase>,
+) -> Option<&'a Purchase> {
+    purchases
+        .into_iter()
+        .filter(|purchase| purchase.account == account && purchase.amount == amount)
+        .filter(|purchase| purchase.date <= date)
+        .filter(|purchase| (date - purchase.date).num_days() <= REFUND_WINDOW_DAYS)
+        .filter(|purchase| same_merchant(title, &purchase.title))
+        .max_by_key(|purchase| (purchase.date, purchase.id))
+}
+
+// Statements list refunds as credits, just like bill payments and income. A credit is a refund
+// when its description says so, or when a card credit that is not a bill payment gives back
+// exactly what an earlier purchase from the same merchant cost.
+pub fn is_refund(
+    kind: &Kind,
+    date: NaiveDate,
+    title: &str,
+    amount: Money,
+    account: &str,
+    purchases: &[Purchase],
+) -> bool {
+    match kind {
+        Kind::Income => has_word(title, &REFUND_WORDS),
+        Kind::CreditBillPayment => {
+            has_word(title, &REFUND_WORDS)
+                || !has_word(title, &PAYMENT_WORDS)
+                    && find_purchase(date, title, amount, account, purchases).is_some()
+        }
+        Kind::Refund => true,
+        Kind::CreditPurchase | Kind::DebitPurchase => false,
+    }
+}
+
+// Links every refund to the purchase it reverses, using each purchase at most once.
+pub fn link_refunds(transactions: &[Transaction]) -> Vec<(u32, u32)> {
+    let purchases: Vec<Purchase> = transactions
+        .iter()
+        .filter_map(Purchase::from_transaction)
+        .collect();
+
+    let mut used = HashSet::new();
+    let mut links = Vec::new();
+    for refund in transactions.iter().filter(|row| row.kind == Kind::Refund) {
+        let available = purchases
+            .iter()
+            .filter(|purchase| purchase.id.is_some_and(|id| !used.contains(&id)));
+        if let Some(id) = find_purchase(
+            refund.date,
+            &refund.title,
+            refund.amount,
+            &refund.account,
+            available,
+        )
+        .and_then(|purchase| purchase.id)
+        {
+            used.insert(id);
+            links.push((refund.id, id));
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Currency;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn brl(cents: i64) -> Money {
+        Money::new(cents, Currency::BRL)
+    }
+
+    fn purchases(transactions: &[Transaction]) -> Vec<Purchase> {
+        transactions
+            .iter()
+            .filter_map(Purchase::from_transaction)
+            .collect()
+    }
+
+    #[test]
+    fn description_marks_a_refund() {
+        let day = date("2024-03-01");
+        for title in ["ESTORNO AMAZON", "Reembolso de compra", "Devolução Loja X"] {
+            assert!(
+                is_refund(&Kind::Income, day, title, brl(500), "nubank", &[]),
+                "{title}"
+            );
+            assert!(is_refund(
+                &Kind::CreditBillPayment,
+                day,
+                title,
+                brl(500),
+                "nubank",
+                &[]
+            ));
+        }
+        assert!(!is_refund(
+            &Kind::Income,
+            day,
+            "SALARIO",
+            brl(500),
+            "nubank",
+            &[]
+        ));
+        assert!(!is_refund(
+            &Kind::DebitPurchase,
+            day,
+            "ESTORNO",
+            brl(500),
+            "nubank",
+            &[]
+        ));
+        assert!(is_refund(
+            &Kind::Refund,
+            day,
+            "SALARIO",
+            brl(500),
+            "nubank",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn card_credit_matching_a_purchase_is_a_refund() {
+        let transactions = [Transaction::sample(
+            1,
+            "2024-02-20",
+            "AMAZON MARKETPLACE",
+            4990,
+            Kind::CreditPurchase,
+        )];
+        let purchases = purchases(&transactions);
+        let day = date("2024-03-01");
+        let credit = |title: &str, cents: i64, account: &str| {
+            is_refund(
+                &Kind::CreditBillPayment,
+                day,
+                title,
+                brl(cents),
+                account,
+                &purchases,
+            )
+        };
+
+        assert!(credit("AMAZON", 4990, "nubank"));
+        assert!(!credit("AMAZON", 5000, "nubank"));
+        assert!(!credit("AMAZON", 4990, "itau"));
+        assert!(!credit("MERCADO LIVRE", 4990, "nubank"));
+        assert!(!credit("PAGAMENTO AMAZON", 4990, "nubank"));
+        assert!(!is_refund(
+            &Kind::CreditBillPayment,
+            date("2024-12-01"),
+            "AMAZON",
+            brl(4990),
+            "nubank",
+            &purchases
+        ));
+    }
+
+    #[test]
+    fn merchant_ignores_the_words_classification_ignores() {
+        let transactions = [Transaction::sample(
+            1,
+            "2024-02-20",
+            "AMAZON - Parcela 1/3",
+            4990,
+            Kind::CreditPurchase,
+        )];
+        let purchases = purchases(&transactions);
+        assert!(is_refund(
+            &Kind::CreditBillPayment,
+            date("2024-03-01"),
+            "PIX AMAZON",
+            brl(4990),
+            "nubank",
+            &purchases,
+        ));
+        for word in STOP_WORDS {
+            assert_eq!(merchant(&format!("Estorno {word} AMAZON")), ["amazon"]);
+        }
+    }
+
+    #[test]
+    fn each_purchase_is_refunded_once() {
+        let transactions = [
+            Transaction::sample(1, "2024-01-10", "CINEMA", 3000, Kind::CreditPurchase),
+            Transaction::sample(2, "2024-01-12", "CINEMA", 3000, Kind::CreditPurchase),
+            Transaction::sample(3, "2024-01-15", "ESTORNO CINEMA", 3000, Kind::Refund),
+            Transaction::sample(4, "2024-01-16", "ESTORNO CINEMA", 3000, Kind::Refund),
+            Transaction::sample(5, "2024-01-17", "ESTORNO CINEMA", 3000, Kind::Refund),
+            Transaction::sample(6, "2024-01-17", "ESTORNO TEATRO", 3000, Kind::Refund),
+        ];
+        assert_eq!(link_refunds(&transactions), vec![(3, 2), (4, 1)]);
+    }
+
+    #[test]
+    fn refund_before_the_purchase_is_not_linked() {
+        let transactions = [
+            Transaction::sample(1, "2024-01-15", "ESTORNO CINEMA", 3000, Kind::Refund),
+            Transaction::sample(2, "2024-01-20", "CINEMA", 3000, Kind::CreditPurchase),
+        ];
+        assert!(link_refunds(&transactions).is_empty());
+    }
+}
@@ -0,0 +1,527 @@
+use chrono::NaiveDate;
+use color_eyre::eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::import::parse_date;
+use crate::labeling::check_rule;
+use crate::models::{Category, CategoryInfo, CategoryList, Currency, Money, Rule};
+
+// The format a pack is written in. A pack with a higher version holds rules this one cannot
+// read, so it is refused instead of loaded halfway.
+const VERSION: u32 = 1;
+
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+// Keys are named after the options of `rules add`, and amounts and dates are written the same
+// way, so a pack reads like the commands that would make its rules.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PackRule {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_amount: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_amount: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rename: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disabled: bool,
+}
+
+fn amount(value: &Option<String>) -> Result<Option<i64>, String> {
+    value
+        .as_ref()
+        .map(|value| {
+            Money::parse(value, Currency::default())
+                .map(|amount| amount.abs().cents)
+                .ok_or_else(|| format!("invalid amount {value}"))
+        })
+        .transpose()
+}
+
+fn date(value: &Option<String>) -> Result<Option<NaiveDate>, String> {
+    value
+        .as_ref()
+        .map(|value| parse_date(value).ok_or_else(|| format!("invalid date {value}")))
+        .transpose()
+}
+
+impl PackRule {
+    fn new(rule: &Rule) -> Self {
+        let cents = |cents: i64| Money::new(cents, Currency::default()).to_string();
+        PackRule {
+            priority: rule.priority,
+            contains: rule.contains.clone(),
+            regex: rule.pattern.clone(),
+            min_amount: rule.min_amount.map(cents),
+            max_amount: rule.max_amount.map(cents),
+            kind: rule.kind.as_ref().map(|kind| kind.to_string()),
+            account: rule.account.clone(),
+            from: rule.from_date.map(|date| date.to_string()),
+            to: rule.to_date.map(|date| date.to_string()),
+            category: rule.category.as_ref().map(|category| category.to_string()),
+            rename: rule.rename.clone(),
+            tags: rule.tags.clone(),
+            disabled: rule.disabled,
+        }
+    }
+
+    // Checked like a rule added from the command line, since a pack may come from anywhere.
+    fn to_rule(&self) -> Result<Rule, String> {
+        let rule = Rule {
+            id: 0,
+            priority: self.priority,
+            contains: self
+                .contains
+                .as_ref()
+                .map(|contains| contains.to_lowercase()),
+            pattern: self.regex.clone(),
+            min_amount: amount(&self.min_amount)?,
+            max_amount: amount(&self.max_amount)?,
+            kind: self
+                .kind
+                .as_ref()
+                .map(|kind| kind.parse().map_err(|_| format!("unknown kind {kind}")))
+                .transpose()?,
+            account: self.account.clone(),
+            from_date: date(&self.from)?,
+            to_date: date(&self.to)?,
+            category: self
+                .category
+                .as_ref()
+                .map(|category| category.parse().map_err(|e| format!("{e}")))
+                .transpose()?,
+            rename: self.rename.clone(),
+            tags: self.tags.clone(),
+            disabled: self.disabled,
+        };
+        check_rule(&rule)?;
+        Ok(rule)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PackCategory {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub archived: bool,
+}
+
+impl PackCategory {
+    fn new(info: &CategoryInfo) -> Self {
+        PackCategory {
+            name: info.category.to_string(),
+            parent: info.parent.as_ref().map(|parent| parent.to_string()),
+            color: info.color.clone(),
+            budget: Some(info.budget.to_string()),
+            archived: info.archived,
+        }
+    }
+
+    fn to_info(&self) -> Result<CategoryInfo, String> {
+        let category = |name: &str| name.parse::<Category>().map_err(|e| format!("{e}"));
+        Ok(CategoryInfo {
+            parent: self.parent.as_deref().map(category).transpose()?,
+            color: self.color.clone(),
+            budget: self
+                .budget
+                .as_ref()
+                .map(|budget| budget.parse().map_err(|e| format!("{e}")))
+                .transpose()?
+                .unwrap_or_default(),
+            archived: self.archived,
+            ..CategoryInfo::new(category(&self.name)?)
+        })
+    }
+}
+
+// Categories come first so the rules that use them find them on another database. Rule IDs are
+// left out: the order rules are checked in is kept by their priorities and by the order they are
+// listed in, which becomes the order of their new IDs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RulePack {
+    pub version: u32,
+    #[serde(default, rename = "category", skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<PackCategory>,
+    #[serde(default, rename = "rule", skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<PackRule>,
+}
+
+impl RulePack {
+    pub fn new(rules: &[Rule], categories: &CategoryList) -> Self {
+        RulePack {
+            version: VERSION,
+            categories: categories.items.iter().map(PackCategory::new).collect(),
+            rules: rules.iter().map(PackRule::new).collect(),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string(self)?;
+        fs::write(path, text).map_err(|e| eyre!("{}: {e}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| eyre!("{}: {e}", path.display()))?;
+        let pack: RulePack = toml::from_str(&text).map_err(|e| eyre!("{}: {e}", path.display()))?;
+        if pack.version > VERSION {
+            return Err(eyre!(
+                "{}: version {} is newer than this easymoney reads ({VERSION})",
+                path.display(),
+                pack.version
+            ));
+        }
+        Ok(pack)
+    }
+
+    // Every entry is checked before anything is merged, so a bad pack changes nothing.
+    pub fn parse(&self) -> Result<(Vec<CategoryInfo>, Vec<Rule>)> {
+        let categories = self
+            .categories
+            .iter()
+            .map(|category| {
+                category
+                    .to_info()
+                    .map_err(|e| eyre!("category {}: {e}", category.name))
+            })
+            .collect::<Result<_>>()?;
+        let rules = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| rule.to_rule().map_err(|e| eyre!("rule {}: {e}", index + 1)))
+            .collect::<Result<_>>()?;
+        Ok((categories, rules))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Merge<T> {
+    Added(T),
+    Unchanged(T),
+    // What the database has, and what the pack says instead.
+    Conflict(T, T),
+    // An earlier entry of the pack, and a later one matched with it that is left out.
+    Repeated(T, T),
+}
+
+impl<T> Merge<T> {
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, Merge::Conflict(..) | Merge::Repeated(..))
+    }
+
+    // What there is to save, if anything, when conflicts take the pack's version or not.
+    pub fn into_saved(self, replace: bool) -> Option<T> {
+        match self {
+            Merge::Added(item) => Some(item),
+            Merge::Conflict(_, item) if replace => Some(item),
+            _ => None,
+        }
+    }
+}
+
+fn same_category(a: &CategoryInfo, b: &CategoryInfo) -> bool {
+    (&a.parent, &a.color, a.budget, a.archived) == (&b.parent, &b.color, b.budget, b.archived)
+}
+
+// A category is matched by its name.
+pub fn merge_categories(
+    existing: &CategoryList,
+    incoming: Vec<CategoryInfo>,
+) -> Vec<Merge<CategoryInfo>> {
+    incoming
+        .into_iter()
+        .map(|info| match existing.get(&info.category) {
+            None => Merge::Added(info),
+            Some(current) if same_category(current, &info) => Merge::Unchanged(current.clone()),
+            Some(current) => Merge::Conflict(current.clone(), info),
+        })
+        .collect()
+}
+
+fn same_rule(a: &Rule, b: &Rule) -> bool {
+    (a.priority, &a.category, &a.rename, &a.tags, a.disabled)
+        == (b.priority, &b.category, &b.rename, &b.tags, b.disabled)
+}
+
+// What a rule looks at and which labels it sets, leaving out the values it sets them to.
+fn rule_key(rule: &Rule) -> (Vec<String>, bool, bool, bool) {
+    (
+        rule.conditions(),
+        rule.rename.is_some(),
+        rule.category.is_some(),
+        !rule.tags.is_empty(),
+    )
+}
+
+// A rule is matched by its conditions and the labels it sets, so the rule that renames a title
+// and the one that gives it a category stay apart, while two rules giving the same titles
+// different categories conflict. A conflict keeps the ID of the rule in the database. A pack
+// rule matched with an earlier one of the same pack is a conflict too, and only the first counts.
+pub fn merge_rules(existing: &[Rule], incoming: Vec<Rule>) -> Vec<Merge<Rule>> {
+    let mut merges: Vec<Merge<Rule>> = Vec::new();
+    for rule in incoming {
+        let earlier = merges.iter().find_map(|merge| match merge {
+            Merge::Added(earlier) | Merge::Unchanged(earlier) | Merge::Conflict(_, earlier)
+                if rule_key(earlier) == rule_key(&rule) =>
+            {
+                Some(earlier.clone())
+            }
+            _ => None,
+        });
+        let merge = match (
+            earlier,
+            existing
+                .iter()
+                .find(|current| rule_key(current) == rule_key(&rule)),
+        ) {
+            (Some(earlier), _) => Merge::Repeated(earlier, rule),
+            (None, None) => Merge::Added(rule),
+            (None, Some(current)) if same_rule(current, &rule) => Merge::Unchanged(current.clone()),
+            (None, Some(current)) => Merge::Conflict(
+                current.clone(),
+                Rule {
+                    id: current.id,
+                    ..rule
+                },
+            ),
+        };
+        merges.push(merge);
+    }
+    merges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{BudgetGroup, Kind};
+
+    fn categorize(id: u32, contains: &str, category: &str) -> Rule {
+        Rule {
+            id,
+            category: Some(Category::from(category)),
+            ..Rule::keyword(contains)
+        }
+    }
+
+    fn info(name: &str, parent: Option<&str>, budget: BudgetGroup) -> CategoryInfo {
+        CategoryInfo {
+            parent: parent.map(Category::from),
+            budget,
+            ..CategoryInfo::new(Category::from(name))
+        }
+    }
+
+    #[test]
+    fn rules_are_matched_by_conditions_and_labels_set() {
+        let existing = [
+            categorize(1, "uber", "Transportation"),
+            Rule {
+                id: 2,
+                rename: Some("Uber".to_string()),
+                ..Rule::keyword("uber")
+            },
+            categorize(3, "ifood", "Food"),
+        ];
+        let merges = merge_rules(
+            &existing,
+            vec![
+                Rule {
+                    rename: Some("Uber".to_string()),
+                    ..Rule::keyword("uber")
+                },
+                categorize(0, "uber", "Trips"),
+                categorize(0, "ifood", "Food"),
+                categorize(0, "spotify", "Personal"),
+            ],
+        );
+        assert_eq!(
+            merges,
+            vec![
+                Merge::Unchanged(existing[1].clone()),
+                Merge::Conflict(existing[0].clone(), categorize(1, "uber", "Trips")),
+                Merge::Unchanged(existing[2].clone()),
+                Merge::Added(categorize(0, "spotify", "Personal")),
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicts_are_saved_only_when_replacing() {
+        let added = Merge::Added(1);
+        let unchanged = Merge::Unchanged(2);
+        let conflict = Merge::Conflict(3, 4);
+        assert!(conflict.is_conflict() && !added.is_conflict());
+        assert_eq!(added.clone().into_saved(false), Some(1));
+        assert_eq!(unchanged.clone().into_saved(true), None);
+        assert_eq!(conflict.clone().into_saved(false), None);
+        assert_eq!(conflict.into_saved(true), Some(4));
+        let repeated = Merge::Repeated(5, 6);
+        assert!(repeated.is_conflict());
+        assert_eq!(repeated.into_saved(true), None);
+    }
+
+    #[test]
+    fn rule_repeated_in_the_pack_is_a_conflict() {
+        let existing = [categorize(1, "uber", "Transportation")];
+        let merges = merge_rules(
+            &existing,
+            vec![
+                categorize(0, "uber", "Trips"),
+                categorize(0, "spotify", "Personal"),
+                categorize(0, "UBER", "Taxi"),
+                categorize(0, "spotify", "Music"),
+                categorize(0, "spotify", "Personal"),
+            ],
+        );
+        assert_eq!(
+            merges,
+            vec![
+                Merge::Conflict(existing[0].clone(), categorize(1, "uber", "Trips")),
+                Merge::Added(categorize(0, "spotify", "Personal")),
+                Merge::Repeated(
+                    categorize(1, "uber", "Trips"),
+                    categorize(0, "uber", "Taxi")
+                ),
+                Merge::Repeated(
+                    categorize(0, "spotify", "Personal"),
+                    categorize(0, "spotify", "Music")
+                ),
+                Merge::Repeated(
+                    categorize(0, "spotify", "Personal"),
+                    categorize(0, "spotify", "Personal")
+                ),
+            ]
+        );
+        let saved: Vec<Rule> = merges
+            .into_iter()
+            .filter_map(|merge| merge.into_saved(true))
+            .collect();
+        assert_eq!(
+            saved,
+            vec![
+                categorize(1, "uber", "Trips"),
+                categorize(0, "spotify", "Personal")
+            ]
+        );
+    }
+
+    #[test]
+    fn categories_conflict_when_their_settings_differ() {
+        let existing = CategoryList::new(vec![
+            info("Food", None, BudgetGroup::Needs),
+            info("Delivery", Some("Food"), BudgetGroup::Wants),
+        ]);
+        let merges = merge_categories(
+            &existing,
+            vec![
+                info("Food", None, BudgetGroup::Needs),
+                info("Delivery", Some("Food"), BudgetGroup::Needs),
+                info("Trips", None, BudgetGroup::Wants),
+            ],
+        );
+        let names: Vec<(&str, bool)> = merges
+            .iter()
+            .map(|merge| match merge {
+                Merge::Added(info) => ("added", info.category == Category::from("Trips")),
+                Merge::Unchanged(info) => ("unchanged", info.category == Category::from("Food")),
+                Merge::Conflict(current, incoming) => (
+                    "conflict",
+                    current.budget == BudgetGroup::Wants && incoming.budget == BudgetGroup::Needs,
+                ),
+                Merge::Repeated(..) => ("repeated", false),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![("unchanged", true), ("conflict", true), ("added", true)]
+        );
+    }
+
+    #[test]
+    fn pack_round_trips_through_toml() {
+        let rules = vec![
+            Rule {
+                id: 4,
+                priority: 10,
+                min_amount: Some(1050),
+                kind: Some(Kind::CreditPurchase),
+                from_date: "2024-01-01".parse().ok(),
+                tags: vec!["trip".to_string()],
+                disabled: true,
+                ..categorize(4, "airbnb", "Trips")
+            },
+            Rule {
+                id: 9,
+                pattern: Some("^ifd\\*".to_string()),
+                rename: Some("iFood".to_string()),
+                ..Rule::default()
+            },
+        ];
+        let categories = CategoryList::new(vec![info("Trips", None, BudgetGroup::Wants)]);
+        let text = toml::to_string(&RulePack::new(&rules, &categories)).unwrap();
+        let pack: RulePack = toml::from_str(&text).unwrap();
+        let (parsed_categories, parsed_rules) = pack.parse().unwrap();
+
+        assert_eq!(
+            parsed_rules,
+            rules
+                .into_iter()
+                .map(|rule| Rule { id: 0, ..rule })
+                .collect::<Vec<Rule>>()
+        );
+        assert_eq!(parsed_categories.len(), 1);
+        assert_eq!(parsed_categories[0].budget, BudgetGroup::Wants);
+    }
+
+    #[test]
+    fn bad_rules_are_reported_by_position() {
+        let pack: RulePack = toml::from_str(
+            "version = 1\n\
+             [[rule]]\ncontains = \"uber\"\ncategory = \"Transportation\"\n\
+             [[rule]]\ncontains = \"ifood\"\n",
+        )
+        .unwrap();
+        let error = pack.parse().unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "rule 2: A rule needs a category, a new title or tags"
+        );
+
+        let pack: Result<RulePack, _> = toml::from_str("version = 1\n[[rule]]\nkeyword = \"x\"\n");
+        assert!(pack.is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use itertools::Itertools;
+use std::collections::HashSet;
+
+use crate::models::{Kind, Transaction};
+
+// The checking account usually posts the bill payment a day or two apart from the card.
+const TRANSFER_WINDOW_DAYS: i64 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settlement {
+    pub payment: u32,
+    pub purchases: Vec<u32>,
+    pub transfer: Option<u32>,
+}
+
+// A bill payment settles the card purchases posted since the previous payment on the same
+// account, which approximates the statement cycle when closing dates are unknown. The bill
+// closed before it was paid, so a purchase posted on the day of a payment belongs to the next.
+pub fn settle_bill_payments(transactions: &[Transaction]) -> Vec<Settlement> {
+    let mut settlements = Vec::new();
+    let mut used_transfers = HashSet::new();
+
+    let payments = transactions
+        .iter()
+        .filter(|row| row.kind == Kind::CreditBillPayment)
+        .sorted_by_key(|row| (row.account.clone(), row.date, row.id))
+        .chunk_by(|row| row.account.clone());
+
+    for (account, payments) in &payments {
+        let mut cycle_start = None;
+        for payment in payments {
+            let purchases = transactions
+                .iter()
+                .filter(|row| row.kind == Kind::CreditPurchase && row.account == account)
+                .filter(|row| cycle_start.is_none_or(|start| row.date >= start))
+                .filter(|row| row.date < payment.date)
+                .map(|row| row.id)
+                .collect();
+            cycle_start = Some(payment.date);
+
+            let transfer = transactions
+                .iter()
+                .filter(|row| row.kind == Kind::DebitPurchase && row.account != account)
+                .filter(|row| row.amount == payment.amount)
+                .filter(|row| (row.date - payment.date).num_days().abs() <= TRANSFER_WINDOW_DAYS)
+                .filter(|row| !used_transfers.contains(&row.id))
+                .min_by_key(|row| ((row.date - payment.date).num_days().abs(), row.id))
+                .map(|row| row.id);
+            if let Some(transfer) = transfer {
+                used_transfers.insert(transfer);
+            }
+
+            settlements.push(Settlement {
+                payment: payment.id,
+                purchases,
+                transfer,
+            });
+        }
+    }
+    settlements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(account: &str, transaction: Transaction) -> Transaction {
+        Transaction {
+            account: account.to_string(),
+            ..transaction
+        }
+    }
+
+    #[test]
+    fn payment_settles_the_purchases_since_the_previous_one() {
+        let transactions = [
+            on(
+                "card",
+                Transaction::sample(1, "2024-01-03", "PADARIA", 1000, Kind::CreditPurchase),
+            ),
+            on(
+                "card",
+                Transaction::sample(2, "2024-01-10", "PAGAMENTO", 3000, Kind::CreditBillPayment),
+            ),
+            on(
+                "card",
+                Transaction::sample(3, "2024-01-10", "MERCADO", 2000, Kind::CreditPurchase),
+            ),
+            on(
+                "card",
+                Transaction::sample(4, "2024-02-10", "PAGAMENTO", 5000, Kind::CreditBillPayment),
+            ),
+            on(
+                "card",
+                Transaction::sample(5, "2024-02-11", "FARMACIA", 700, Kind::CreditPurchase),
+            ),
+            on(
+                "other",
+                Transaction::sample(6, "2024-01-05", "LIVRARIA", 900, Kind::CreditPurchase),
+            ),
+        ];
+        let settlements = settle_bill_payments(&transactions);
+        assert_eq!(
+            settlements,
+            vec![
+                Settlement {
+                    payment: 2,
+                    purchases: vec![1],
+                    transfer: None,
+                },
+                Settlement {
+                    payment: 4,
+                    purchases: vec![3],
+                    transfer: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn purchase_on_the_day_of_a_payment_goes_on_the_next_bill() {
+        let transactions = [
+            Transaction::sample(1, "2024-01-10", "PAGAMENTO", 3000, Kind::CreditBillPayment),
+            Transaction::sample(2, "2024-01-10", "PADARIA", 1500, Kind::CreditPurchase),
+            Transaction::sample(3, "2024-02-10", "PAGAMENTO", 1500, Kind::CreditBillPayment),
+        ];
+        let purchases: Vec<Vec<u32>> = settle_bill_payments(&transactions)
+            .into_iter()
+            .map(|settlement| settlement.purchases)
+            .collect();
+        assert_eq!(purchases, vec![vec![], vec![2]]);
+    }
+
+    #[test]
+    fn transfer_is_the_closest_matching_debit() {
+        let transactions = [
+            on(
+                "card",
+                Transaction::sample(1, "2024-01-10", "PAGAMENTO", 3000, Kind::CreditBillPayment),
+            ),
+            on(
+                "card",
+                Transaction::sample(2, "2024-02-10", "PAGAMENTO", 3000, Kind::CreditBillPayment),
+            ),
+            on(
+                "bank",
+                Transaction::sample(3, "2024-01-14", "PAG FATURA", 3000, Kind::DebitPurchase),
+            ),
+            on(
+                "bank",
+                Transaction::sample(4, "2024-01-09", "PAG FATURA", 3000, Kind::DebitPurchase),
+            ),
+            on(
+                "bank",
+                Transaction::sample(5, "2024-02-10", "PAG FATURA", 3001, Kind::DebitPurchase),
+            ),
+            on(
+                "bank",
+                Transaction::sample(6, "2024-02-20", "PAG FATURA", 3000, Kind::DebitPurchase),
+            ),
+        ];
+        let transfers: Vec<Option<u32>> = settle_bill_payments(&transactions)
+            .into_iter()
+            .map(|settlement| settlement.transfer)
+            .collect();
+        assert_eq!(transfers, vec![Some(4), None]);
+    }
+
+    #[test]
+    fn transfer_is_used_once() {
+        let transactions = [
+            on(
+                "card",
+                Transaction::sample(1, "2024-01-10", "PAGAMENTO", 3000, Kind::CreditBillPayment),
+            ),
+            on(
+                "visa",
+                Transaction::sample(2, "2024-01-11", "PAGAMENTO", 3000, Kind::CreditBillPayment),
+            ),
+            on(
+                "bank",
+                Transaction::sample(3, "2024-01-10", "PAG FATURA", 3000, Kind::DebitPurchase),
+            ),
+        ];
+        let transfers: Vec<Option<u32>> = settle_bill_payments(&transactions)
+            .into_iter()
+            .map(|settlement| settlement.transfer)
+            .collect();
+        assert_eq!(transfers, vec![Some(3), None]);
+    }
+}
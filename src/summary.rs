@@ -0,0 +1,599 @@
+use chrono::{Datelike, Months, NaiveDate};
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::exchange::ExchangeRates;
+use crate::models::{Account, BudgetGroup, Category, CategoryList, Kind, Money, Transaction};
+
+pub const NULL_KEY: &str = "[N/A]";
+
+// Amounts only add up in one currency. Transactions are converted with `ExchangeRates` before
+// they are summed, and what still cannot be added is set apart by `summable` first, so the sums
+// below always fit.
+fn plus(total: Money, amount: Money) -> Money {
+    total.checked_add(amount).unwrap_or(total)
+}
+
+// Splits the rows into those that add up with `start` and those that do not: rows in another
+// currency, with a split in one, or large enough to overflow. A total is made either of whole
+// amounts or of split parts, so it is at most one of the two sums of absolute values checked here.
+fn summable(transactions: &[Transaction], start: Money) -> (Vec<Transaction>, Vec<Transaction>) {
+    let mut bounds = (start.abs(), start.abs());
+    let mut kept = Vec::new();
+    let mut left_out = Vec::new();
+    for transaction in transactions {
+        let amounts = bounds.0.checked_add(transaction.amount.abs());
+        let parts = transaction
+            .parts()
+            .into_iter()
+            .try_fold(bounds.1, |sum, (_, amount)| sum.checked_add(amount.abs()));
+        match amounts.zip(parts) {
+            Some(sums) => {
+                bounds = sums;
+                kept.push(transaction.clone());
+            }
+            None => left_out.push(transaction.clone()),
+        }
+    }
+    (kept, left_out)
+}
+
+fn add_to<K: std::hash::Hash + Eq>(totals: &mut HashMap<K, Money>, key: K, amount: Money) {
+    let total = totals.entry(key).or_insert(Money::zero(amount.currency));
+    *total = plus(*total, amount);
+}
+
+fn zero(transactions: &[Transaction]) -> Money {
+    let currency = transactions
+        .first()
+        .map(|row| row.amount.currency)
+        .unwrap_or_default();
+    Money::zero(currency)
+}
+
+fn get_transactions_by_category(
+    transactions: &[Transaction],
+    categories: &CategoryList,
+) -> HashMap<String, Money> {
+    let zero = zero(transactions);
+    let mut category_totals: HashMap<String, Money> = categories
+        .active()
+        .into_iter()
+        .map(|category| (category.to_string(), zero))
+        .collect();
+
+    category_totals.insert(NULL_KEY.to_string(), zero);
+
+    for (category, amount) in transactions.iter().flat_map(Transaction::parts) {
+        let key = match category {
+            Some(category) => category.to_string(),
+            None => NULL_KEY.to_string(),
+        };
+        add_to(&mut category_totals, key, amount);
+    }
+    category_totals
+}
+
+// A transaction counts in full towards each of its tags, so tag totals can add up to more than
+// what was spent. Untagged transactions are left out.
+fn get_transactions_by_tag(transactions: &[Transaction]) -> HashMap<String, Money> {
+    let mut tag_totals: HashMap<String, Money> = HashMap::new();
+    for transaction in transactions {
+        for tag in &transaction.tags {
+            add_to(&mut tag_totals, tag.clone(), transaction.amount);
+        }
+    }
+    tag_totals
+}
+
+// Adds what was spent in every category to the totals of all its ancestors.
+fn roll_up(totals: &HashMap<String, Money>, categories: &CategoryList) -> HashMap<String, Money> {
+    let mut rolled_up = totals.clone();
+    for (name, total) in totals {
+        let category = Category::from(name.as_str());
+        for ancestor in categories
+            .path(&category)
+            .iter()
+            .filter(|row| **row != category)
+        {
+            add_to(&mut rolled_up, ancestor.to_string(), *total);
+        }
+    }
+    rolled_up
+}
+
+// Folds every category deeper than `depth` into its ancestor at that level.
+pub fn collapse_to(
+    totals: &HashMap<String, Money>,
+    categories: &CategoryList,
+    depth: usize,
+) -> HashMap<String, Money> {
+    let mut collapsed: HashMap<String, Money> = HashMap::new();
+    for (name, total) in totals {
+        let key = categories.at_depth(&Category::from(name.as_str()), depth);
+        add_to(&mut collapsed, key.to_string(), *total);
+    }
+    collapsed
+}
+
+pub fn get_transactions_by_month(
+    transactions: &[Transaction],
+    categories: &CategoryList,
+) -> Vec<MonthSummary> {
+    let month_set: HashSet<(i32, u32)> = transactions
+        .iter()
+        .filter(|row| !row.hidden)
+        .map(|row| (row.date.year(), row.date.month()))
+        .collect();
+
+    month_set
+        .iter()
+        .map(|(year, month)| MonthSummary::new(transactions, categories, *year, *month))
+        .sorted_by_key(|summary| (summary.year, summary.month))
+        .rev()
+        .collect()
+}
+
+#[derive(PartialEq, Debug)]
+pub struct InstallmentProjection {
+    pub year: i32,
+    pub month: u32,
+    pub total: Money,
+    pub purchases: usize,
+}
+
+// A purchase is identified by its title, installment count, amount and the month of its first
+// installment. Only its latest installment is projected forward, so overlapping statements
+// listing earlier installments of the same purchase are not counted twice. Months before the one
+// `today` falls in are already over, so an old statement projects nothing into them.
+// Installments that cannot be added to the others are returned apart.
+pub fn get_installment_projection(
+    transactions: &[Transaction],
+    today: NaiveDate,
+) -> (Vec<InstallmentProjection>, Vec<Transaction>) {
+    let installments: Vec<Transaction> = transactions
+        .iter()
+        .filter(|row| !row.hidden && row.installment.is_some())
+        .cloned()
+        .collect();
+    let (installments, left_out) = summable(&installments, zero(&installments));
+
+    let mut purchases: HashMap<(String, u32, Money, i32, u32), &Transaction> = HashMap::new();
+    for transaction in &installments {
+        let Some(installment) = &transaction.installment else {
+            continue;
+        };
+        let Some(first) = transaction
+            .date
+            .checked_sub_months(Months::new(installment.number - 1))
+        else {
+            continue;
+        };
+        let key = (
+            installment.purchase_title.to_lowercase(),
+            installment.total,
+            transaction.amount,
+            first.year(),
+            first.month(),
+        );
+        let latest = purchases.entry(key).or_insert(transaction);
+        if latest
+            .installment
+            .as_ref()
+            .is_some_and(|latest| latest.number < installment.number)
+        {
+            *latest = transaction;
+        }
+    }
+
+    let mut months: BTreeMap<(i32, u32), (Money, usize)> = BTreeMap::new();
+    for transaction in purchases.values() {
+        let remaining = transaction
+            .installment
+            .as_ref()
+            .map(|installment| installment.remaining())
+            .unwrap_or(0);
+        for offset in 1..=remaining {
+            if let Some(date) = transaction.date.checked_add_months(Months::new(offset))
+                && (date.year(), date.month()) >= (today.year(), today.month())
+            {
+                let month = months
+                    .entry((date.year(), date.month()))
+                    .or_insert((Money::zero(transaction.amount.currency), 0));
+                month.0 = plus(month.0, transaction.amount);
+                month.1 += 1;
+            }
+        }
+    }
+
+    let projection = months
+        .into_iter()
+        .map(
+            |((year, month), (total, purchases))| InstallmentProjection {
+                year,
+                month,
+                total,
+                purchases,
+            },
+        )
+        .collect();
+    (projection, left_out)
+}
+
+pub struct AccountBalance {
+    pub account: Account,
+    pub balance: Money,
+    pub transactions: usize,
+    // Transactions in another currency without a rate to the account's, left out of the balance.
+    pub missing_rates: usize,
+    // Transactions that could not be added to the balance even after conversion.
+    pub left_out: Vec<Transaction>,
+}
+
+// Balances are in each account's own currency, and transactions stored in another one are
+// converted to it first.
+pub fn get_account_balances(
+    accounts: &[Account],
+    transactions: &[Transaction],
+    rates: &ExchangeRates,
+) -> Vec<AccountBalance> {
+    accounts
+        .iter()
+        .map(|account| {
+            let rows: Vec<Transaction> = transactions
+                .iter()
+                .filter(|row| row.account == account.name)
+                .cloned()
+                .collect();
+            let (converted, missing_rates) = rates.convert_transactions(&rows, account.currency);
+            let (converted, left_out) = summable(&converted, account.opening_balance);
+            AccountBalance {
+                account: account.clone(),
+                balance: converted
+                    .iter()
+                    .fold(account.opening_balance, |balance, row| {
+                        plus(balance, row.amount * row.kind.sign())
+                    }),
+                transactions: rows.len(),
+                missing_rates,
+                left_out,
+            }
+        })
+        .collect()
+}
+
+#[derive(PartialEq, Debug)]
+pub struct FiftyThirtyTwenty {
+    pub needs: Money,
+    pub wants: Money,
+    pub savings: Money,
+}
+
+impl FiftyThirtyTwenty {
+    fn reduce_by(
+        transactions: &[Transaction],
+        categories: &CategoryList,
+        budget: BudgetGroup,
+    ) -> Money {
+        let zero = zero(transactions);
+        transactions
+            .iter()
+            .flat_map(Transaction::parts)
+            .filter(|(category, _)| match category {
+                Some(category) => categories.budget(category) == budget,
+                None => false,
+            })
+            .fold(zero, |total, (_, amount)| plus(total, amount))
+    }
+
+    fn new(transactions: &[Transaction], categories: &CategoryList) -> Self {
+        let needs = FiftyThirtyTwenty::reduce_by(transactions, categories, BudgetGroup::Needs);
+        let wants = FiftyThirtyTwenty::reduce_by(transactions, categories, BudgetGroup::Wants);
+        let savings = FiftyThirtyTwenty::reduce_by(transactions, categories, BudgetGroup::Savings);
+        Self {
+            needs,
+            wants,
+            savings,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MonthSummary {
+    pub year: i32,
+    pub month: u32,
+    pub total_income: Money,
+    pub total_expenses: Money,
+    pub fifty_thirty_twenty: FiftyThirtyTwenty,
+    pub categorized_expenses: HashMap<String, Money>,
+    pub rolled_up_expenses: HashMap<String, Money>,
+    pub tagged_expenses: HashMap<String, Money>,
+    // Transactions of the month that could not be added to its totals.
+    pub left_out: Vec<Transaction>,
+}
+
+impl MonthSummary {
+    pub fn new(
+        transactions: &[Transaction],
+        categories: &CategoryList,
+        year: i32,
+        month: u32,
+    ) -> Self {
+        // Hidden transactions stay in the table but are left out of every total.
+        let month_transactions: Vec<Transaction> = transactions
+            .iter()
+            .filter(|row| !row.hidden)
+            .filter(|row| row.date.month() == month && row.date.year() == year)
+            .cloned()
+            .collect();
+        let (month_transactions, left_out) =
+            summable(&month_transactions, zero(&month_transactions));
+        let expenses: Vec<Transaction> = month_transactions
+            .iter()
+            .filter(|row| !row.is_transfer())
+            .filter_map(|row| match row.kind {
+                Kind::DebitPurchase | Kind::CreditPurchase => Some(row.clone()),
+                // Refunds net against the category of the purchase they give money back for.
+                Kind::Refund => Some(row.negated()),
+                Kind::Income | Kind::CreditBillPayment => None,
+            })
+            .collect();
+        let zero = zero(&month_transactions);
+        let total_income = month_transactions
+            .iter()
+            .filter(|row| row.kind == Kind::Income)
+            .fold(zero, |total, row| plus(total, row.amount));
+
+        let total_expenses = expenses
+            .iter()
+            .fold(zero, |total, row| plus(total, row.amount));
+        let fifty_thirty_twenty = FiftyThirtyTwenty::new(&expenses, categories);
+        let categorized_expenses = get_transactions_by_category(&expenses, categories);
+        let rolled_up_expenses = roll_up(&categorized_expenses, categories);
+        let tagged_expenses = get_transactions_by_tag(&expenses);
+        Self {
+            year,
+            month,
+            total_income,
+            total_expenses,
+            fifty_thirty_twenty,
+            categorized_expenses,
+            rolled_up_expenses,
+            tagged_expenses,
+            left_out,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AccountKind, BudgetGroup, CategoryInfo, Currency};
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn months(projection: &[InstallmentProjection]) -> Vec<(i32, u32, i64, usize)> {
+        projection
+            .iter()
+            .map(|month| (month.year, month.month, month.total.cents, month.purchases))
+            .collect()
+    }
+
+    #[test]
+    fn projection_continues_from_the_latest_installment() {
+        let transactions = [
+            Transaction::sample(
+                1,
+                "2024-01-10",
+                "TV - Parcela 1/4",
+                10000,
+                Kind::CreditPurchase,
+            ),
+            Transaction::sample(
+                2,
+                "2024-02-10",
+                "TV - Parcela 2/4",
+                10000,
+                Kind::CreditPurchase,
+            ),
+            Transaction::sample(
+                3,
+                "2024-02-15",
+                "SOFA PARC 01/02",
+                5000,
+                Kind::CreditPurchase,
+            ),
+            Transaction::sample(4, "2024-02-20", "MERCADO", 3000, Kind::CreditPurchase),
+        ];
+        let (projection, _) = get_installment_projection(&transactions, date("2024-02-25"));
+        assert_eq!(
+            months(&projection),
+            vec![(2024, 3, 15000, 2), (2024, 4, 10000, 1)]
+        );
+        assert_eq!(projection[0].total.currency, Currency::BRL);
+    }
+
+    #[test]
+    fn projection_leaves_out_months_already_over() {
+        let transactions = [Transaction::sample(
+            1,
+            "2023-11-05",
+            "NOTEBOOK PARC 02/06",
+            25000,
+            Kind::CreditPurchase,
+        )];
+        let (projection, _) = get_installment_projection(&transactions, date("2024-02-01"));
+        assert_eq!(
+            months(&projection),
+            vec![(2024, 2, 25000, 1), (2024, 3, 25000, 1)]
+        );
+        assert!(
+            get_installment_projection(&transactions, date("2024-04-01"))
+                .0
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn projection_skips_hidden_transactions() {
+        let transactions = [Transaction {
+            hidden: true,
+            ..Transaction::sample(
+                1,
+                "2024-01-10",
+                "TV - Parcela 1/4",
+                10000,
+                Kind::CreditPurchase,
+            )
+        }];
+        assert!(
+            get_installment_projection(&transactions, date("2024-01-10"))
+                .0
+                .is_empty()
+        );
+    }
+
+    fn ids(transactions: &[Transaction]) -> Vec<u32> {
+        transactions.iter().map(|row| row.id).collect()
+    }
+
+    // A purchase in dollars next to ones in reais, and one too large to add to anything.
+    fn mixed(suffix: &str) -> Vec<Transaction> {
+        let usd: Currency = "USD".parse().unwrap();
+        let rows = [
+            (1, "2024-01-10", "PADARIA", 1000),
+            (2, "2024-01-11", "STEAM", 500),
+            (3, "2024-01-12", "MERCADO", i64::MAX),
+            (4, "2024-01-13", "FARMACIA", 250),
+        ];
+        rows.into_iter()
+            .map(|(id, date, title, cents)| {
+                let title = format!("{title}{suffix}");
+                let mut row = Transaction::sample(id, date, &title, cents, Kind::CreditPurchase);
+                if id == 2 {
+                    row.amount = Money::new(cents, usd);
+                }
+                row
+            })
+            .collect()
+    }
+
+    #[test]
+    fn month_lists_transactions_left_out_of_its_totals() {
+        let summary = MonthSummary::new(&mixed(""), &CategoryList::new(Vec::new()), 2024, 1);
+        assert_eq!(summary.total_expenses, Money::new(1250, Currency::BRL));
+        assert_eq!(ids(&summary.left_out), vec![2, 3]);
+    }
+
+    #[test]
+    fn balance_lists_transactions_left_out_of_it() {
+        let account = Account {
+            opening_balance: Money::new(i64::MAX - 1000, Currency::BRL),
+            ..Account::new("nubank", AccountKind::Checking)
+        };
+        let transactions = [
+            Transaction::sample(1, "2024-01-10", "SALARIO", 1000, Kind::Income),
+            Transaction::sample(2, "2024-01-11", "PIX", 250, Kind::Income),
+        ];
+        let balances = get_account_balances(&[account], &transactions, &ExchangeRates::new(&[]));
+        assert_eq!(balances[0].balance, Money::new(i64::MAX, Currency::BRL));
+        assert_eq!(balances[0].transactions, 2);
+        assert_eq!(ids(&balances[0].left_out), vec![2]);
+    }
+
+    #[test]
+    fn projection_lists_installments_left_out_of_it() {
+        let transactions = mixed(" - Parcela 1/2");
+        let (projection, left_out) = get_installment_projection(&transactions, date("2024-01-20"));
+        assert_eq!(months(&projection), vec![(2024, 2, 1250, 2)]);
+        assert_eq!(ids(&left_out), vec![2, 3]);
+    }
+
+    fn categories() -> CategoryList {
+        let info = |name: &str, parent: Option<&str>, budget| CategoryInfo {
+            parent: parent.map(Category::from),
+            budget,
+            ..CategoryInfo::new(Category::from(name))
+        };
+        CategoryList::new(vec![
+            info("Food", None, BudgetGroup::Needs),
+            info("Groceries", Some("Food"), BudgetGroup::Unbudgeted),
+            info("Restaurants", Some("Food"), BudgetGroup::Wants),
+            info("Delivery", Some("Restaurants"), BudgetGroup::Unbudgeted),
+        ])
+    }
+
+    fn spent(id: u32, cents: i64, category: &str) -> Transaction {
+        Transaction {
+            group: Some(Category::from(category)),
+            ..Transaction::sample(id, "2024-01-10", "LOJA", cents, Kind::CreditPurchase)
+        }
+    }
+
+    fn cents(totals: &HashMap<String, Money>) -> Vec<(String, i64)> {
+        totals
+            .iter()
+            .filter(|(_, total)| !total.is_zero())
+            .map(|(name, total)| (name.clone(), total.cents))
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn child_keeps_its_own_budget_group() {
+        let transactions = [
+            spent(1, 1000, "Groceries"),
+            spent(2, 2000, "Restaurants"),
+            spent(3, 4000, "Delivery"),
+            spent(4, 8000, "Food"),
+        ];
+        let summary = MonthSummary::new(&transactions, &categories(), 2024, 1);
+        assert_eq!(
+            summary.fifty_thirty_twenty,
+            FiftyThirtyTwenty {
+                needs: Money::new(9000, Currency::BRL),
+                wants: Money::new(6000, Currency::BRL),
+                savings: Money::new(0, Currency::BRL),
+            }
+        );
+    }
+
+    #[test]
+    fn nested_category_rolls_up_and_collapses_to_its_top_level_parent() {
+        let categories = categories();
+        let transactions = [
+            spent(1, 1000, "Groceries"),
+            spent(2, 2000, "Restaurants"),
+            spent(3, 4000, "Delivery"),
+        ];
+        let summary = MonthSummary::new(&transactions, &categories, 2024, 1);
+        assert_eq!(
+            cents(&summary.rolled_up_expenses),
+            vec![
+                ("Delivery".to_string(), 4000),
+                ("Food".to_string(), 7000),
+                ("Groceries".to_string(), 1000),
+                ("Restaurants".to_string(), 6000),
+            ]
+        );
+
+        let collapsed = |depth| {
+            cents(&collapse_to(
+                &summary.categorized_expenses,
+                &categories,
+                depth,
+            ))
+        };
+        assert_eq!(collapsed(1), vec![("Food".to_string(), 7000)]);
+        assert_eq!(
+            collapsed(2),
+            vec![
+                ("Groceries".to_string(), 1000),
+                ("Restaurants".to_string(), 6000),
+            ]
+        );
+        assert_eq!(collapsed(3), cents(&summary.categorized_expenses));
+    }
+}